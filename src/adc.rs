@@ -0,0 +1,403 @@
+//! ADC driver.
+//!
+//! ESP8266 has a single 10-bit ADC, which measures either the voltage on the TOUT pin (0 - 1.0 V)
+//! or the supply voltage of the chip (VDD33). Mode is selected by the type parameter of
+//! [Adc](struct.Adc.html) and can be switched at runtime.
+//!
+//! VDD33 can only be measured if TOUT pin is not connected. Also 107th byte of `esp_init_data`
+//! should be set to 255 for VDD33 measurement and to the supply voltage in 0.1 V units for TOUT
+//! measurement.
+//!
+//! TOUT readings are converted to millivolts with the [AdcCalibration](struct.AdcCalibration.html),
+//! which accounts for the external voltage divider of the board.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     adc::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//!
+//! // NodeMCU boards have 220k/100k divider on the A0 pin
+//! let mut initializer = AdcInitializer::new(peripherals.adc);
+//! initializer.set_calibration(AdcCalibration::with_divider(220_000, 100_000));
+//! let mut adc = initializer.initialize_tout().ok().unwrap();
+//!
+//! let battery_mv = adc.read_mv().ok().unwrap();
+//! let smooth = adc.read_average(16).ok().unwrap();
+//! ```
+use core::marker::PhantomData;
+
+use crate::{
+    peripherals::AdcPeripherals,
+    sys::{
+        adc::*,
+        error::*,
+    },
+};
+
+/// Maximal raw value of the 10-bit ADC
+pub const ADC_MAX_VALUE: u16 = 1023;
+
+const ADC_RESOLUTION: u32 = 1024;
+const TOUT_FULL_SCALE_MV: u32 = 1000;
+
+const MIN_CLOCK_DIVIDER: u8 = 8;
+const MAX_CLOCK_DIVIDER: u8 = 32;
+
+const MAX_OVERSAMPLING_BITS: u8 = 6;
+
+/// Measures TOUT pin voltage
+pub struct Tout;
+/// Measures supply voltage
+pub struct Vdd33;
+
+pub trait AdcModeMarker {
+    #[doc(hidden)]
+    fn map_to_ffi() -> adc_mode_t;
+}
+
+impl AdcModeMarker for Tout {
+    fn map_to_ffi() -> adc_mode_t { adc_mode_t_ADC_READ_TOUT_MODE }
+}
+
+impl AdcModeMarker for Vdd33 {
+    fn map_to_ffi() -> adc_mode_t { adc_mode_t_ADC_READ_VDD_MODE }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AdcConfigError {
+    /// Clock divider should be in 8..=32 range
+    InvalidClockDivider,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AdcError {
+    /// ADC driver rejected the configuration
+    InitializationFailed,
+    /// ADC driver failed to read the value. Fast reads fail if WiFi is enabled
+    ReadFailed,
+}
+
+/// Conversion of the raw TOUT readings to millivolts on the board input
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AdcCalibration {
+    full_scale_mv: u32,
+    offset_mv: i32,
+}
+
+impl Default for AdcCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdcCalibration {
+    /// Calibration of the TOUT pin without external divider: 1.0 V full scale
+    pub fn new() -> Self {
+        Self { full_scale_mv: TOUT_FULL_SCALE_MV, offset_mv: 0 }
+    }
+
+    /// Calibration of the input connected through the voltage divider: `top_ohm` resistor
+    /// between the input and TOUT, `bottom_ohm` resistor between TOUT and the ground
+    pub fn with_divider(top_ohm: u32, bottom_ohm: u32) -> Self {
+        let full_scale_mv = TOUT_FULL_SCALE_MV as u64 * (top_ohm as u64 + bottom_ohm as u64)
+            / (bottom_ohm as u64).max(1);
+        Self { full_scale_mv: full_scale_mv.min(u32::MAX as u64) as u32, offset_mv: 0 }
+    }
+
+    /// Calibration, which maps `raw` reading of the known reference voltage to `mv`
+    pub fn from_reference(raw: u16, mv: u32) -> Self {
+        let full_scale_mv = mv as u64 * ADC_RESOLUTION as u64 / (raw as u64).max(1);
+        Self { full_scale_mv: full_scale_mv.min(u32::MAX as u64) as u32, offset_mv: 0 }
+    }
+
+    /// Input voltage, which corresponds to the raw value of 1024
+    pub fn set_full_scale_mv(&mut self, mv: u32) -> &mut Self {
+        self.full_scale_mv = mv;
+        self
+    }
+
+    /// Offset added to the converted value, e.g. to compensate the zero error
+    pub fn set_offset_mv(&mut self, offset_mv: i32) -> &mut Self {
+        self.offset_mv = offset_mv;
+        self
+    }
+
+    pub fn full_scale_mv(&self) -> u32 {
+        self.full_scale_mv
+    }
+
+    /// Converts raw 10-bit reading to millivolts. Negative results are clamped to zero
+    pub fn raw_to_mv(&self, raw: u16) -> u32 {
+        let mv = raw as i64 * self.full_scale_mv as i64 / ADC_RESOLUTION as i64 + self.offset_mv as i64;
+        mv.max(0).min(u32::MAX as i64) as u32
+    }
+}
+
+pub struct AdcInitializer {
+    clock_divider: u8,
+    calibration: AdcCalibration,
+    peripherals: AdcPeripherals,
+}
+
+impl AdcInitializer {
+    pub fn new(adc: AdcPeripherals) -> Self {
+        Self {
+            clock_divider: MIN_CLOCK_DIVIDER,
+            calibration: AdcCalibration::new(),
+            peripherals: adc,
+        }
+    }
+
+    /// Divider of the 80 MHz sample clock. Default is 8
+    pub fn set_clock_divider(&mut self, divider: u8) -> Result<&mut Self, AdcConfigError> {
+        if !(MIN_CLOCK_DIVIDER..=MAX_CLOCK_DIVIDER).contains(&divider) {
+            Err(AdcConfigError::InvalidClockDivider)
+        } else {
+            self.clock_divider = divider;
+            Ok(self)
+        }
+    }
+
+    /// Calibration of the TOUT readings. Default is 1.0 V full scale without offset
+    pub fn set_calibration(&mut self, calibration: AdcCalibration) -> &mut Self {
+        self.calibration = calibration;
+        self
+    }
+
+    fn initialize<Mode: AdcModeMarker>(self) -> Result<Adc<Mode>, (AdcError, Self)> {
+        if let Err(err) = init_adc::<Mode>(self.clock_divider) {
+            return Err((err, self));
+        }
+
+        Ok(Adc {
+            clock_divider: self.clock_divider,
+            calibration: self.calibration,
+            peripherals: self.peripherals,
+            _mode: PhantomData,
+        })
+    }
+
+    /// Initializes ADC for TOUT pin measurement. Returns error and initializer back if driver
+    /// rejects the configuration
+    pub fn initialize_tout(self) -> Result<Adc<Tout>, (AdcError, Self)> {
+        self.initialize()
+    }
+
+    /// Initializes ADC for supply voltage measurement. Returns error and initializer back if
+    /// driver rejects the configuration
+    pub fn initialize_vdd33(self) -> Result<Adc<Vdd33>, (AdcError, Self)> {
+        self.initialize()
+    }
+}
+
+fn init_adc<Mode: AdcModeMarker>(clock_divider: u8) -> Result<(), AdcError> {
+    let mut config = adc_config_t {
+        mode: Mode::map_to_ffi(),
+        clk_div: clock_divider,
+    };
+
+    if unsafe { adc_init(&mut config) } == esp_err_t_ESP_OK {
+        Ok(())
+    } else {
+        Err(AdcError::InitializationFailed)
+    }
+}
+
+pub struct Adc<Mode> {
+    clock_divider: u8,
+    calibration: AdcCalibration,
+    peripherals: AdcPeripherals,
+    _mode: PhantomData<Mode>,
+}
+
+impl<Mode: AdcModeMarker> Adc<Mode> {
+    fn into_mode<NewMode: AdcModeMarker>(self) -> Result<Adc<NewMode>, (AdcError, Self)> {
+        unsafe { adc_deinit() };
+
+        if let Err(err) = init_adc::<NewMode>(self.clock_divider) {
+            // Previous mode is restored, so the driver stays usable
+            let _ = init_adc::<Mode>(self.clock_divider);
+            return Err((err, self));
+        }
+
+        Ok(Adc {
+            clock_divider: self.clock_divider,
+            calibration: self.calibration,
+            peripherals: self.peripherals,
+            _mode: PhantomData,
+        })
+    }
+
+    /// Single raw reading. In VDD33 mode the value is in millivolts
+    pub fn read_raw(&mut self) -> Result<u16, AdcError> {
+        let mut value = 0;
+        if unsafe { adc_read(&mut value) } == esp_err_t_ESP_OK {
+            Ok(value)
+        } else {
+            Err(AdcError::ReadFailed)
+        }
+    }
+
+    fn sum_samples(&mut self, samples: u32) -> Result<u32, AdcError> {
+        let mut sum = 0;
+        for _ in 0..samples {
+            sum += self.read_raw()? as u32;
+        }
+        Ok(sum)
+    }
+
+    /// Average of `samples` raw readings, which reduces the noise. Zero samples are treated as one
+    pub fn read_average(&mut self, samples: u16) -> Result<u16, AdcError> {
+        let samples = samples.max(1) as u32;
+        let sum = self.sum_samples(samples)?;
+        Ok(((sum + samples / 2) / samples) as u16)
+    }
+
+    /// Disables ADC and returns peripherals back
+    pub fn deinitialize(self) -> AdcPeripherals {
+        unsafe { adc_deinit() };
+        self.peripherals
+    }
+}
+
+impl Adc<Tout> {
+    /// Switches ADC to the supply voltage measurement. Returns error and ADC in the TOUT mode
+    /// back if mode can't be changed
+    pub fn into_vdd33(self) -> Result<Adc<Vdd33>, (AdcError, Self)> {
+        self.into_mode()
+    }
+
+    /// Reads `buffer.len()` raw samples back-to-back. Fast reads are only available when WiFi is
+    /// disabled
+    pub fn read_fast(&mut self, buffer: &mut [u16]) -> Result<(), AdcError> {
+        for chunk in buffer.chunks_mut(u16::MAX as usize) {
+            if unsafe { adc_read_fast(chunk.as_mut_ptr(), chunk.len() as u16) } != esp_err_t_ESP_OK {
+                return Err(AdcError::ReadFailed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Increases resolution by `extra_bits` by summing `4^extra_bits` readings. Returned value
+    /// has `10 + extra_bits` bits. `extra_bits` is limited to 6 (4096 readings). Oversampling
+    /// works only if the signal has some noise
+    pub fn read_oversampled(&mut self, extra_bits: u8) -> Result<u16, AdcError> {
+        let extra_bits = extra_bits.min(MAX_OVERSAMPLING_BITS);
+        self.sum_samples(1 << (2 * extra_bits)).map(|sum| (sum >> extra_bits) as u16)
+    }
+
+    pub fn calibration(&self) -> &AdcCalibration {
+        &self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: AdcCalibration) -> &mut Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Single reading converted to millivolts with the calibration
+    pub fn read_mv(&mut self) -> Result<u32, AdcError> {
+        self.read_raw().map(|raw| self.calibration.raw_to_mv(raw))
+    }
+
+    /// Average of `samples` readings converted to millivolts with the calibration
+    pub fn read_average_mv(&mut self, samples: u16) -> Result<u32, AdcError> {
+        self.read_average(samples).map(|raw| self.calibration.raw_to_mv(raw))
+    }
+}
+
+impl Adc<Vdd33> {
+    /// Switches ADC to the TOUT pin measurement. Returns error and ADC in the VDD33 mode back if
+    /// mode can't be changed
+    pub fn into_tout(self) -> Result<Adc<Tout>, (AdcError, Self)> {
+        self.into_mode()
+    }
+
+    /// Supply voltage in millivolts
+    pub fn read_mv(&mut self) -> Result<u32, AdcError> {
+        self.read_raw().map(|mv| mv as u32)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::mock;
+    use super::*;
+
+    fn tout() -> Adc<Tout> {
+        AdcInitializer::new(AdcPeripherals {}).initialize_tout().ok().unwrap()
+    }
+
+    #[test]
+    fn calibration_converts_raw_readings_to_millivolts() {
+        assert_eq!(AdcCalibration::new().raw_to_mv(512), 500);
+        assert_eq!(AdcCalibration::new().raw_to_mv(1024), 1000);
+        assert_eq!(AdcCalibration::with_divider(220_000, 100_000).full_scale_mv(), 3200);
+        assert_eq!(AdcCalibration::with_divider(220_000, 100_000).raw_to_mv(512), 1600);
+        assert_eq!(AdcCalibration::from_reference(500, 1000).raw_to_mv(250), 500);
+    }
+
+    #[test]
+    fn negative_offset_is_clamped_to_zero() {
+        let mut calibration = AdcCalibration::new();
+        calibration.set_offset_mv(-10);
+
+        assert_eq!(calibration.raw_to_mv(5), 0);
+        assert_eq!(calibration.raw_to_mv(1024), 990);
+    }
+
+    #[test]
+    fn clock_divider_is_validated() {
+        let mut initializer = AdcInitializer::new(AdcPeripherals {});
+        assert_eq!(initializer.set_clock_divider(7).err(), Some(AdcConfigError::InvalidClockDivider));
+        assert_eq!(initializer.set_clock_divider(33).err(), Some(AdcConfigError::InvalidClockDivider));
+
+        initializer.set_clock_divider(16).ok().unwrap();
+        let _adc = initializer.initialize_tout().ok().unwrap();
+
+        assert_eq!(mock::adc::config().unwrap().clk_div, 16);
+    }
+
+    #[test]
+    fn readings_are_averaged_and_oversampled() {
+        let mut adc = tout();
+        mock::adc::set_tout(512);
+        assert_eq!(adc.read_raw(), Ok(512));
+
+        mock::adc::push_tout_samples(&[510, 514]);
+        assert_eq!(adc.read_average(4), Ok(512));
+
+        mock::adc::push_tout_samples(&[512, 513, 512, 513]);
+        assert_eq!(adc.read_oversampled(1), Ok(1025));
+
+        let mut buffer = [0; 5];
+        adc.read_fast(&mut buffer).unwrap();
+        assert_eq!(buffer, [512; 5]);
+    }
+
+    #[test]
+    fn mode_is_switched_between_tout_and_vdd33() {
+        let adc = tout();
+        mock::adc::set_vdd33_mv(3210);
+
+        let mut adc = adc.into_vdd33().ok().unwrap();
+        assert_eq!(adc.read_mv(), Ok(3210));
+
+        mock::inject_error("adc_init", -1);
+        let adc = match adc.into_tout() {
+            Err((err, adc)) => {
+                assert_eq!(err, AdcError::InitializationFailed);
+                adc
+            }
+            Ok(_) => panic!("failed initialization should return the vdd33 adc"),
+        };
+        assert_eq!(mock::adc::config().unwrap().mode, adc_mode_t_ADC_READ_VDD_MODE);
+
+        let _adc = adc.deinitialize();
+        assert!(mock::adc::config().is_none());
+    }
+}
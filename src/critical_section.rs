@@ -0,0 +1,15 @@
+//! Crate-internal critical section helper.
+//!
+//! Wraps FreeRTOS `vPortEnterCritical`/`vPortExitCritical`, which disable interrupts and
+//! task switching on the current core. Sections may be nested.
+use crate::sys::freertos::{vPortEnterCritical, vPortExitCritical};
+
+/// Executes `f` with interrupts and task switching disabled
+pub(crate) fn free<F, R>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    unsafe { vPortEnterCritical(); }
+    let result = f();
+    unsafe { vPortExitCritical(); }
+    result
+}
@@ -0,0 +1,42 @@
+//! Blocking delays
+use crate::sys::{
+    freertos::*,
+    rom::ets_delay_us,
+};
+
+/// Provides blocking delays.
+///
+/// Parts of the delay which are longer than FreeRTOS tick period are performed with `vTaskDelay`,
+/// yielding cpu to other tasks. The rest is busy-waited.
+#[derive(Copy, Clone, Default)]
+pub struct Delay;
+
+impl Delay {
+    pub fn new() -> Self {
+        Delay
+    }
+
+    pub fn delay_us(&mut self, us: u32) {
+        let tick_period_us = 1_000_000 / configTICK_RATE_HZ;
+
+        let ticks = us / tick_period_us;
+        if ticks > 0 {
+            // vTaskDelay returns when the tick counter is incremented `ticks` times, which
+            // could happen up to one tick period earlier than requested
+            unsafe { vTaskDelay((ticks + 1) as TickType_t) };
+        }
+
+        unsafe { ets_delay_us(us % tick_period_us) };
+    }
+
+    pub fn delay_ms(&mut self, ms: u32) {
+        const MAX_MS_PER_DELAY : u32 = u32::MAX / 1000;
+
+        let mut remaining = ms;
+        while remaining > 0 {
+            let chunk = remaining.min(MAX_MS_PER_DELAY);
+            self.delay_us(chunk * 1000);
+            remaining -= chunk;
+        }
+    }
+}
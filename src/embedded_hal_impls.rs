@@ -0,0 +1,341 @@
+//! [embedded-hal](https://docs.rs/embedded-hal/1.0.0) and
+//! [embedded-io](https://docs.rs/embedded-io/0.6.1) traits implementations, which allow to use
+//! ecosystem drivers with idf-hal types. Enabled with `embedded-hal` feature.
+use core::convert::Infallible;
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital,
+    i2c::{self, I2c},
+    pwm::{self, SetDutyCycle},
+    spi::{self, SpiBus, SpiDevice},
+};
+use embedded_io::{ErrorKind, ErrorType, Read, Write};
+
+use crate::{
+    delay::Delay,
+    gpio::{
+        mode::*,
+        AnyInitializedPin, GpioPin, InitializedPin, InputPin, InputPinMarker, OpenDrainPinMarker, OutputPin,
+        OutputPinMarker, RtcPin, read_output_latch,
+    },
+    i2c::{I2cError, I2cMaster, I2cNackSource, I2cOperation},
+    pwm::{PwmChannel, PwmConfigurationError},
+    spi::{SpiError, SpiMaster, SpiOperation},
+    sys::freertos::portMAX_DELAY,
+    uart::{ReadError, ReceivingUart, TransmittingUart, Uart0, Uart0Alt, Uart1, WaitError},
+};
+
+impl<T: GpioPin, Mode> digital::ErrorType for InitializedPin<T, Mode> {
+    type Error = Infallible;
+}
+
+impl<T, Pull> digital::InputPin for InitializedPin<T, Input<Pull>>
+    where T: GpioPin + InputPinMarker
+{
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.get_level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.get_level())
+    }
+}
+
+impl<T> digital::InputPin for InitializedPin<T, Output<OpenDrain>>
+    where T: GpioPin + OpenDrainPinMarker
+{
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.get_level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.get_level())
+    }
+}
+
+impl<T, Kind> digital::OutputPin for InitializedPin<T, Output<Kind>>
+    where T: GpioPin + OutputPinMarker
+{
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_level(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_level(true);
+        Ok(())
+    }
+}
+
+// Output latch is read instead of the pad, which could be pulled low by another open drain device
+impl<T, Kind> digital::StatefulOutputPin for InitializedPin<T, Output<Kind>>
+    where T: GpioPin + OutputPinMarker
+{
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(read_output_latch(T::get_pin_id()))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!read_output_latch(T::get_pin_id()))
+    }
+}
+
+impl<Mode> digital::ErrorType for AnyInitializedPin<Mode> {
+    type Error = Infallible;
+}
+
+impl<Pull> digital::InputPin for AnyInitializedPin<Input<Pull>> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.get_level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.get_level())
+    }
+}
+
+impl digital::InputPin for AnyInitializedPin<Output<OpenDrain>> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.get_level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.get_level())
+    }
+}
+
+impl<Kind> digital::OutputPin for AnyInitializedPin<Output<Kind>> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_level(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_level(true);
+        Ok(())
+    }
+}
+
+impl<Mode> digital::ErrorType for RtcPin<Mode> {
+    type Error = Infallible;
+}
+
+impl<Pull> digital::InputPin for RtcPin<Input<Pull>> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.get_level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.get_level())
+    }
+}
+
+impl digital::OutputPin for RtcPin<Output<PushPull>> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_level(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_level(true);
+        Ok(())
+    }
+}
+
+impl digital::StatefulOutputPin for RtcPin<Output<PushPull>> {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.get_output_level())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.get_output_level())
+    }
+}
+
+impl i2c::Error for I2cError {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            I2cError::Nack(I2cNackSource::Address) => {
+                i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address)
+            }
+            I2cError::Nack(I2cNackSource::Data) => {
+                i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Data)
+            }
+            I2cError::ArbitrationLoss => i2c::ErrorKind::ArbitrationLoss,
+            I2cError::ClockStretchTimeout => i2c::ErrorKind::Bus,
+        }
+    }
+}
+
+impl<Scl, Sda> i2c::ErrorType for I2cMaster<Scl, Sda>
+    where Scl: GpioPin + OutputPinMarker + OpenDrainPinMarker,
+          Sda: GpioPin + OutputPinMarker + OpenDrainPinMarker,
+{
+    type Error = I2cError;
+}
+
+impl<Scl, Sda> I2c for I2cMaster<Scl, Sda>
+    where Scl: GpioPin + OutputPinMarker + OpenDrainPinMarker,
+          Sda: GpioPin + OutputPinMarker + OpenDrainPinMarker,
+{
+    fn transaction(&mut self, address: u8, operations: &mut [i2c::Operation<'_>]) -> Result<(), Self::Error> {
+        self.run_transaction(address, operations.iter_mut().map(|operation| match operation {
+            i2c::Operation::Read(buffer) => I2cOperation::Read(buffer),
+            i2c::Operation::Write(bytes) => I2cOperation::Write(bytes),
+        }))
+    }
+}
+
+impl spi::Error for SpiError {
+    fn kind(&self) -> spi::ErrorKind {
+        spi::ErrorKind::Other
+    }
+}
+
+impl spi::ErrorType for SpiMaster {
+    type Error = SpiError;
+}
+
+/// Bus operations don't touch CS, so other devices on the bus can be selected with their own pins
+impl SpiBus for SpiMaster {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus_read(words)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.bus_write(words)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.bus_transfer(read, write)
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus_transfer_in_place(words)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // Transactions are blocking
+        Ok(())
+    }
+}
+
+/// Device transactions select the device with the HSPI CS pin
+impl SpiDevice for SpiMaster {
+    fn transaction(&mut self, operations: &mut [spi::Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.run_transaction(operations.iter_mut().map(|operation| match operation {
+            spi::Operation::Read(buffer) => SpiOperation::Read(buffer),
+            spi::Operation::Write(bytes) => SpiOperation::Write(bytes),
+            spi::Operation::Transfer(read, write) => SpiOperation::Transfer(read, write),
+            spi::Operation::TransferInPlace(buffer) => SpiOperation::TransferInPlace(buffer),
+            spi::Operation::DelayNs(ns) => SpiOperation::DelayUs(ns.div_ceil(1000)),
+        }))
+    }
+}
+
+/// Error of the uart `embedded-io` operations
+#[derive(Debug)]
+pub enum UartIoError {
+    Read(ReadError),
+    Write(WaitError),
+}
+
+impl embedded_io::Error for UartIoError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            // `uart_read_bytes` reports the timeout by reading nothing, so the error means the
+            // driver has failed
+            UartIoError::Read(ReadError::Timeout) => ErrorKind::Other,
+            UartIoError::Write(WaitError::Timeout) => ErrorKind::TimedOut,
+        }
+    }
+}
+
+macro_rules! impl_uart_error_type_for {
+    ($($type:ident),+) => {$(
+        impl ErrorType for $type {
+            type Error = UartIoError;
+        }
+    )+}
+}
+
+impl_uart_error_type_for!(Uart0, Uart0Alt, Uart1);
+
+macro_rules! impl_uart_write_for {
+    ($($type:ident),+) => {$(
+        impl Write for $type {
+            fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+                Ok(self.write_bytes(buf))
+            }
+
+            fn flush(&mut self) -> Result<(), Self::Error> {
+                self.wait_write_done(portMAX_DELAY).map_err(UartIoError::Write)
+            }
+        }
+    )+}
+}
+
+impl_uart_write_for!(Uart0, Uart0Alt, Uart1);
+
+macro_rules! impl_uart_read_for {
+    ($($type:ident),+) => {$(
+        impl Read for $type {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+
+                // Block until at least one byte is available, then take what is already received
+                let mut read = 0;
+                while read == 0 {
+                    read = self.read_bytes(&mut buf[..1], portMAX_DELAY)
+                        .map_err(UartIoError::Read)?;
+                }
+
+                let rest = self.read_bytes(&mut buf[1..], 0).map_err(UartIoError::Read)?;
+                Ok(read + rest)
+            }
+        }
+    )+}
+}
+
+impl_uart_read_for!(Uart0, Uart0Alt);
+
+impl pwm::Error for PwmConfigurationError {
+    fn kind(&self) -> pwm::ErrorKind {
+        pwm::ErrorKind::Other
+    }
+}
+
+impl<'a, Pin> pwm::ErrorType for PwmChannel<'a, Pin> {
+    type Error = PwmConfigurationError;
+}
+
+impl<'a, Pin> SetDutyCycle for PwmChannel<'a, Pin> {
+    fn max_duty_cycle(&self) -> u16 {
+        self.period().min(u16::MAX as u32) as u16
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        let max_duty = self.max_duty_cycle();
+        let raw_duty = (duty as u64 * self.period() as u64 / max_duty as u64) as u32;
+
+        self.set_duty(raw_duty)?;
+        Ok(())
+    }
+}
+
+impl DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        Delay::delay_us(self, ns.div_ceil(1000));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        Delay::delay_us(self, us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        Delay::delay_ms(self, ms);
+    }
+}
@@ -1,8 +1,25 @@
 use core::marker::PhantomData;
 
-use idf_sys::gpio::*;
+use crate::sys::gpio::*;
 use crate::peripherals::GpioPeripherals;
 
+mod interrupt;
+mod debounce;
+mod pulse;
+mod encoder;
+mod any;
+mod group;
+mod rtc;
+pub(crate) mod registers;
+
+pub use interrupt::*;
+pub use debounce::*;
+pub use pulse::*;
+pub use encoder::*;
+pub use any::*;
+pub use group::*;
+pub use rtc::*;
+
 pub struct GpioHardware {
     pub gpio0 : Option<Gpio0>,
     pub gpio1 : Option<Gpio1>,
@@ -10,15 +27,70 @@ pub struct GpioHardware {
     pub gpio3 : Option<Gpio3>,
     pub gpio4 : Option<Gpio4>,
     pub gpio5 : Option<Gpio5>,
+    /// Available only with `dio-flash-pins` feature, GPIO9 is free when flash operates in
+    /// DIO/DOUT mode
+    #[cfg(feature = "dio-flash-pins")]
+    pub gpio9 : Option<Gpio9>,
+    /// Available only with `dio-flash-pins` feature, GPIO10 is free when flash operates in
+    /// DIO/DOUT mode
+    #[cfg(feature = "dio-flash-pins")]
+    pub gpio10 : Option<Gpio10>,
     pub gpio12 : Option<Gpio12>,
     pub gpio13 : Option<Gpio13>,
     pub gpio14 : Option<Gpio14>,
     pub gpio15 : Option<Gpio15>,
     pub gpio16 : Option<Gpio16>,
 
+    flash_pins: FlashPinSlots,
+
     _data : PhantomData<()>,
 }
 
+/// Pins connected to the SPI flash chip.
+///
+/// Can be obtained with [GpioHardware::take_flash_pins](struct.GpioHardware.html#method.take_flash_pins)
+pub struct FlashPins {
+    /// SD_CLK
+    pub gpio6 : Gpio6,
+    /// SD_DATA0
+    pub gpio7 : Gpio7,
+    /// SD_DATA1
+    pub gpio8 : Gpio8,
+    /// SD_DATA2. Moved to `GpioHardware` with `dio-flash-pins` feature
+    #[cfg(not(feature = "dio-flash-pins"))]
+    pub gpio9 : Gpio9,
+    /// SD_DATA3. Moved to `GpioHardware` with `dio-flash-pins` feature
+    #[cfg(not(feature = "dio-flash-pins"))]
+    pub gpio10 : Gpio10,
+    /// SD_CMD
+    pub gpio11 : Gpio11,
+}
+
+/// Flash pin tokens kept by `GpioHardware`. Pins are taken all together, but are released back
+/// by the drivers one by one
+struct FlashPinSlots {
+    gpio6 : Option<Gpio6>,
+    gpio7 : Option<Gpio7>,
+    gpio8 : Option<Gpio8>,
+    #[cfg(not(feature = "dio-flash-pins"))]
+    gpio9 : Option<Gpio9>,
+    #[cfg(not(feature = "dio-flash-pins"))]
+    gpio10 : Option<Gpio10>,
+    gpio11 : Option<Gpio11>,
+}
+
+impl FlashPinSlots {
+    fn is_full(&self) -> bool {
+        #[cfg(not(feature = "dio-flash-pins"))]
+        let qio_pins_present = self.gpio9.is_some() && self.gpio10.is_some();
+        #[cfg(feature = "dio-flash-pins")]
+        let qio_pins_present = true;
+
+        self.gpio6.is_some() && self.gpio7.is_some() && self.gpio8.is_some()
+            && self.gpio11.is_some() && qio_pins_present
+    }
+}
+
 impl GpioHardware {
     pub fn new(_peripherals: GpioPeripherals) -> Self {
         GpioHardware {
@@ -28,15 +100,55 @@ impl GpioHardware {
             gpio3 : Some(Gpio3 { _data: PhantomData }),
             gpio4 : Some(Gpio4 { _data: PhantomData }),
             gpio5 : Some(Gpio5 { _data: PhantomData }),
+            #[cfg(feature = "dio-flash-pins")]
+            gpio9 : Some(Gpio9 { _data: PhantomData }),
+            #[cfg(feature = "dio-flash-pins")]
+            gpio10 : Some(Gpio10 { _data: PhantomData }),
             gpio12 : Some(Gpio12 { _data: PhantomData }),
             gpio13 : Some(Gpio13 { _data: PhantomData }),
             gpio14 : Some(Gpio14 { _data: PhantomData }),
             gpio15 : Some(Gpio15 { _data: PhantomData }),
             gpio16 : Some(Gpio16 { _data: PhantomData }),
 
+            flash_pins: FlashPinSlots {
+                gpio6 : Some(Gpio6 { _data: PhantomData }),
+                gpio7 : Some(Gpio7 { _data: PhantomData }),
+                gpio8 : Some(Gpio8 { _data: PhantomData }),
+                #[cfg(not(feature = "dio-flash-pins"))]
+                gpio9 : Some(Gpio9 { _data: PhantomData }),
+                #[cfg(not(feature = "dio-flash-pins"))]
+                gpio10 : Some(Gpio10 { _data: PhantomData }),
+                gpio11 : Some(Gpio11 { _data: PhantomData }),
+            },
+
             _data : PhantomData,
         }
     }
+
+    /// Takes pins connected to the SPI flash chip. Returns `None` if pins were already taken and
+    /// not all of them were released back by the drivers.
+    ///
+    /// # Safety
+    /// Code is executed from the flash, so reconfiguring these pins will crash the chip unless
+    /// the board wires them differently (e.g. flash in DIO/DOUT mode does not use GPIO9 and
+    /// GPIO10). Caller must ensure that taken pins are not used by the flash.
+    pub unsafe fn take_flash_pins(&mut self) -> Option<FlashPins> {
+        let slots = &mut self.flash_pins;
+        if !slots.is_full() {
+            return None;
+        }
+
+        Some(FlashPins {
+            gpio6 : slots.gpio6.take()?,
+            gpio7 : slots.gpio7.take()?,
+            gpio8 : slots.gpio8.take()?,
+            #[cfg(not(feature = "dio-flash-pins"))]
+            gpio9 : slots.gpio9.take()?,
+            #[cfg(not(feature = "dio-flash-pins"))]
+            gpio10 : slots.gpio10.take()?,
+            gpio11 : slots.gpio11.take()?,
+        })
+    }
 }
 
 pub(crate) type PinMask = u32;
@@ -52,6 +164,13 @@ pub trait GpioPin {
     fn get_pin_mask() -> PinMask {
         return 1 << Self::PIN_NUM as PinMask;
     }
+
+    /// Creates pin token without taking it from the [GpioHardware](struct.GpioHardware.html)
+    ///
+    /// # Safety
+    /// Pin token represents pin ownership, so caller must ensure that no other token of the same
+    /// pin exists
+    unsafe fn steal() -> Self;
 }
 
 /// Special case of pin specifying "not connected"
@@ -59,6 +178,10 @@ pub struct PhantomPin { _data: () }
 impl GpioPin for PhantomPin {
     const PIN_NUM: u8 = 255;
 
+    unsafe fn steal() -> Self {
+        PhantomPin { _data: () }
+    }
+
     fn get_pin_id() -> u8 {
         Self::PIN_NUM
     }
@@ -76,6 +199,10 @@ macro_rules! define_gpio_pins {
 
         impl GpioPin for $type {
             const PIN_NUM : PinId = $id;
+
+            unsafe fn steal() -> Self {
+                $type { _data: PhantomData }
+            }
         }
 
         impl $type {
@@ -94,6 +221,12 @@ define_gpio_pins!(
     Gpio3 : 3,
     Gpio4 : 4,
     Gpio5 : 5,
+    Gpio6 : 6,
+    Gpio7 : 7,
+    Gpio8 : 8,
+    Gpio9 : 9,
+    Gpio10 : 10,
+    Gpio11 : 11,
     Gpio12 : 12,
     Gpio13 : 13,
     Gpio14 : 14,
@@ -102,6 +235,65 @@ define_gpio_pins!(
 );
 
 
+/// Provides moving of the pin token out of and back to the [GpioHardware](struct.GpioHardware.html)
+///
+/// Used by drivers which capture pins on initialization and release them on deinitialization
+pub trait CaptureGpioPin {
+    /// Returns `true` if pin token is present in `gpio_hw`
+    fn is_pin_available(gpio_hw: &GpioHardware) -> bool;
+    fn capture_pin(gpio_hw: &mut GpioHardware);
+    fn release_pin(gpio_hw: &mut GpioHardware);
+}
+
+impl CaptureGpioPin for PhantomPin {
+    fn is_pin_available(_gpio_hw: &GpioHardware) -> bool { true }
+    fn capture_pin(_gpio_hw: &mut GpioHardware) {}
+    fn release_pin(_gpio_hw: &mut GpioHardware) {}
+}
+
+macro_rules! impl_capture_gpio_pin_for {
+    ($($type:ident : $field:ident),+) => {$(
+        impl CaptureGpioPin for $type {
+            fn is_pin_available(gpio_hw: &GpioHardware) -> bool { gpio_hw.$field.is_some() }
+            fn capture_pin(gpio_hw: &mut GpioHardware) { gpio_hw.$field.take(); }
+            fn release_pin(gpio_hw: &mut GpioHardware) { gpio_hw.$field.replace( $type::new() ); }
+        }
+    )+}
+}
+
+impl_capture_gpio_pin_for!(
+    Gpio0 : gpio0,
+    Gpio1 : gpio1,
+    Gpio2 : gpio2,
+    Gpio3 : gpio3,
+    Gpio4 : gpio4,
+    Gpio5 : gpio5,
+    Gpio12 : gpio12,
+    Gpio13 : gpio13,
+    Gpio14 : gpio14,
+    Gpio15 : gpio15,
+    Gpio16 : gpio16
+);
+
+#[cfg(feature = "dio-flash-pins")]
+impl_capture_gpio_pin_for!(Gpio9 : gpio9, Gpio10 : gpio10);
+
+macro_rules! impl_capture_flash_pin_for {
+    ($($type:ident : $field:ident),+) => {$(
+        impl CaptureGpioPin for $type {
+            fn is_pin_available(gpio_hw: &GpioHardware) -> bool { gpio_hw.flash_pins.$field.is_some() }
+            fn capture_pin(gpio_hw: &mut GpioHardware) { gpio_hw.flash_pins.$field.take(); }
+            fn release_pin(gpio_hw: &mut GpioHardware) { gpio_hw.flash_pins.$field.replace( $type::new() ); }
+        }
+    )+}
+}
+
+// Released flash pins can be taken again with GpioHardware::take_flash_pins
+impl_capture_flash_pin_for!(Gpio6 : gpio6, Gpio7 : gpio7, Gpio8 : gpio8, Gpio11 : gpio11);
+
+#[cfg(not(feature = "dio-flash-pins"))]
+impl_capture_flash_pin_for!(Gpio9 : gpio9, Gpio10 : gpio10);
+
 pub trait OutputPinMarker {}
 pub trait InputPinMarker {}
 pub trait OpenDrainPinMarker {}
@@ -109,6 +301,7 @@ pub trait PullDownPinMarker {}
 pub trait PullUpPinMarker {}
 pub trait InterruptPinMarker {}
 pub trait PwmPinMarker {}
+pub trait PortPinMarker {}
 
 macro_rules! impl_interrupt_pin_for {
     ($($type:ident),+) => { $(impl InterruptPinMarker for $type {})+ };
@@ -116,7 +309,8 @@ macro_rules! impl_interrupt_pin_for {
 
 // All pins except Gpio16 can be configured as interrupt pins
 impl_interrupt_pin_for!(
-    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio12, Gpio13, Gpio14, Gpio15
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15
 );
 
 
@@ -126,7 +320,8 @@ macro_rules! impl_input_pin_for {
 
 // All pins can be configured as interrupt pins
 impl_input_pin_for!(
-    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio12, Gpio13, Gpio14, Gpio15, Gpio16
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15, Gpio16
 );
 
 macro_rules! impl_output_pin_for {
@@ -135,7 +330,8 @@ macro_rules! impl_output_pin_for {
 
 // All pins can be configured as output pins
 impl_output_pin_for!(
-    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio12, Gpio13, Gpio14, Gpio15, Gpio16
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15, Gpio16
 );
 
 macro_rules! impl_open_drain_pin_for {
@@ -144,7 +340,8 @@ macro_rules! impl_open_drain_pin_for {
 
 // All pins except Gpio16 can be configured as open drain pins
 impl_open_drain_pin_for!(
-    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio12, Gpio13, Gpio14, Gpio15
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15
 );
 
 macro_rules! impl_pull_down_pin_for {
@@ -159,14 +356,30 @@ macro_rules! impl_pull_up_pin_for {
 }
 
 // All pins except Gpio16 can be configured as pull up pins
-impl_pull_up_pin_for!(Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio12, Gpio13, Gpio14, Gpio15);
+impl_pull_up_pin_for!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15
+);
 
 macro_rules! impl_pwm_pin_for {
     ($($type:ident),+) => { $(impl PwmPinMarker for $type {})+ };
 }
 
 // All pins except Gpio16 can be configured as pwm pins
-impl_pwm_pin_for!(Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio12, Gpio13, Gpio14, Gpio15);
+impl_pwm_pin_for!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15
+);
+
+macro_rules! impl_port_pin_for {
+    ($($type:ident),+) => { $(impl PortPinMarker for $type {})+ };
+}
+
+// All pins except Gpio16 are controlled by GPIO registers and can be a part of PinGroup
+impl_port_pin_for!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15
+);
 
 #[derive(Copy, Clone)]
 pub enum PinInterruptMode {
@@ -192,55 +405,162 @@ impl PinInterruptMode {
 }
 
 
-pub struct PinInitializer<T : GpioPin> {
+/// Pin mode type states. Mode is a part of
+/// [InitializedPin](struct.InitializedPin.html) and [PinInitializer](struct.PinInitializer.html)
+/// types, so pin can be used only in a way its mode allows (e.g. push-pull output doesn't
+/// implement `InputPin`, the level it is set to is read with `get_output_level`)
+pub mod mode {
+    use core::marker::PhantomData;
+
+    /// Pin is not configured yet
+    pub struct Disabled;
+
+    /// Input pin mode with `Pull` resistor configuration
+    pub struct Input<Pull> {
+        _pull: PhantomData<Pull>,
+    }
+
+    /// Output pin mode of `Kind` (push-pull or open drain)
+    pub struct Output<Kind> {
+        _kind: PhantomData<Kind>,
+    }
+
+    /// Input pin without pull resistors
+    pub struct Floating;
+    /// Input pin with enabled pull up resistor
+    pub struct PullUp;
+    /// Input pin with enabled pull down resistor
+    pub struct PullDown;
+
+    /// Output which drives both high and low levels
+    pub struct PushPull;
+    /// Output which drives only low level; Level of such pin can also be read
+    pub struct OpenDrain;
+}
+
+use mode::*;
+
+// Gpio16 is located in the RTC domain and has pull down resistor instead of pull up
+const RTC_GPIO_PIN_ID : PinId = 16;
+
+fn disable_pulls(pin_id: PinId) {
+    unsafe {
+        if pin_id == RTC_GPIO_PIN_ID {
+            gpio_pulldown_dis(pin_id as gpio_num_t);
+        } else {
+            gpio_pullup_dis(pin_id as gpio_num_t);
+        }
+    }
+}
+
+/// Level written to the output latch of the pin. Unlike the pad level, it doesn't depend on the
+/// other devices driving the line
+#[cfg(feature = "embedded-hal")]
+pub(crate) fn read_output_latch(pin_id: PinId) -> bool {
+    if pin_id == RTC_GPIO_PIN_ID {
+        registers::read_rtc(registers::RtcRegister::GpioOut) & 1 != 0
+    } else {
+        registers::read_out() & (1 << pin_id) != 0
+    }
+}
+
+pub struct PinInitializer<T : GpioPin, Mode> {
     _pin: PhantomData<T>,
+    _mode: PhantomData<Mode>,
     config: gpio_config_t,
 }
 
-pub struct InitializedPin<T : GpioPin> {
+/// Configured pin in the specific `Mode`.
+///
+/// Can be converted to another mode with `into_*` methods
+pub struct InitializedPin<T : GpioPin, Mode> {
     _pin: PhantomData<T>,
+    _mode: PhantomData<Mode>,
 }
 
-impl<T: GpioPin> InitializedPin<T> {
-    pub fn enable_pull_up(&mut self) -> &mut Self where T: PullUpPinMarker {
-        unsafe { gpio_pullup_en(T::get_pin_id() as gpio_num_t); };
-        self
+impl<T: GpioPin, Mode> InitializedPin<T, Mode> {
+    fn new() -> Self {
+        Self { _pin: PhantomData, _mode: PhantomData }
     }
 
-    pub fn disable_pull_up(&mut self) -> &mut Self where T: PullUpPinMarker {
-        unsafe { gpio_pullup_dis(T::get_pin_id() as gpio_num_t); };
-        self
+    fn into_mode<NewMode>(self, mode: gpio_mode_t) -> InitializedPin<T, NewMode> {
+        unsafe { gpio_set_direction(T::get_pin_id() as gpio_num_t, mode); };
+        InitializedPin::new()
+    }
+
+    pub fn into_floating_input(self) -> InitializedPin<T, Input<Floating>>
+        where T: InputPinMarker
+    {
+        disable_pulls(T::get_pin_id());
+        self.into_mode(gpio_mode_t_GPIO_MODE_INPUT)
+    }
+
+    pub fn into_pull_up_input(self) -> InitializedPin<T, Input<PullUp>>
+        where T: InputPinMarker + PullUpPinMarker
+    {
+        unsafe { gpio_pullup_en(T::get_pin_id() as gpio_num_t); };
+        self.into_mode(gpio_mode_t_GPIO_MODE_INPUT)
     }
 
-    pub fn enable_pull_down(&mut self) -> &mut Self where T: PullDownPinMarker {
+    pub fn into_pull_down_input(self) -> InitializedPin<T, Input<PullDown>>
+        where T: InputPinMarker + PullDownPinMarker
+    {
         unsafe { gpio_pulldown_en(T::get_pin_id() as gpio_num_t); };
-        self
+        self.into_mode(gpio_mode_t_GPIO_MODE_INPUT)
     }
 
-    pub fn disable_pull_down(&mut self) -> &mut Self where T: PullDownPinMarker {
-        unsafe { gpio_pulldown_dis(T::get_pin_id() as gpio_num_t); };
-        self
+    pub fn into_push_pull_output(self) -> InitializedPin<T, Output<PushPull>>
+        where T: OutputPinMarker
+    {
+        disable_pulls(T::get_pin_id());
+        self.into_mode(gpio_mode_t_GPIO_MODE_OUTPUT)
     }
 
-    pub fn configure_as_input(&mut self) -> &mut Self where T: InputPinMarker {
-        unsafe { gpio_set_direction(T::get_pin_id() as gpio_num_t, gpio_mode_t_GPIO_MODE_INPUT); };
-        self
+    pub fn into_open_drain_output(self) -> InitializedPin<T, Output<OpenDrain>>
+        where T: OpenDrainPinMarker
+    {
+        disable_pulls(T::get_pin_id());
+        self.into_mode(gpio_mode_t_GPIO_MODE_OUTPUT_OD)
     }
 
-    pub fn configure_as_output(&mut self) -> &mut Self where T: OutputPinMarker {
-        unsafe { gpio_set_direction(T::get_pin_id() as gpio_num_t, gpio_mode_t_GPIO_MODE_OUTPUT); };
+    pub fn set_interrupt_mode(&mut self, mode: PinInterruptMode) -> &mut Self where T: InterruptPinMarker {
+        unsafe { gpio_set_intr_type(T::get_pin_id() as gpio_num_t, mode.to_raw()); };
         self
     }
 
-    pub fn configure_as_open_drain(&mut self) -> &mut Self where T: OpenDrainPinMarker {
-        unsafe {
-            gpio_set_direction(T::get_pin_id() as gpio_num_t, gpio_mode_t_GPIO_MODE_OUTPUT_OD);
+    /// Disables pin and returns its token back, so it can be used by another driver
+    pub fn release(self) -> T {
+        let config = gpio_config_t {
+            pin_bit_mask: T::get_pin_mask(),
+            mode: gpio_mode_t_GPIO_MODE_DISABLE,
+            pull_up_en: gpio_pullup_t_GPIO_PULLUP_DISABLE,
+            pull_down_en: gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
+            intr_type: gpio_int_type_t_GPIO_INTR_DISABLE,
         };
+
+        unsafe {
+            gpio_config(&config);
+            T::steal()
+        }
+    }
+}
+
+impl<T: GpioPin, Kind> InitializedPin<T, Output<Kind>> {
+    /// Returns level of the output pin. For open drain pins level could be pulled low by
+    /// another device even if pin itself is set high
+    pub fn get_output_level(&self) -> bool where T: OutputPinMarker {
+        (unsafe { gpio_get_level(T::get_pin_id() as gpio_num_t) }) != 0
+    }
+}
+
+impl<T: GpioPin> InitializedPin<T, Output<OpenDrain>> {
+    pub fn enable_pull_up(&mut self) -> &mut Self where T: PullUpPinMarker {
+        unsafe { gpio_pullup_en(T::get_pin_id() as gpio_num_t); };
         self
     }
 
-    pub fn set_interrupt_mode(&mut self, mode: PinInterruptMode) -> &mut Self where T: InterruptPinMarker {
-        unsafe { gpio_set_intr_type(T::get_pin_id() as gpio_num_t, mode.to_raw()); };
+    pub fn disable_pull_up(&mut self) -> &mut Self where T: PullUpPinMarker {
+        unsafe { gpio_pullup_dis(T::get_pin_id() as gpio_num_t); };
         self
     }
 }
@@ -249,7 +569,13 @@ pub trait InputPin {
     fn get_level(&self) -> bool;
 }
 
-impl<T> InputPin for InitializedPin<T> where T: GpioPin + InputPinMarker {
+impl<T, Pull> InputPin for InitializedPin<T, Input<Pull>> where T: GpioPin + InputPinMarker {
+    fn get_level(&self) -> bool {
+        (unsafe { gpio_get_level(T::get_pin_id() as gpio_num_t) }) != 0
+    }
+}
+
+impl<T> InputPin for InitializedPin<T, Output<OpenDrain>> where T: GpioPin + OpenDrainPinMarker {
     fn get_level(&self) -> bool {
         (unsafe { gpio_get_level(T::get_pin_id() as gpio_num_t) }) != 0
     }
@@ -259,14 +585,61 @@ pub trait OutputPin {
     fn set_level(&mut self, value: bool);
 }
 
-impl<T> OutputPin for InitializedPin<T> where T: GpioPin + OutputPinMarker {
+impl<T, Kind> OutputPin for InitializedPin<T, Output<Kind>> where T: GpioPin + OutputPinMarker {
     fn set_level(&mut self, value: bool) {
         unsafe { gpio_set_level(T::get_pin_id() as gpio_num_t, value as u32) };
     }
 }
 
 
-impl<T : GpioPin> PinInitializer<T> {
+impl<T : GpioPin, Mode> PinInitializer<T, Mode> {
+    fn into_mode<NewMode>(self) -> PinInitializer<T, NewMode> {
+        PinInitializer {
+            config: self.config,
+            _pin: PhantomData,
+            _mode: PhantomData,
+        }
+    }
+
+    pub fn configure_as_input(mut self) -> PinInitializer<T, Input<Floating>>
+        where T: InputPinMarker
+    {
+        self.config.mode = gpio_mode_t_GPIO_MODE_INPUT;
+        self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_DISABLE;
+        self.config.pull_down_en = gpio_pulldown_t_GPIO_PULLDOWN_DISABLE;
+        self.into_mode()
+    }
+
+    pub fn configure_as_output(mut self) -> PinInitializer<T, Output<PushPull>>
+        where T: OutputPinMarker
+    {
+        self.config.mode = gpio_mode_t_GPIO_MODE_OUTPUT;
+        self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_DISABLE;
+        self.config.pull_down_en = gpio_pulldown_t_GPIO_PULLDOWN_DISABLE;
+        self.into_mode()
+    }
+
+    pub fn configure_as_open_drain(mut self) -> PinInitializer<T, Output<OpenDrain>>
+        where T: OpenDrainPinMarker
+    {
+        self.config.mode = gpio_mode_t_GPIO_MODE_OUTPUT_OD;
+        self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_DISABLE;
+        self.config.pull_down_en = gpio_pulldown_t_GPIO_PULLDOWN_DISABLE;
+        self.into_mode()
+    }
+
+    pub fn set_interrupt_mode(mut self, mode: PinInterruptMode) -> Self where T: InterruptPinMarker {
+        self.config.intr_type = mode.to_raw();
+        self
+    }
+
+    pub fn init(self) -> InitializedPin<T, Mode> {
+        unsafe { gpio_config(&self.config); };
+        InitializedPin::new()
+    }
+}
+
+impl<T : GpioPin> PinInitializer<T, Disabled> {
     pub fn new(_pin: T) -> Self {
         Self {
             config: gpio_config_t {
@@ -276,42 +649,29 @@ impl<T : GpioPin> PinInitializer<T> {
                 pull_down_en: gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
                 intr_type: gpio_int_type_t_GPIO_INTR_DISABLE,
             },
-            _pin: PhantomData
+            _pin: PhantomData,
+            _mode: PhantomData,
         }
     }
+}
 
-    pub fn enable_pull_up(mut self) -> Self where T: PullUpPinMarker {
+impl<T : GpioPin> PinInitializer<T, Input<Floating>> {
+    pub fn enable_pull_up(mut self) -> PinInitializer<T, Input<PullUp>> where T: PullUpPinMarker {
         self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_ENABLE;
-        self
+        self.into_mode()
     }
 
-    pub fn enable_pull_down(mut self) -> Self where T: PullDownPinMarker {
+    pub fn enable_pull_down(mut self) -> PinInitializer<T, Input<PullDown>>
+        where T: PullDownPinMarker
+    {
         self.config.pull_down_en = gpio_pulldown_t_GPIO_PULLDOWN_ENABLE;
-        self
-    }
-
-    pub fn configure_as_input(mut self) -> Self where T: InputPinMarker {
-        self.config.mode = gpio_mode_t_GPIO_MODE_INPUT;
-        self
-    }
-
-    pub fn configure_as_output(mut self) -> Self where T: OutputPinMarker {
-        self.config.mode = gpio_mode_t_GPIO_MODE_OUTPUT;
-        self
-    }
-
-    pub fn configure_as_open_drain(mut self) -> Self where T: OpenDrainPinMarker {
-        self.config.mode = gpio_mode_t_GPIO_MODE_OUTPUT_OD;
-        self
+        self.into_mode()
     }
+}
 
-    pub fn set_interrupt_mode(mut self, mode: PinInterruptMode) -> Self where T: InterruptPinMarker {
-        self.config.intr_type = mode.to_raw();
+impl<T : GpioPin> PinInitializer<T, Output<OpenDrain>> {
+    pub fn enable_pull_up(mut self) -> Self where T: PullUpPinMarker {
+        self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_ENABLE;
         self
     }
-
-    pub fn init(self) -> InitializedPin<T> {
-        unsafe { gpio_config(&self.config); };
-        InitializedPin { _pin : PhantomData }
-    }
 }
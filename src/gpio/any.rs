@@ -0,0 +1,425 @@
+//! Type-erased pins, which carry pin number at runtime.
+//!
+//! Useful when pins should be stored in collections or selected at runtime (e.g. from the
+//! configuration). Pin capabilities are checked at runtime, so operations which are not
+//! supported by the pin return [AnyPinError](enum.AnyPinError.html) instead of failing to
+//! compile.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     gpio::mode::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut led_bar: [AnyInitializedPin<Output<PushPull>>; 3] = [
+//!     PinInitializer::new(gpio.gpio4.take().unwrap()).configure_as_output().init().into(),
+//!     PinInitializer::new(gpio.gpio5.take().unwrap()).configure_as_output().init().into(),
+//!     gpio.take_pin(12).unwrap().into_push_pull_output().ok().unwrap(),
+//! ];
+//!
+//! for led in led_bar.iter_mut() {
+//!     led.set_level(true);
+//! }
+//! ```
+use core::marker::PhantomData;
+
+use crate::sys::gpio::*;
+use super::{
+    mode::*,
+    CaptureGpioPin, GpioHardware, GpioPin, InitializedPin, InputPin, InputPinMarker, OutputPin, PinId,
+    PinInterruptMode, RTC_GPIO_PIN_ID,
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15, Gpio16,
+};
+
+/// Pin capabilities, which are encoded with marker traits for typed pins
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PinCapability {
+    Input,
+    Output,
+    OpenDrain,
+    PullUp,
+    PullDown,
+    Interrupt,
+    Pwm,
+}
+
+impl PinCapability {
+    /// Mirrors marker traits implementations for typed pins
+    fn is_supported_by(self, id: PinId) -> bool {
+        match self {
+            PinCapability::Input | PinCapability::Output => true,
+            PinCapability::PullDown => id == RTC_GPIO_PIN_ID,
+            PinCapability::OpenDrain
+            | PinCapability::PullUp
+            | PinCapability::Interrupt
+            | PinCapability::Pwm => id != RTC_GPIO_PIN_ID,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AnyPinError {
+    /// Requested operation is not supported by the pin
+    NotSupported(PinCapability),
+}
+
+fn check_capability(id: PinId, capability: PinCapability) -> Result<(), AnyPinError> {
+    if capability.is_supported_by(id) {
+        Ok(())
+    } else {
+        Err(AnyPinError::NotSupported(capability))
+    }
+}
+
+/// Pin token with the pin number known only at runtime.
+///
+/// Can be obtained from any typed pin token with `into()` or from the
+/// [GpioHardware::take_pin](../struct.GpioHardware.html#method.take_pin)
+pub struct AnyPin {
+    id: PinId,
+}
+
+/// Initialized pin with the pin number known only at runtime.
+///
+/// Can be obtained from any [InitializedPin](../struct.InitializedPin.html) with `into()`
+/// or by the initialization of the [AnyPin](struct.AnyPin.html)
+pub struct AnyInitializedPin<Mode> {
+    id: PinId,
+    _mode: PhantomData<Mode>,
+}
+
+/// Configures pin like [PinInitializer](../struct.PinInitializer.html) does, which also selects
+/// the GPIO function of the pin and disables its interrupt
+fn configure(id: PinId, mode: gpio_mode_t, pull_up_en: gpio_pullup_t, pull_down_en: gpio_pulldown_t) {
+    let config = gpio_config_t {
+        pin_bit_mask: 1 << id as u32,
+        mode,
+        pull_up_en,
+        pull_down_en,
+        intr_type: gpio_int_type_t_GPIO_INTR_DISABLE,
+    };
+    unsafe { gpio_config(&config); };
+}
+
+/// Initializes pin in the selected mode without pulls
+fn into_mode<Mode>(id: PinId, mode: gpio_mode_t) -> AnyInitializedPin<Mode> {
+    configure(id, mode, gpio_pullup_t_GPIO_PULLUP_DISABLE, gpio_pulldown_t_GPIO_PULLDOWN_DISABLE);
+    AnyInitializedPin { id, _mode: PhantomData }
+}
+
+macro_rules! impl_any_pin_mode_conversions {
+    () => {
+        pub fn into_floating_input(self) -> Result<AnyInitializedPin<Input<Floating>>, (AnyPinError, Self)> {
+            Ok(into_mode(self.id, gpio_mode_t_GPIO_MODE_INPUT))
+        }
+
+        pub fn into_pull_up_input(self) -> Result<AnyInitializedPin<Input<PullUp>>, (AnyPinError, Self)> {
+            if let Err(err) = check_capability(self.id, PinCapability::PullUp) {
+                return Err((err, self));
+            }
+
+            configure(
+                self.id,
+                gpio_mode_t_GPIO_MODE_INPUT,
+                gpio_pullup_t_GPIO_PULLUP_ENABLE,
+                gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
+            );
+            Ok(AnyInitializedPin { id: self.id, _mode: PhantomData })
+        }
+
+        pub fn into_pull_down_input(self) -> Result<AnyInitializedPin<Input<PullDown>>, (AnyPinError, Self)> {
+            if let Err(err) = check_capability(self.id, PinCapability::PullDown) {
+                return Err((err, self));
+            }
+
+            configure(
+                self.id,
+                gpio_mode_t_GPIO_MODE_INPUT,
+                gpio_pullup_t_GPIO_PULLUP_DISABLE,
+                gpio_pulldown_t_GPIO_PULLDOWN_ENABLE,
+            );
+            Ok(AnyInitializedPin { id: self.id, _mode: PhantomData })
+        }
+
+        pub fn into_push_pull_output(self) -> Result<AnyInitializedPin<Output<PushPull>>, (AnyPinError, Self)> {
+            Ok(into_mode(self.id, gpio_mode_t_GPIO_MODE_OUTPUT))
+        }
+
+        pub fn into_open_drain_output(self) -> Result<AnyInitializedPin<Output<OpenDrain>>, (AnyPinError, Self)> {
+            if let Err(err) = check_capability(self.id, PinCapability::OpenDrain) {
+                return Err((err, self));
+            }
+
+            Ok(into_mode(self.id, gpio_mode_t_GPIO_MODE_OUTPUT_OD))
+        }
+    }
+}
+
+impl AnyPin {
+    /// Creates pin token from the pin number. Caller has to ensure token of this pin doesn't exist
+    pub(super) unsafe fn steal(id: PinId) -> Self {
+        AnyPin { id }
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn supports(&self, capability: PinCapability) -> bool {
+        capability.is_supported_by(self.id)
+    }
+
+    impl_any_pin_mode_conversions!();
+}
+
+impl<Mode> AnyInitializedPin<Mode> {
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn supports(&self, capability: PinCapability) -> bool {
+        capability.is_supported_by(self.id)
+    }
+
+    pub fn set_interrupt_mode(&mut self, mode: PinInterruptMode)
+        -> Result<&mut Self, AnyPinError>
+    {
+        check_capability(self.id, PinCapability::Interrupt)?;
+        unsafe { gpio_set_intr_type(self.id as gpio_num_t, mode.to_raw()); };
+        Ok(self)
+    }
+
+    /// Disables pin and returns its token back
+    pub fn release(self) -> AnyPin {
+        into_mode::<Disabled>(self.id, gpio_mode_t_GPIO_MODE_DISABLE);
+        AnyPin { id: self.id }
+    }
+
+    impl_any_pin_mode_conversions!();
+}
+
+impl<Pull> InputPin for AnyInitializedPin<Input<Pull>> {
+    fn get_level(&self) -> bool {
+        (unsafe { gpio_get_level(self.id as gpio_num_t) }) != 0
+    }
+}
+
+impl InputPin for AnyInitializedPin<Output<OpenDrain>> {
+    fn get_level(&self) -> bool {
+        (unsafe { gpio_get_level(self.id as gpio_num_t) }) != 0
+    }
+}
+
+impl<Kind> OutputPin for AnyInitializedPin<Output<Kind>> {
+    fn set_level(&mut self, value: bool) {
+        unsafe { gpio_set_level(self.id as gpio_num_t, value as u32) };
+    }
+}
+
+// All physical pins are input pins, which excludes `PhantomPin`
+impl<T: GpioPin + InputPinMarker, Mode> From<InitializedPin<T, Mode>> for AnyInitializedPin<Mode> {
+    fn from(_pin: InitializedPin<T, Mode>) -> Self {
+        AnyInitializedPin { id: T::get_pin_id(), _mode: PhantomData }
+    }
+}
+
+macro_rules! impl_any_pin_from {
+    ($($type:ident),+) => {$(
+        impl From<$type> for AnyPin {
+            fn from(_pin: $type) -> Self {
+                AnyPin { id: $type::PIN_NUM }
+            }
+        }
+    )+}
+}
+
+impl_any_pin_from!(
+    Gpio0, Gpio1, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9, Gpio10, Gpio11,
+    Gpio12, Gpio13, Gpio14, Gpio15, Gpio16
+);
+
+macro_rules! impl_gpio_hardware_any_pin_access {
+    ($($id:expr => $field:ident),+) => {
+        impl GpioHardware {
+            /// Takes pin by its number. Returns `None` if pin was already taken or pin is not
+            /// stored in `GpioHardware` (e.g. flash pins)
+            pub fn take_pin(&mut self, id: u8) -> Option<AnyPin> {
+                match id {
+                    $($id => self.$field.take().map(AnyPin::from),)+
+                    _ => None,
+                }
+            }
+
+        }
+    }
+}
+
+impl GpioHardware {
+    /// Returns pin token back. Flash pins are returned to the flash pins, which can be taken
+    /// again with [take_flash_pins](#method.take_flash_pins)
+    pub fn return_pin(&mut self, pin: AnyPin) {
+        match pin.id {
+            0 => Gpio0::release_pin(self),
+            1 => Gpio1::release_pin(self),
+            2 => Gpio2::release_pin(self),
+            3 => Gpio3::release_pin(self),
+            4 => Gpio4::release_pin(self),
+            5 => Gpio5::release_pin(self),
+            6 => Gpio6::release_pin(self),
+            7 => Gpio7::release_pin(self),
+            8 => Gpio8::release_pin(self),
+            9 => Gpio9::release_pin(self),
+            10 => Gpio10::release_pin(self),
+            11 => Gpio11::release_pin(self),
+            12 => Gpio12::release_pin(self),
+            13 => Gpio13::release_pin(self),
+            14 => Gpio14::release_pin(self),
+            15 => Gpio15::release_pin(self),
+            16 => Gpio16::release_pin(self),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(not(feature = "dio-flash-pins"))]
+impl_gpio_hardware_any_pin_access!(
+    0 => gpio0,
+    1 => gpio1,
+    2 => gpio2,
+    3 => gpio3,
+    4 => gpio4,
+    5 => gpio5,
+    12 => gpio12,
+    13 => gpio13,
+    14 => gpio14,
+    15 => gpio15,
+    16 => gpio16
+);
+
+#[cfg(feature = "dio-flash-pins")]
+impl_gpio_hardware_any_pin_access!(
+    0 => gpio0,
+    1 => gpio1,
+    2 => gpio2,
+    3 => gpio3,
+    4 => gpio4,
+    5 => gpio5,
+    9 => gpio9,
+    10 => gpio10,
+    12 => gpio12,
+    13 => gpio13,
+    14 => gpio14,
+    15 => gpio15,
+    16 => gpio16
+);
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        gpio::PinInitializer,
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    #[test]
+    fn conversion_configures_pin_with_gpio_config() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let pin = gpio.take_pin(1).unwrap();
+        mock::clear_calls();
+
+        let mut pin = pin.into_push_pull_output().ok().unwrap();
+        pin.set_level(true);
+
+        let calls = mock::calls_of("gpio_config");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].args, [
+            1 << 1,
+            gpio_mode_t_GPIO_MODE_OUTPUT as i64,
+            gpio_pullup_t_GPIO_PULLUP_DISABLE as i64,
+            gpio_pulldown_t_GPIO_PULLDOWN_DISABLE as i64,
+            gpio_int_type_t_GPIO_INTR_DISABLE as i64,
+        ]);
+        assert!(mock::gpio::pin_state(1).level());
+    }
+
+    #[test]
+    fn mode_conversion_replaces_pulls_and_interrupt() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let mut pin = gpio.take_pin(5).unwrap().into_pull_up_input().ok().unwrap();
+        assert!(mock::gpio::pin_state(5).pull_up);
+        pin.set_interrupt_mode(PinInterruptMode::AnyEdge).ok().unwrap();
+
+        let _pin = pin.into_open_drain_output().ok().unwrap();
+
+        let state = mock::gpio::pin_state(5);
+        assert_eq!(state.mode, gpio_mode_t_GPIO_MODE_OUTPUT_OD);
+        assert!(!state.pull_up);
+        assert_eq!(state.intr_type, gpio_int_type_t_GPIO_INTR_DISABLE);
+    }
+
+    #[test]
+    fn unsupported_capabilities_return_pin_back() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let pin = gpio.take_pin(16).unwrap();
+        assert!(!pin.supports(PinCapability::PullUp));
+
+        let (err, pin) = pin.into_pull_up_input().err().unwrap();
+        assert_eq!(err, AnyPinError::NotSupported(PinCapability::PullUp));
+
+        let mut pin = pin.into_pull_down_input().ok().unwrap();
+        assert!(mock::gpio::pin_state(16).pull_down);
+        assert_eq!(
+            pin.set_interrupt_mode(PinInterruptMode::AnyEdge).err(),
+            Some(AnyPinError::NotSupported(PinCapability::Interrupt))
+        );
+    }
+
+    #[test]
+    fn typed_pins_are_converted_to_any_pins() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let pin: AnyInitializedPin<Input<Floating>> =
+            PinInitializer::new(gpio.gpio4.take().unwrap()).configure_as_input().init().into();
+        mock::gpio::set_input_level(4, true);
+
+        assert_eq!(pin.id(), 4);
+        assert!(pin.get_level());
+    }
+
+    #[test]
+    fn returned_pins_can_be_taken_again() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let pin = gpio.take_pin(12).unwrap();
+        assert!(gpio.take_pin(12).is_none());
+
+        let pin = pin.into_push_pull_output().ok().unwrap().release();
+        assert_eq!(mock::gpio::pin_state(12).mode, gpio_mode_t_GPIO_MODE_DISABLE);
+        gpio.return_pin(pin);
+
+        assert!(gpio.take_pin(12).is_some());
+    }
+
+    #[test]
+    fn returned_flash_pins_are_kept_for_take_flash_pins() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let flash_pins = unsafe { gpio.take_flash_pins() }.unwrap();
+        assert!(unsafe { gpio.take_flash_pins() }.is_none());
+
+        gpio.return_pin(flash_pins.gpio6.into());
+        gpio.return_pin(flash_pins.gpio7.into());
+        gpio.return_pin(flash_pins.gpio8.into());
+        #[cfg(not(feature = "dio-flash-pins"))]
+        gpio.return_pin(flash_pins.gpio9.into());
+        #[cfg(not(feature = "dio-flash-pins"))]
+        gpio.return_pin(flash_pins.gpio10.into());
+        gpio.return_pin(flash_pins.gpio11.into());
+
+        assert!(unsafe { gpio.take_flash_pins() }.is_some());
+    }
+}
@@ -0,0 +1,480 @@
+//! Software debouncing of buttons and other mechanical contacts.
+//!
+//! [Debounced](struct.Debounced.html) attaches an interrupt handler to the input pin, which only
+//! records the level and the time of each edge. Periodic [software timer](../timer/struct.Timer.html)
+//! ticks at half of the settle time and accepts the level once it has been stable for the settle
+//! time, so contact bounces are ignored. Accepted levels are turned into
+//! [ButtonEvent](enum.ButtonEvent.html)s, which are read with
+//! [poll](struct.Debounced.html#method.poll) or [wait_event](struct.Debounced.html#method.wait_event).
+//!
+//! Events are detected by the timer task, so they are not delayed or lost if the pin is polled
+//! rarely. Up to 8 events are buffered, newer events are dropped until the buffer is read.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let pin = PinInitializer::new(gpio.gpio0.take().unwrap())
+//!     .configure_as_input()
+//!     .enable_pull_up()
+//!     .init();
+//!
+//! let mut config = DebounceConfig::new();
+//! config.set_settle_time_ms(30).set_long_press_time_ms(2000);
+//!
+//! let mut button = Debounced::new(pin, &config).ok().unwrap();
+//!
+//! loop {
+//!     match button.wait_event(100) {
+//!         Some(ButtonEvent::DoubleClick) => { /* ... */ }
+//!         Some(ButtonEvent::LongPress) => { /* ... */ }
+//!         _ => {}
+//!     }
+//! }
+//! ```
+use alloc::sync::Arc;
+
+use crate::time::{Duration, TickType_t, duration_to_ticks};
+use crate::timer::{Timer, TimerError};
+use crate::sys::{
+    esp_timer::esp_timer_get_time,
+    freertos::vTaskDelay,
+    gpio::*,
+};
+use super::{
+    mode::*,
+    interrupt::IsrShared,
+    GpioInterruptError, GpioPin, InitializedPin, InputPin, InputPinMarker, InterruptPin,
+    InterruptPinMarker, PinInterruptMode,
+};
+
+const EVENT_QUEUE_SIZE: usize = 8;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ButtonEvent {
+    Pressed,
+    Released,
+    /// Button has been held for the long press time. Reported once per press, before `Released`
+    LongPress,
+    /// Button has been pressed again within the double click time after the short press.
+    /// Reported after `Pressed`
+    DoubleClick,
+}
+
+#[derive(Debug)]
+pub enum DebounceError {
+    /// Interrupt handler can't be attached to the pin
+    Interrupt(GpioInterruptError),
+    /// Software timer, which accepts the levels, can't be created
+    Timer(TimerError),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DebounceConfig {
+    settle_time_us: u64,
+    long_press_time_us: u64,
+    double_click_time_us: u64,
+    active_level: bool,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self {
+            settle_time_us: 20_000,
+            long_press_time_us: 1_000_000,
+            double_click_time_us: 400_000,
+            active_level: false,
+        }
+    }
+}
+
+impl DebounceConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time the level has to be stable to be accepted. Default is 20 ms
+    pub fn set_settle_time_ms(&mut self, time_ms: u32) -> &mut Self {
+        self.settle_time_us = time_ms as u64 * 1000;
+        self
+    }
+
+    /// Time the button has to be held to report `LongPress`. Default is 1 s
+    pub fn set_long_press_time_ms(&mut self, time_ms: u32) -> &mut Self {
+        self.long_press_time_us = time_ms as u64 * 1000;
+        self
+    }
+
+    /// Maximal time between the release and the next press to report `DoubleClick`.
+    /// Default is 400 ms
+    pub fn set_double_click_time_ms(&mut self, time_ms: u32) -> &mut Self {
+        self.double_click_time_us = time_ms as u64 * 1000;
+        self
+    }
+
+    /// Level of the pressed button. Default is low, i.e. button connects pin to the ground
+    pub fn set_active_level(&mut self, level: bool) -> &mut Self {
+        self.active_level = level;
+        self
+    }
+}
+
+struct EventQueue {
+    events: [ButtonEvent; EVENT_QUEUE_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl EventQueue {
+    fn push(&mut self, event: ButtonEvent) {
+        if self.len < EVENT_QUEUE_SIZE {
+            self.events[(self.head + self.len) % EVENT_QUEUE_SIZE] = event;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<ButtonEvent> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % EVENT_QUEUE_SIZE;
+        self.len -= 1;
+        Some(event)
+    }
+}
+
+struct DebounceState {
+    config: DebounceConfig,
+    /// Level after the last edge and time of that edge
+    raw_level: bool,
+    raw_since: u64,
+    /// Last accepted level
+    stable_level: bool,
+    pressed_at: u64,
+    long_press_reported: bool,
+    /// Release time of the last short press, which could start a double click
+    last_click_at: Option<u64>,
+    events: EventQueue,
+}
+
+impl DebounceState {
+    fn is_active(&self, level: bool) -> bool {
+        level == self.config.active_level
+    }
+
+    fn on_edge(&mut self, level: bool, now: u64) {
+        // Timer task could be delayed past the settle time of the previous level, which is still
+        // accepted before it is overwritten
+        self.settle(now);
+        self.raw_level = level;
+        self.raw_since = now;
+    }
+
+    fn on_tick(&mut self, now: u64) {
+        self.settle(now);
+        self.check_long_press(now);
+    }
+
+    /// Accepts the raw level if it has been stable for the settle time at the moment `now`
+    fn settle(&mut self, now: u64) {
+        if self.raw_level == self.stable_level
+            || now.saturating_sub(self.raw_since) < self.config.settle_time_us
+        {
+            return;
+        }
+
+        // Level has changed at the time of the edge, not when the change was accepted
+        let changed_at = self.raw_since;
+        if self.is_active(self.raw_level) {
+            self.stable_level = self.raw_level;
+            self.on_press(changed_at);
+        } else {
+            self.check_long_press(changed_at);
+            self.stable_level = self.raw_level;
+            self.on_release(changed_at);
+        }
+    }
+
+    fn on_press(&mut self, at: u64) {
+        self.pressed_at = at;
+        self.long_press_reported = false;
+        self.events.push(ButtonEvent::Pressed);
+
+        // Click is consumed, so the third press doesn't make another double click
+        if let Some(click_at) = self.last_click_at.take() {
+            if at - click_at <= self.config.double_click_time_us {
+                self.events.push(ButtonEvent::DoubleClick);
+            }
+        }
+    }
+
+    fn on_release(&mut self, at: u64) {
+        // Long press is not a click
+        self.last_click_at = if self.long_press_reported { None } else { Some(at) };
+        self.events.push(ButtonEvent::Released);
+    }
+
+    fn check_long_press(&mut self, now: u64) {
+        if self.is_active(self.stable_level)
+            && !self.long_press_reported
+            && now.saturating_sub(self.pressed_at) >= self.config.long_press_time_us
+        {
+            self.long_press_reported = true;
+            self.events.push(ButtonEvent::LongPress);
+        }
+    }
+}
+
+fn now_us() -> u64 {
+    (unsafe { esp_timer_get_time() }) as u64
+}
+
+/// Period of the timer, which accepts the levels. Level is accepted up to one period after it
+/// has settled
+fn tick_period(config: &DebounceConfig) -> Duration {
+    Duration::from_micros((config.settle_time_us / 2).max(1000))
+}
+
+/// Debounced input pin, which reports button events.
+///
+/// Can be created from any input pin which supports interrupts
+pub struct Debounced<T: GpioPin + InterruptPinMarker, Pull> {
+    pin: InterruptPin<T, Input<Pull>>,
+    _timer: Timer,
+    state: Arc<IsrShared<DebounceState>>,
+}
+
+impl<T, Pull> Debounced<T, Pull> where T: GpioPin + InputPinMarker + InterruptPinMarker {
+    /// Attaches interrupt handler to the pin. Current level of the pin is considered stable.
+    ///
+    /// Returns error and pin back if handler or timer can't be created
+    pub fn new(pin: InitializedPin<T, Input<Pull>>, config: &DebounceConfig)
+        -> Result<Self, (DebounceError, InitializedPin<T, Input<Pull>>)>
+    {
+        let level = pin.get_level();
+        let state = DebounceState {
+            config: *config,
+            raw_level: level,
+            raw_since: now_us(),
+            stable_level: level,
+            pressed_at: 0,
+            long_press_reported: false,
+            last_click_at: None,
+            events: EventQueue {
+                events: [ButtonEvent::Released; EVENT_QUEUE_SIZE],
+                head: 0,
+                len: 0,
+            },
+        };
+        let state = IsrShared::new(state);
+
+        let timer_state = state.clone();
+        let timer = Timer::periodic(tick_period(config), move || {
+            let now = now_us();
+            timer_state.with(|state| state.on_tick(now));
+        });
+        let timer = match timer {
+            Ok(timer) => timer,
+            Err(err) => return Err((DebounceError::Timer(err), pin)),
+        };
+
+        let gpio_num = T::get_pin_id() as gpio_num_t;
+        let isr_state = state.clone();
+        let pin = pin.attach_interrupt(PinInterruptMode::AnyEdge, move || {
+            let level = (unsafe { gpio_get_level(gpio_num) }) != 0;
+            let now = now_us();
+            isr_state.with(|state| state.on_edge(level, now));
+        });
+        let pin = pin.map_err(|(err, pin)| (DebounceError::Interrupt(err), pin))?;
+
+        Ok(Debounced { pin, _timer: timer, state })
+    }
+
+    /// Returns the oldest event, or `None` if there are no events
+    pub fn poll(&mut self) -> Option<ButtonEvent> {
+        self.state.with(|state| state.events.pop())
+    }
+
+    /// Waits up to `ticks` for the event
+    pub fn wait_event(&mut self, ticks: TickType_t) -> Option<ButtonEvent> {
+        let mut waited = 0;
+        loop {
+            if let Some(event) = self.poll() {
+                return Some(event);
+            }
+            if waited >= ticks {
+                return None;
+            }
+
+            unsafe { vTaskDelay(1) };
+            waited += 1;
+        }
+    }
+
+    /// Waits up to `timeout` for the event
+    pub fn wait_event_timeout(&mut self, timeout: Duration) -> Option<ButtonEvent> {
+        self.wait_event(duration_to_ticks(timeout))
+    }
+
+    /// Returns `true` if the accepted level of the pin is the active level
+    pub fn is_pressed(&self) -> bool {
+        self.state.with(|state| state.is_active(state.stable_level))
+    }
+
+    /// Removes interrupt handler and returns pin back
+    pub fn release(self) -> InitializedPin<T, Input<Pull>> {
+        self.pin.detach()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        gpio::{GpioHardware, Gpio4, PinInitializer},
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    const MS: u64 = 1000;
+
+    fn button() -> Debounced<Gpio4, PullUp> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let pin = PinInitializer::new(gpio.gpio4.take().unwrap())
+            .configure_as_input()
+            .enable_pull_up()
+            .init();
+
+        Debounced::new(pin, &DebounceConfig::new()).ok().unwrap()
+    }
+
+    /// Contact bounces for 6 ms before settling at `level`
+    fn bounce_to(level: bool) {
+        for _ in 0..3 {
+            mock::gpio::set_input_level(4, level);
+            mock::esp_timer::advance(MS);
+            mock::gpio::set_input_level(4, !level);
+            mock::esp_timer::advance(MS);
+        }
+        mock::gpio::set_input_level(4, level);
+    }
+
+    fn events(button: &mut Debounced<Gpio4, PullUp>) -> std::vec::Vec<ButtonEvent> {
+        core::iter::from_fn(|| button.poll()).collect()
+    }
+
+    #[test]
+    fn level_is_accepted_on_tick_after_settle_time() {
+        let mut button = button();
+
+        bounce_to(false);
+        // Level has settled at 26 ms, the next tick is at 30 ms
+        mock::esp_timer::advance(23 * MS);
+        assert_eq!(button.poll(), None);
+        assert!(!button.is_pressed());
+
+        mock::esp_timer::advance(MS);
+        assert!(button.is_pressed());
+        assert_eq!(events(&mut button), [ButtonEvent::Pressed]);
+    }
+
+    #[test]
+    fn interrupt_handler_only_records_edges() {
+        let _button = button();
+        mock::clear_calls();
+
+        mock::gpio::set_input_level(4, false);
+        mock::gpio::set_input_level(4, true);
+
+        assert!(mock::calls().iter().all(|call| !call.function.starts_with("esp_timer")));
+    }
+
+    #[test]
+    fn level_is_accepted_on_edge_if_tick_is_late() {
+        let mut button = button();
+
+        mock::gpio::set_input_level(4, false);
+        // Clock runs without the timer task
+        mock::advance_time(50 * MS);
+        mock::gpio::set_input_level(4, true);
+        mock::esp_timer::advance(100 * MS);
+
+        assert_eq!(events(&mut button), [ButtonEvent::Pressed, ButtonEvent::Released]);
+    }
+
+    #[test]
+    fn events_are_detected_without_polling() {
+        let mut button = button();
+
+        bounce_to(false);
+        mock::esp_timer::advance(100 * MS);
+        bounce_to(true);
+        mock::esp_timer::advance(100 * MS);
+        bounce_to(false);
+        mock::esp_timer::advance(100 * MS);
+        bounce_to(true);
+        mock::esp_timer::advance(100 * MS);
+
+        assert_eq!(events(&mut button), [
+            ButtonEvent::Pressed,
+            ButtonEvent::Released,
+            ButtonEvent::Pressed,
+            ButtonEvent::DoubleClick,
+            ButtonEvent::Released,
+        ]);
+    }
+
+    #[test]
+    fn long_press_is_reported_while_button_is_held() {
+        let mut button = button();
+
+        bounce_to(false);
+        mock::esp_timer::advance(500 * MS);
+        assert_eq!(events(&mut button), [ButtonEvent::Pressed]);
+
+        // Button is pressed since 6 ms, the tick after the long press time is at 1010 ms
+        mock::esp_timer::advance(503 * MS);
+        assert_eq!(events(&mut button), []);
+
+        mock::esp_timer::advance(MS);
+        assert_eq!(events(&mut button), [ButtonEvent::LongPress]);
+
+        // Long press is not a click, so the next press is not a double click
+        bounce_to(true);
+        mock::esp_timer::advance(50 * MS);
+        bounce_to(false);
+        mock::esp_timer::advance(50 * MS);
+        assert_eq!(events(&mut button), [ButtonEvent::Released, ButtonEvent::Pressed]);
+    }
+
+    #[test]
+    fn wait_event_returns_event_accepted_by_timer() {
+        let mut button = button();
+
+        bounce_to(false);
+        assert_eq!(button.wait_event(1), None);
+        assert_eq!(button.wait_event(5), Some(ButtonEvent::Pressed));
+        assert_eq!(button.wait_event_timeout(Duration::from_millis(500)), None);
+    }
+
+    #[test]
+    fn release_removes_handler_and_timer() {
+        let button = button();
+        assert_eq!(mock::esp_timer::timer_count(), 1);
+
+        let _pin = button.release();
+
+        assert!(!mock::gpio::has_isr_handler(4));
+        // Timer is freed by the timer task
+        mock::esp_timer::advance(0);
+        assert_eq!(mock::esp_timer::timer_count(), 0);
+    }
+}
@@ -0,0 +1,297 @@
+//! Quadrature rotary encoder driver.
+//!
+//! Both encoder pins are configured as pull up inputs with any edge interrupts. Each edge is
+//! decoded with the state table, which tracks the Gray code sequence of the pins and ignores
+//! invalid transitions caused by contact bounces. Position is positive when pin A leads pin B.
+//!
+//! Mechanical encoders usually produce several steps per detent (click), so the position is
+//! reported in detents - see [set_steps_per_detent](struct.Encoder.html#method.set_steps_per_detent).
+//! Integrated push button can be attached as the [Debounced](struct.Debounced.html) pin.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let button = PinInitializer::new(gpio.gpio0.take().unwrap())
+//!     .configure_as_input()
+//!     .enable_pull_up()
+//!     .init();
+//!
+//! let mut encoder = Encoder::new(gpio.gpio4.take().unwrap(), gpio.gpio5.take().unwrap())
+//!     .ok().unwrap()
+//!     .with_button(Debounced::new(button, &DebounceConfig::new()).ok().unwrap());
+//!
+//! let mut volume = 0;
+//! loop {
+//!     volume += encoder.take_delta();
+//!     if let Some(ButtonEvent::Pressed) = encoder.button().poll() {
+//!         volume = 0;
+//!     }
+//! }
+//! ```
+use alloc::sync::Arc;
+
+use crate::sys::gpio::*;
+use super::{
+    mode::*,
+    interrupt::IsrShared,
+    Debounced, GpioInterruptError, GpioPin, InputPinMarker, InterruptPin,
+    InterruptPinMarker, PinInitializer, PinInterruptMode, PullUpPinMarker,
+};
+
+/// Position change for each transition, indexed by `previous_state << 2 | new_state`, where
+/// state is `a << 1 | b`. Invalid transitions (both pins changed) are ignored
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+const DEFAULT_STEPS_PER_DETENT: u8 = 4;
+
+/// Placeholder for the encoder without push button
+pub struct NoButton;
+
+struct EncoderState {
+    pin_state: u8,
+    steps: i32,
+}
+
+impl EncoderState {
+    fn update(&mut self, a: gpio_num_t, b: gpio_num_t) {
+        let new_state = unsafe { ((gpio_get_level(a) as u8 & 1) << 1) | (gpio_get_level(b) as u8 & 1) };
+        let change = TRANSITIONS[((self.pin_state << 2) | new_state) as usize];
+        self.steps = self.steps.wrapping_add(change as i32);
+        self.pin_state = new_state;
+    }
+}
+
+/// Quadrature encoder on pins `A` and `B` with optional push `Button`
+pub struct Encoder<A, B, Button = NoButton>
+    where A: GpioPin + InterruptPinMarker, B: GpioPin + InterruptPinMarker
+{
+    pin_a: InterruptPin<A, Input<PullUp>>,
+    pin_b: InterruptPin<B, Input<PullUp>>,
+    state: Arc<IsrShared<EncoderState>>,
+    steps_per_detent: u8,
+    last_taken_position: i32,
+    button: Button,
+}
+
+impl<A, B> Encoder<A, B, NoButton>
+    where A: GpioPin + InputPinMarker + PullUpPinMarker + InterruptPinMarker,
+          B: GpioPin + InputPinMarker + PullUpPinMarker + InterruptPinMarker,
+{
+    /// Configures pins and attaches interrupt handlers. Returns error and pins back if handlers
+    /// can't be attached
+    pub fn new(pin_a: A, pin_b: B) -> Result<Self, (GpioInterruptError, A, B)> {
+        let pin_a = PinInitializer::new(pin_a).configure_as_input().enable_pull_up().init();
+        let pin_b = PinInitializer::new(pin_b).configure_as_input().enable_pull_up().init();
+
+        let a = A::get_pin_id() as gpio_num_t;
+        let b = B::get_pin_id() as gpio_num_t;
+
+        let mut initial_state = EncoderState { pin_state: 0, steps: 0 };
+        initial_state.update(a, b);
+        // Initial level is not a transition
+        initial_state.steps = 0;
+        let state = IsrShared::new(initial_state);
+
+        let pin_a = match
+            pin_a.attach_interrupt_with_shared(PinInterruptMode::AnyEdge, &state, move |state| state.update(a, b))
+        {
+            Ok(pin) => pin,
+            Err((err, pin_a)) => return Err((err, pin_a.release(), pin_b.release())),
+        };
+        let pin_b = match
+            pin_b.attach_interrupt_with_shared(PinInterruptMode::AnyEdge, &state, move |state| state.update(a, b))
+        {
+            Ok(pin) => pin,
+            Err((err, pin_b)) => return Err((err, pin_a.detach().release(), pin_b.release())),
+        };
+
+        Ok(Encoder {
+            pin_a,
+            pin_b,
+            state,
+            steps_per_detent: DEFAULT_STEPS_PER_DETENT,
+            last_taken_position: 0,
+            button: NoButton,
+        })
+    }
+
+    /// Attaches integrated push button
+    pub fn with_button<T, Pull>(self, button: Debounced<T, Pull>) -> Encoder<A, B, Debounced<T, Pull>>
+        where T: GpioPin + InputPinMarker + InterruptPinMarker
+    {
+        Encoder {
+            pin_a: self.pin_a,
+            pin_b: self.pin_b,
+            state: self.state,
+            steps_per_detent: self.steps_per_detent,
+            last_taken_position: self.last_taken_position,
+            button,
+        }
+    }
+
+    /// Removes interrupt handlers and returns pins back
+    pub fn release(self) -> (A, B) {
+        let (pin_a, pin_b, _) = self.release_pins();
+        (pin_a, pin_b)
+    }
+}
+
+impl<A, B, Button> Encoder<A, B, Button>
+    where A: GpioPin + InterruptPinMarker, B: GpioPin + InterruptPinMarker
+{
+    /// Number of steps between two detents of the encoder. Default is 4, which is typical for
+    /// mechanical encoders; Use 1 to report raw steps
+    pub fn set_steps_per_detent(&mut self, steps: u8) -> &mut Self {
+        self.steps_per_detent = steps.max(1);
+        self
+    }
+
+    /// Position in raw steps
+    pub fn steps(&self) -> i32 {
+        self.state.with(|state| state.steps)
+    }
+
+    /// Position in detents. Partial rotation between detents is not counted
+    pub fn position(&self) -> i32 {
+        self.steps() / self.steps_per_detent as i32
+    }
+
+    pub fn set_position(&mut self, position: i32) -> &mut Self {
+        let steps = position.wrapping_mul(self.steps_per_detent as i32);
+        self.state.with(|state| state.steps = steps);
+        self.last_taken_position = position;
+        self
+    }
+
+    /// Returns number of detents since the previous call
+    pub fn take_delta(&mut self) -> i32 {
+        let position = self.position();
+        let delta = position.wrapping_sub(self.last_taken_position);
+        self.last_taken_position = position;
+        delta
+    }
+
+    fn release_pins(self) -> (A, B, Button) {
+        let Encoder { pin_a, pin_b, button, .. } = self;
+        (pin_a.detach().release(), pin_b.detach().release(), button)
+    }
+}
+
+impl<A, B, T, Pull> Encoder<A, B, Debounced<T, Pull>>
+    where A: GpioPin + InterruptPinMarker,
+          B: GpioPin + InterruptPinMarker,
+          T: GpioPin + InputPinMarker + InterruptPinMarker,
+{
+    pub fn button(&mut self) -> &mut Debounced<T, Pull> {
+        &mut self.button
+    }
+
+    /// Removes interrupt handlers and returns pins and push button back
+    pub fn release(self) -> (A, B, Debounced<T, Pull>) {
+        self.release_pins()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        gpio::{DebounceConfig, GpioHardware, Gpio4, Gpio5},
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn encoder() -> Encoder<Gpio4, Gpio5> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        mock::gpio::set_input_level(4, true);
+        mock::gpio::set_input_level(5, true);
+        Encoder::new(gpio.gpio4.take().unwrap(), gpio.gpio5.take().unwrap()).ok().unwrap()
+    }
+
+    fn step(a: bool, b: bool) {
+        mock::gpio::set_input_level(4, a);
+        mock::gpio::set_input_level(5, b);
+    }
+
+    /// A leads B: 11 -> 01 -> 00 -> 10 -> 11
+    fn detent_forward() {
+        step(false, true);
+        step(false, false);
+        step(true, false);
+        step(true, true);
+    }
+
+    /// B leads A: 11 -> 10 -> 00 -> 01 -> 11
+    fn detent_backward() {
+        step(true, false);
+        step(false, false);
+        step(false, true);
+        step(true, true);
+    }
+
+    #[test]
+    fn rotation_is_counted_in_detents() {
+        let mut encoder = encoder();
+        for _ in 0..3 {
+            detent_forward();
+        }
+        assert_eq!(encoder.steps(), 12);
+        assert_eq!(encoder.position(), 3);
+        assert_eq!(encoder.take_delta(), 3);
+
+        detent_backward();
+
+        assert_eq!(encoder.position(), 2);
+        assert_eq!(encoder.take_delta(), -1);
+        assert_eq!(encoder.take_delta(), 0);
+    }
+
+    #[test]
+    fn bounces_of_one_pin_cancel_out() {
+        let encoder = encoder();
+
+        for _ in 0..5 {
+            mock::gpio::set_input_level(4, false);
+            mock::gpio::set_input_level(4, true);
+        }
+
+        assert_eq!(encoder.steps(), 0);
+    }
+
+    #[test]
+    fn steps_per_detent_and_position_are_configurable() {
+        let mut encoder = encoder();
+        encoder.set_steps_per_detent(1).set_position(10);
+
+        detent_forward();
+
+        assert_eq!(encoder.position(), 14);
+        assert_eq!(encoder.take_delta(), 4);
+    }
+
+    #[test]
+    fn release_removes_handlers_and_returns_button() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let button = PinInitializer::new(gpio.gpio0.take().unwrap())
+            .configure_as_input()
+            .enable_pull_up()
+            .init();
+        let button = Debounced::new(button, &DebounceConfig::new()).ok().unwrap();
+        let mut encoder = encoder().with_button(button);
+        assert_eq!(encoder.button().poll(), None);
+
+        let (_a, _b, button) = encoder.release();
+
+        assert!(!mock::gpio::has_isr_handler(4));
+        assert!(!mock::gpio::has_isr_handler(5));
+        assert!(mock::gpio::has_isr_handler(0));
+        let _pin = button.release();
+    }
+}
@@ -0,0 +1,344 @@
+//! Groups of pins, which are configured together and read or written at once.
+//!
+//! Pins of the group are configured with a single `gpio_config` call and accessed through
+//! GPIO registers, so levels of all pins change at the same moment (e.g. parallel data bus).
+//! Only pins controlled by GPIO registers can be grouped, which excludes Gpio16.
+//!
+//! Values read from or written to the group are indexed by the order in which pins were added,
+//! i.e. bit 0 belongs to the first added pin, bit 1 to the second one and so on.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut nibble = PinGroupInitializer::new()
+//!     .add_pin(gpio.gpio12.take().unwrap())
+//!     .add_pin(gpio.gpio13.take().unwrap())
+//!     .add_pin(gpio.gpio14.take().unwrap())
+//!     .add_pin(gpio.gpio15.take().unwrap())
+//!     .configure_as_output()
+//!     .init();
+//!
+//! // Sets Gpio12 and Gpio14 high, Gpio13 and Gpio15 low
+//! nibble.write(0b0101);
+//! // Sets Gpio15 high, other pins keep their levels
+//! nibble.write_masked(0b1000, 0b1000);
+//!
+//! nibble.release(&mut gpio);
+//! ```
+use core::marker::PhantomData;
+
+use crate::critical_section;
+use crate::sys::gpio::*;
+use super::{
+    mode::*,
+    registers,
+    AnyPin, GpioHardware, GpioPin, PinId, PinMask, PortPinMarker,
+};
+
+const MAX_GROUP_PINS: usize = 16;
+
+/// Pins of the group in the order they were added
+#[derive(Copy, Clone)]
+struct GroupPins {
+    ids: [PinId; MAX_GROUP_PINS],
+    count: usize,
+}
+
+impl GroupPins {
+    fn as_slice(&self) -> &[PinId] {
+        &self.ids[..self.count]
+    }
+
+    fn mask(&self) -> PinMask {
+        self.as_slice().iter().fold(0, |mask, id| mask | (1 << *id as u32))
+    }
+
+    /// Converts group value to the value of GPIO register
+    fn register_value(&self, value: u32) -> u32 {
+        self.as_slice().iter().enumerate()
+            .filter(|(index, _)| value & (1 << *index) != 0)
+            .fold(0, |register, (_, id)| register | (1 << *id as u32))
+    }
+
+    /// Converts value of GPIO register to the group value
+    fn group_value(&self, register: u32) -> u32 {
+        self.as_slice().iter().enumerate()
+            .filter(|(_, id)| register & (1 << **id as u32) != 0)
+            .fold(0, |value, (index, _)| value | (1 << index))
+    }
+}
+
+pub struct PinGroupInitializer<Mode> {
+    pins: GroupPins,
+    config: gpio_config_t,
+    _mode: PhantomData<Mode>,
+}
+
+/// Group of pins configured in the same `Mode`
+pub struct PinGroup<Mode> {
+    pins: GroupPins,
+    _mode: PhantomData<Mode>,
+}
+
+impl PinGroupInitializer<Disabled> {
+    pub fn new() -> Self {
+        Self {
+            pins: GroupPins { ids: [0; MAX_GROUP_PINS], count: 0 },
+            config: gpio_config_t {
+                pin_bit_mask: 0,
+                mode: gpio_mode_t_GPIO_MODE_DISABLE,
+                pull_up_en: gpio_pullup_t_GPIO_PULLUP_DISABLE,
+                pull_down_en: gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
+                intr_type: gpio_int_type_t_GPIO_INTR_DISABLE,
+            },
+            _mode: PhantomData,
+        }
+    }
+}
+
+impl Default for PinGroupInitializer<Disabled> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Mode> PinGroupInitializer<Mode> {
+    fn into_mode<NewMode>(self) -> PinGroupInitializer<NewMode> {
+        PinGroupInitializer {
+            pins: self.pins,
+            config: self.config,
+            _mode: PhantomData,
+        }
+    }
+
+    /// Adds pin to the group. Pin token is consumed and returned back when the group is released
+    pub fn add_pin<T>(mut self, _pin: T) -> Self where T: GpioPin + PortPinMarker {
+        // Tokens are unique and there are only 16 port pins, so the group can't overflow
+        self.pins.ids[self.pins.count] = T::get_pin_id();
+        self.pins.count += 1;
+        self.config.pin_bit_mask |= T::get_pin_mask();
+        self
+    }
+
+    pub fn configure_as_input(self) -> PinGroupInitializer<Input<Floating>> {
+        self.into_mode_without_pulls(gpio_mode_t_GPIO_MODE_INPUT)
+    }
+
+    pub fn configure_as_output(self) -> PinGroupInitializer<Output<PushPull>> {
+        self.into_mode_without_pulls(gpio_mode_t_GPIO_MODE_OUTPUT)
+    }
+
+    pub fn configure_as_open_drain(self) -> PinGroupInitializer<Output<OpenDrain>> {
+        self.into_mode_without_pulls(gpio_mode_t_GPIO_MODE_OUTPUT_OD)
+    }
+
+    /// Pulls enabled for the previous mode are not carried over to the new one
+    fn into_mode_without_pulls<NewMode>(mut self, mode: gpio_mode_t) -> PinGroupInitializer<NewMode> {
+        self.config.mode = mode;
+        self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_DISABLE;
+        self.config.pull_down_en = gpio_pulldown_t_GPIO_PULLDOWN_DISABLE;
+        self.into_mode()
+    }
+
+    /// Configures all pins of the group with a single `gpio_config` call
+    pub fn init(self) -> PinGroup<Mode> {
+        unsafe { gpio_config(&self.config); };
+        PinGroup { pins: self.pins, _mode: PhantomData }
+    }
+}
+
+impl PinGroupInitializer<Input<Floating>> {
+    pub fn enable_pull_up(mut self) -> PinGroupInitializer<Input<PullUp>> {
+        self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_ENABLE;
+        self.into_mode()
+    }
+}
+
+impl PinGroupInitializer<Output<OpenDrain>> {
+    pub fn enable_pull_up(mut self) -> Self {
+        self.config.pull_up_en = gpio_pullup_t_GPIO_PULLUP_ENABLE;
+        self
+    }
+}
+
+impl<Mode> PinGroup<Mode> {
+    /// Number of pins in the group
+    pub fn len(&self) -> usize {
+        self.pins.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.count == 0
+    }
+
+    /// Numbers of pins in the group in the order they were added
+    pub fn pin_ids(&self) -> &[u8] {
+        self.pins.as_slice()
+    }
+
+    /// Disables pins of the group and returns their tokens back to the `gpio_hw`. Flash pins can
+    /// be taken again with [take_flash_pins](../struct.GpioHardware.html#method.take_flash_pins)
+    pub fn release(self, gpio_hw: &mut GpioHardware) {
+        let config = gpio_config_t {
+            pin_bit_mask: self.pins.mask(),
+            mode: gpio_mode_t_GPIO_MODE_DISABLE,
+            pull_up_en: gpio_pullup_t_GPIO_PULLUP_DISABLE,
+            pull_down_en: gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
+            intr_type: gpio_int_type_t_GPIO_INTR_DISABLE,
+        };
+        unsafe { gpio_config(&config); };
+
+        for id in self.pins.as_slice() {
+            gpio_hw.return_pin(unsafe { AnyPin::steal(*id) });
+        }
+    }
+}
+
+impl<Pull> PinGroup<Input<Pull>> {
+    /// Reads levels of all pins of the group at once
+    pub fn read(&self) -> u32 {
+        self.pins.group_value(registers::read_in())
+    }
+}
+
+impl PinGroup<Output<OpenDrain>> {
+    /// Reads levels of all pins of the group at once. Pins set high could be pulled low by
+    /// another device
+    pub fn read(&self) -> u32 {
+        self.pins.group_value(registers::read_in())
+    }
+}
+
+impl<Kind> PinGroup<Output<Kind>> {
+    /// Returns levels the pins of the group are set to
+    pub fn read_output(&self) -> u32 {
+        self.pins.group_value(registers::read_out())
+    }
+
+    /// Sets levels of all pins of the group at once
+    pub fn write(&mut self, value: u32) {
+        self.write_masked(u32::MAX, value);
+    }
+
+    /// Sets levels of pins selected by the `mask` at once, other pins keep their levels
+    pub fn write_masked(&mut self, mask: u32, value: u32) {
+        let register_mask = self.pins.register_value(mask);
+        let register_value = self.pins.register_value(value);
+
+        // Read-modify-write of the output register must not be interrupted by another
+        // task or ISR changing other pins
+        critical_section::free(|| {
+            let output = registers::read_out();
+            registers::write_out((output & !register_mask) | (register_value & register_mask));
+        });
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    #[test]
+    fn group_is_configured_with_single_call() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        mock::clear_calls();
+
+        let group = PinGroupInitializer::new()
+            .add_pin(gpio.gpio12.take().unwrap())
+            .add_pin(gpio.gpio4.take().unwrap())
+            .configure_as_output()
+            .init();
+
+        let calls = mock::calls_of("gpio_config");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].args[0], (1 << 12) | (1 << 4));
+        assert_eq!(group.pin_ids(), [12, 4]);
+    }
+
+    #[test]
+    fn values_are_mapped_to_pins_in_order_of_addition() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let mut group = PinGroupInitializer::new()
+            .add_pin(gpio.gpio12.take().unwrap())
+            .add_pin(gpio.gpio4.take().unwrap())
+            .add_pin(gpio.gpio13.take().unwrap())
+            .configure_as_output()
+            .init();
+
+        group.write(0b101);
+        assert!(mock::gpio::pin_state(12).level());
+        assert!(!mock::gpio::pin_state(4).level());
+        assert!(mock::gpio::pin_state(13).level());
+
+        group.write_masked(0b011, 0b010);
+        assert!(!mock::gpio::pin_state(12).level());
+        assert!(mock::gpio::pin_state(4).level());
+        assert!(mock::gpio::pin_state(13).level());
+        assert_eq!(group.read_output(), 0b110);
+    }
+
+    #[test]
+    fn input_group_reads_all_pins() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let group = PinGroupInitializer::new()
+            .add_pin(gpio.gpio5.take().unwrap())
+            .add_pin(gpio.gpio0.take().unwrap())
+            .configure_as_input()
+            .enable_pull_up()
+            .init();
+
+        mock::gpio::set_input_level(0, false);
+
+        assert_eq!(group.read(), 0b01);
+    }
+
+    #[test]
+    fn output_modes_disable_pulls() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let group = PinGroupInitializer::new()
+            .add_pin(gpio.gpio5.take().unwrap())
+            .configure_as_input()
+            .enable_pull_up()
+            .configure_as_open_drain()
+            .init();
+
+        assert!(!mock::gpio::pin_state(5).pull_up);
+        assert!(!group.is_empty());
+    }
+
+    #[test]
+    fn release_returns_tokens_and_flash_pins() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let flash_pins = unsafe { gpio.take_flash_pins() }.unwrap();
+        let group = PinGroupInitializer::new()
+            .add_pin(gpio.gpio14.take().unwrap())
+            .add_pin(flash_pins.gpio6)
+            .add_pin(flash_pins.gpio7)
+            .add_pin(flash_pins.gpio8)
+            .add_pin(flash_pins.gpio11)
+            .configure_as_output()
+            .init();
+        #[cfg(not(feature = "dio-flash-pins"))]
+        {
+            gpio.return_pin(flash_pins.gpio9.into());
+            gpio.return_pin(flash_pins.gpio10.into());
+        }
+
+        group.release(&mut gpio);
+
+        assert!(gpio.gpio14.is_some());
+        assert_eq!(mock::gpio::pin_state(6).mode, gpio_mode_t_GPIO_MODE_DISABLE);
+        assert!(unsafe { gpio.take_flash_pins() }.is_some());
+    }
+}
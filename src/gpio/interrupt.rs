@@ -0,0 +1,287 @@
+//! Per-pin GPIO interrupt handlers.
+//!
+//! Handler is attached to the [InitializedPin](../struct.InitializedPin.html) with
+//! [attach_interrupt](../struct.InitializedPin.html#method.attach_interrupt), which produces
+//! [InterruptPin](struct.InterruptPin.html). Handler is removed when `InterruptPin` is dropped
+//! or [detached](struct.InterruptPin.html#method.detach).
+//!
+//! **NOTE:** Handlers are executed in the interrupt context - they should be short, should not
+//! block and should not allocate memory
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    cell::UnsafeCell,
+    ptr::null_mut,
+};
+
+use crate::critical_section;
+use crate::sys::{
+    gpio::*,
+    error::*,
+    ffi::*,
+};
+use super::{GpioPin, InterruptPinMarker, InitializedPin, PinInterruptMode};
+
+type InterruptHandler = Box<dyn FnMut() + Send + 'static>;
+
+/// GPIO interrupt attach error
+#[derive(Debug)]
+pub enum GpioInterruptError {
+    /// Interrupt can't be attached with `PinInterruptMode::Disabled`
+    InvalidMode,
+    /// Internal IDF error
+    IdfError(esp_err_t),
+}
+
+unsafe extern "C" fn interrupt_handler_wrapper(ctx: *mut xtensa_void) {
+    let handler = &mut *(ctx as *mut InterruptHandler);
+    handler();
+}
+
+/// GPIO ISR service is shared between all pins and stays installed after the first use
+fn install_isr_service() -> Result<(), esp_err_t> {
+    match unsafe { gpio_install_isr_service(0) } {
+        // Service has been already installed
+        esp_err_t_ESP_OK | esp_err_t_ESP_ERR_INVALID_STATE => Ok(()),
+        err => Err(err),
+    }
+}
+
+/// State shared between the interrupt handler and the task. Accessed only inside critical
+/// sections, so the handler and the task never observe partial updates
+pub(super) struct IsrShared<S>(UnsafeCell<S>);
+
+// State is accessed only inside critical sections
+unsafe impl<S: Send> Sync for IsrShared<S> {}
+
+impl<S> IsrShared<S> {
+    pub(super) fn new(state: S) -> Arc<Self> {
+        Arc::new(IsrShared(UnsafeCell::new(state)))
+    }
+
+    pub(super) fn with<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut S) -> R
+    {
+        critical_section::free(|| f(unsafe { &mut *self.0.get() }))
+    }
+}
+
+/// Interrupt pin with the state shared with its handler. Handler holds its own reference to the
+/// state, so the state is freed only after both the handler and the owner are gone
+pub(super) struct SharedInterruptPin<T: GpioPin + InterruptPinMarker, Mode, S> {
+    pin: InterruptPin<T, Mode>,
+    shared: Arc<IsrShared<S>>,
+}
+
+impl<T: GpioPin + InterruptPinMarker, Mode, S> SharedInterruptPin<T, Mode, S> {
+    pub(super) fn with<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut S) -> R
+    {
+        self.shared.with(f)
+    }
+
+    /// Removes interrupt handler and returns pin back. Shared state is dropped with the last
+    /// reference
+    pub(super) fn detach(self) -> InitializedPin<T, Mode> {
+        self.pin.detach()
+    }
+}
+
+/// Pin with the attached interrupt handler.
+///
+/// Can be obtained from
+/// [InitializedPin::attach_interrupt](../struct.InitializedPin.html#method.attach_interrupt)
+pub struct InterruptPin<T: GpioPin + InterruptPinMarker, Mode> {
+    pin: InitializedPin<T, Mode>,
+    handler: *mut InterruptHandler,
+}
+
+// Handler is required to be `Send` and is accessed only by the ISR until removal
+unsafe impl<T: GpioPin + InterruptPinMarker, Mode> Send for InterruptPin<T, Mode> {}
+
+impl<T: GpioPin + InterruptPinMarker, Mode> InitializedPin<T, Mode> {
+    /// Attaches `handler`, which will be called from the interrupt context on each pin event
+    /// selected by `mode`.
+    ///
+    /// Returns error and pin back if handler can't be attached
+    pub fn attach_interrupt<F>(self, mode: PinInterruptMode, handler: F)
+        -> Result<InterruptPin<T, Mode>, (GpioInterruptError, Self)>
+        where F: FnMut() + Send + 'static
+    {
+        if let PinInterruptMode::Disabled = mode {
+            return Err((GpioInterruptError::InvalidMode, self));
+        }
+
+        if let Err(err) = install_isr_service() {
+            return Err((GpioInterruptError::IdfError(err), self));
+        }
+
+        let handler: *mut InterruptHandler = Box::into_raw(Box::new(Box::new(handler)));
+        let gpio_num = T::get_pin_id() as gpio_num_t;
+
+        unsafe {
+            let err = gpio_isr_handler_add(
+                gpio_num,
+                Some(interrupt_handler_wrapper),
+                handler as *mut xtensa_void
+            );
+            if err != esp_err_t_ESP_OK {
+                drop(Box::from_raw(handler));
+                return Err((GpioInterruptError::IdfError(err), self));
+            }
+
+            gpio_set_intr_type(gpio_num, mode.to_raw());
+        }
+
+        Ok(InterruptPin { pin: self, handler })
+    }
+
+    /// Attaches `handler`, which updates `state` shared with the task
+    pub(super) fn attach_shared_interrupt<S, F>(self, mode: PinInterruptMode, state: S, handler: F)
+        -> Result<SharedInterruptPin<T, Mode, S>, (GpioInterruptError, Self)>
+        where S: Send + 'static, F: FnMut(&mut S) + Send + 'static
+    {
+        let shared = IsrShared::new(state);
+        let pin = self.attach_interrupt_with_shared(mode, &shared, handler)?;
+        Ok(SharedInterruptPin { pin, shared })
+    }
+
+    /// Attaches `handler`, which updates `shared` state. Same state can be shared by the handlers
+    /// of several pins; each handler keeps its own reference until it is removed
+    pub(super) fn attach_interrupt_with_shared<S, F>(
+        self,
+        mode: PinInterruptMode,
+        shared: &Arc<IsrShared<S>>,
+        mut handler: F,
+    ) -> Result<InterruptPin<T, Mode>, (GpioInterruptError, Self)>
+        where S: Send + 'static, F: FnMut(&mut S) + Send + 'static
+    {
+        let shared = shared.clone();
+        self.attach_interrupt(mode, move || shared.with(&mut handler))
+    }
+}
+
+impl<T: GpioPin + InterruptPinMarker, Mode> InterruptPin<T, Mode> {
+    pub fn pin(&self) -> &InitializedPin<T, Mode> {
+        &self.pin
+    }
+
+    pub fn pin_mut(&mut self) -> &mut InitializedPin<T, Mode> {
+        &mut self.pin
+    }
+
+    /// Changes events which trigger the handler. `PinInterruptMode::Disabled` temporarily
+    /// disables the handler without removing it
+    pub fn set_interrupt_mode(&mut self, mode: PinInterruptMode) -> &mut Self {
+        self.pin.set_interrupt_mode(mode);
+        self
+    }
+
+    /// Removes interrupt handler and returns pin back
+    pub fn detach(mut self) -> InitializedPin<T, Mode> {
+        self.remove_handler();
+        InitializedPin::new()
+    }
+
+    fn remove_handler(&mut self) {
+        if self.handler.is_null() {
+            return;
+        }
+
+        let gpio_num = T::get_pin_id() as gpio_num_t;
+        unsafe {
+            gpio_set_intr_type(gpio_num, PinInterruptMode::Disabled.to_raw());
+            gpio_isr_handler_remove(gpio_num);
+            drop(Box::from_raw(self.handler));
+        }
+        self.handler = null_mut();
+    }
+}
+
+impl<T: GpioPin + InterruptPinMarker, Mode> Drop for InterruptPin<T, Mode> {
+    fn drop(&mut self) {
+        self.remove_handler();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{
+        gpio::{mode::*, GpioHardware, Gpio4, PinInitializer},
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn input_pin() -> InitializedPin<Gpio4, Input<Floating>> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        mock::gpio::set_input_level(4, false);
+        PinInitializer::new(gpio.gpio4.take().unwrap()).configure_as_input().init()
+    }
+
+    fn counting_handler() -> (Arc<AtomicUsize>, impl FnMut() + Send + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let handler_count = count.clone();
+        (count, move || { handler_count.fetch_add(1, Ordering::Relaxed); })
+    }
+
+    #[test]
+    fn handler_is_called_only_on_configured_edge() {
+        let (count, handler) = counting_handler();
+        let _pin = input_pin()
+            .attach_interrupt(PinInterruptMode::PositiveEdge, handler)
+            .ok().unwrap();
+
+        mock::gpio::set_input_level(4, true);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        mock::gpio::set_input_level(4, false);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        mock::gpio::pulse(4, true);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn disabled_mode_is_rejected() {
+        let (_, handler) = counting_handler();
+        let result = input_pin().attach_interrupt(PinInterruptMode::Disabled, handler);
+
+        assert!(matches!(result, Err((GpioInterruptError::InvalidMode, _))));
+        assert!(!mock::gpio::has_isr_handler(4));
+    }
+
+    #[test]
+    fn handler_is_removed_on_drop() {
+        let (count, handler) = counting_handler();
+        let pin = input_pin()
+            .attach_interrupt(PinInterruptMode::AnyEdge, handler)
+            .ok().unwrap();
+        assert!(mock::gpio::has_isr_handler(4));
+
+        drop(pin);
+        mock::gpio::pulse(4, true);
+
+        assert!(!mock::gpio::has_isr_handler(4));
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn handler_is_removed_on_detach() {
+        let (count, handler) = counting_handler();
+        let pin = input_pin()
+            .attach_interrupt(PinInterruptMode::AnyEdge, handler)
+            .ok().unwrap();
+
+        let _pin = pin.detach();
+        mock::gpio::pulse(4, true);
+
+        assert!(!mock::gpio::has_isr_handler(4));
+        assert_eq!(mock::gpio::pin_state(4).intr_type, gpio_int_type_t_GPIO_INTR_DISABLE);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,408 @@
+//! Pulse width and frequency measurement on interrupt pins.
+//!
+//! [PulseMeter](struct.PulseMeter.html) measures durations of high and low levels of the signal
+//! (e.g. echo pulse of the ultrasonic sensor), [PulseCounter](struct.PulseCounter.html) counts
+//! edges over time windows (e.g. anemometer pulses).
+//!
+//! Edges are timestamped in the interrupt handler with the CCOUNT CPU cycle counter, which gives
+//! sub-microsecond resolution but wraps around every 2^32 cycles (~53 s at 80 MHz). Intervals
+//! longer than 10 s are measured with the system timer instead, so wrap-around doesn't corrupt
+//! measurements of slow signals.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut trigger = PinInitializer::new(gpio.gpio4.take().unwrap())
+//!     .configure_as_output()
+//!     .init();
+//! let echo = PinInitializer::new(gpio.gpio5.take().unwrap())
+//!     .configure_as_input()
+//!     .init();
+//!
+//! let mut echo = PulseMeter::new(echo).ok().unwrap();
+//!
+//! echo.clear();
+//! trigger.set_level(true);
+//! trigger.set_level(false);
+//!
+//! if let Some(echo_us) = echo.wait_high_time_us(10) {
+//!     let distance_mm = echo_us * 10 / 58;
+//! }
+//! ```
+use crate::time::{Duration, TickType_t, duration_to_ticks};
+use crate::sys::{
+    esp_timer::esp_timer_get_time,
+    freertos::vTaskDelay,
+    gpio::*,
+    rom::ets_get_cpu_frequency,
+    xtensa::xthal_get_ccount,
+};
+use super::{
+    mode::*,
+    interrupt::SharedInterruptPin,
+    GpioInterruptError, GpioPin, InitializedPin, InputPin, InputPinMarker, InterruptPinMarker,
+    PinInterruptMode,
+};
+
+/// Intervals shorter than this are measured with CCOUNT, which is safe from wrap-around even at
+/// 160 MHz CPU frequency
+const CCOUNT_SAFE_INTERVAL_US: u64 = 10_000_000;
+
+#[derive(Copy, Clone)]
+struct Timestamp {
+    cycles: u32,
+    time_us: u64,
+}
+
+impl Timestamp {
+    fn now() -> Self {
+        unsafe {
+            Timestamp {
+                cycles: xthal_get_ccount(),
+                time_us: esp_timer_get_time() as u64,
+            }
+        }
+    }
+
+    fn elapsed_us(&self, later: &Timestamp) -> u64 {
+        let coarse_us = later.time_us.saturating_sub(self.time_us);
+        if coarse_us < CCOUNT_SAFE_INTERVAL_US {
+            let cpu_mhz = unsafe { ets_get_cpu_frequency() };
+            (later.cycles.wrapping_sub(self.cycles) / cpu_mhz) as u64
+        } else {
+            coarse_us
+        }
+    }
+}
+
+fn saturate_us(us: u64) -> u32 {
+    if us > u32::MAX as u64 { u32::MAX } else { us as u32 }
+}
+
+struct PulseMeterState {
+    level: bool,
+    last_edge: Timestamp,
+    high_time_us: Option<u32>,
+    low_time_us: Option<u32>,
+}
+
+impl PulseMeterState {
+    fn on_edge(&mut self, level: bool, now: Timestamp) {
+        let duration = saturate_us(self.last_edge.elapsed_us(&now));
+        if level == self.level {
+            // Edge between two levels was missed, so the duration can't be attributed to any
+            // level
+        } else if self.level {
+            self.high_time_us = Some(duration);
+        } else {
+            self.low_time_us = Some(duration);
+        }
+
+        self.level = level;
+        self.last_edge = now;
+    }
+}
+
+/// Measures durations of high and low levels of the signal.
+///
+/// Durations longer than `u32::MAX` microseconds (~71 minutes) are saturated
+pub struct PulseMeter<T: GpioPin + InterruptPinMarker, Pull> {
+    pin: SharedInterruptPin<T, Input<Pull>, PulseMeterState>,
+}
+
+impl<T, Pull> PulseMeter<T, Pull> where T: GpioPin + InputPinMarker + InterruptPinMarker {
+    /// Attaches interrupt handler to the pin. Returns error and pin back if handler can't be
+    /// attached
+    pub fn new(pin: InitializedPin<T, Input<Pull>>)
+        -> Result<Self, (GpioInterruptError, InitializedPin<T, Input<Pull>>)>
+    {
+        let state = PulseMeterState {
+            level: pin.get_level(),
+            last_edge: Timestamp::now(),
+            high_time_us: None,
+            low_time_us: None,
+        };
+
+        let gpio_num = T::get_pin_id() as gpio_num_t;
+        let pin = pin.attach_shared_interrupt(PinInterruptMode::AnyEdge, state, move |state| {
+            let now = Timestamp::now();
+            let level = (unsafe { gpio_get_level(gpio_num) }) != 0;
+            state.on_edge(level, now);
+        })?;
+
+        Ok(PulseMeter { pin })
+    }
+
+    /// Duration of the last complete high pulse in microseconds
+    pub fn high_time_us(&self) -> Option<u32> {
+        self.pin.with(|state| state.high_time_us)
+    }
+
+    /// Duration of the last complete low pulse in microseconds
+    pub fn low_time_us(&self) -> Option<u32> {
+        self.pin.with(|state| state.low_time_us)
+    }
+
+    /// Period of the signal in microseconds, measured as the sum of the last high and low pulses
+    pub fn period_us(&self) -> Option<u32> {
+        self.pin.with(|state| match (state.high_time_us, state.low_time_us) {
+            (Some(high), Some(low)) => Some(high.saturating_add(low)),
+            _ => None,
+        })
+    }
+
+    pub fn frequency_hz(&self) -> Option<f32> {
+        match self.period_us() {
+            Some(period) if period != 0 => Some(1_000_000.0 / period as f32),
+            _ => None,
+        }
+    }
+
+    /// Forgets measured durations, e.g. before triggering the sensor
+    pub fn clear(&mut self) {
+        self.pin.with(|state| {
+            state.high_time_us = None;
+            state.low_time_us = None;
+        });
+    }
+
+    /// Waits up to `ticks` for the complete high pulse and returns its duration
+    pub fn wait_high_time_us(&mut self, ticks: TickType_t) -> Option<u32> {
+        self.wait(ticks, |meter| meter.high_time_us())
+    }
+
+    /// Waits up to `ticks` for the complete low pulse and returns its duration
+    pub fn wait_low_time_us(&mut self, ticks: TickType_t) -> Option<u32> {
+        self.wait(ticks, |meter| meter.low_time_us())
+    }
+
+    /// Waits up to `timeout` for the complete high pulse and returns its duration
+    pub fn wait_high_time_us_timeout(&mut self, timeout: Duration) -> Option<u32> {
+        self.wait_high_time_us(duration_to_ticks(timeout))
+    }
+
+    /// Waits up to `timeout` for the complete low pulse and returns its duration
+    pub fn wait_low_time_us_timeout(&mut self, timeout: Duration) -> Option<u32> {
+        self.wait_low_time_us(duration_to_ticks(timeout))
+    }
+
+    fn wait<F>(&self, ticks: TickType_t, measurement: F) -> Option<u32>
+        where F: Fn(&Self) -> Option<u32>
+    {
+        let mut waited = 0;
+        loop {
+            if let Some(duration) = measurement(self) {
+                return Some(duration);
+            }
+            if waited >= ticks {
+                return None;
+            }
+
+            unsafe { vTaskDelay(1) };
+            waited += 1;
+        }
+    }
+
+    /// Removes interrupt handler and returns pin back
+    pub fn release(self) -> InitializedPin<T, Input<Pull>> {
+        self.pin.detach()
+    }
+}
+
+/// Edges counted by [PulseCounter](struct.PulseCounter.html)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PulseEdge {
+    Rising,
+    Falling,
+    Both,
+}
+
+impl PulseEdge {
+    fn interrupt_mode(self) -> PinInterruptMode {
+        match self {
+            PulseEdge::Rising => PinInterruptMode::PositiveEdge,
+            PulseEdge::Falling => PinInterruptMode::NegativeEdge,
+            PulseEdge::Both => PinInterruptMode::AnyEdge,
+        }
+    }
+}
+
+/// Edges counted during the window
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PulseWindow {
+    /// Number of counted edges, saturated at `u32::MAX`
+    pub edges: u32,
+    pub duration_us: u64,
+    /// Counter has reached `u32::MAX`, so some edges were not counted
+    pub overflowed: bool,
+}
+
+impl PulseWindow {
+    /// Edges per second. When both edges are counted, this is twice the frequency of the signal
+    pub fn frequency_hz(&self) -> f32 {
+        if self.duration_us == 0 {
+            return 0.0;
+        }
+
+        self.edges as f32 * 1_000_000.0 / self.duration_us as f32
+    }
+}
+
+struct PulseCounterState {
+    edges: u32,
+    overflowed: bool,
+    window_start: Timestamp,
+}
+
+/// Counts signal edges over time windows
+pub struct PulseCounter<T: GpioPin + InterruptPinMarker, Pull> {
+    pin: SharedInterruptPin<T, Input<Pull>, PulseCounterState>,
+}
+
+impl<T, Pull> PulseCounter<T, Pull> where T: GpioPin + InputPinMarker + InterruptPinMarker {
+    /// Attaches interrupt handler to the pin and starts the first window. Returns error and pin
+    /// back if handler can't be attached
+    pub fn new(pin: InitializedPin<T, Input<Pull>>, edge: PulseEdge)
+        -> Result<Self, (GpioInterruptError, InitializedPin<T, Input<Pull>>)>
+    {
+        let state = PulseCounterState {
+            edges: 0,
+            overflowed: false,
+            window_start: Timestamp::now(),
+        };
+
+        let pin = pin.attach_shared_interrupt(edge.interrupt_mode(), state, |state| {
+            match state.edges.checked_add(1) {
+                Some(edges) => state.edges = edges,
+                None => state.overflowed = true,
+            }
+        })?;
+
+        Ok(PulseCounter { pin })
+    }
+
+    /// Number of edges counted in the current window
+    pub fn edges(&self) -> u32 {
+        self.pin.with(|state| state.edges)
+    }
+
+    /// Finishes the current window and starts the next one
+    pub fn take_window(&mut self) -> PulseWindow {
+        self.pin.with(|state| {
+            let now = Timestamp::now();
+            let window = PulseWindow {
+                edges: state.edges,
+                duration_us: state.window_start.elapsed_us(&now),
+                overflowed: state.overflowed,
+            };
+
+            state.edges = 0;
+            state.overflowed = false;
+            state.window_start = now;
+            window
+        })
+    }
+
+    /// Removes interrupt handler and returns pin back
+    pub fn release(self) -> InitializedPin<T, Input<Pull>> {
+        self.pin.detach()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        gpio::{GpioHardware, Gpio4, PinInitializer},
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn input_pin(level: bool) -> InitializedPin<Gpio4, Input<Floating>> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        mock::gpio::set_input_level(4, level);
+        PinInitializer::new(gpio.gpio4.take().unwrap()).configure_as_input().init()
+    }
+
+    #[test]
+    fn meter_measures_high_and_low_pulses() {
+        let mut meter = PulseMeter::new(input_pin(false)).ok().unwrap();
+        assert_eq!(meter.high_time_us(), None);
+
+        mock::advance_time(100);
+        mock::gpio::set_input_level(4, true);
+        mock::advance_time(580);
+        mock::gpio::set_input_level(4, false);
+
+        assert_eq!(meter.wait_high_time_us(1), Some(580));
+        assert_eq!(meter.low_time_us(), Some(100));
+        assert_eq!(meter.period_us(), Some(680));
+        assert!((meter.frequency_hz().unwrap() - 1470.588).abs() < 0.01);
+    }
+
+    #[test]
+    fn meter_measures_long_intervals_with_timer() {
+        let meter = PulseMeter::new(input_pin(false)).ok().unwrap();
+
+        // CCOUNT wraps around in less than a minute
+        mock::advance_time(60_000_000);
+        mock::gpio::set_input_level(4, true);
+
+        assert_eq!(meter.low_time_us(), Some(60_000_000));
+    }
+
+    #[test]
+    fn cleared_meter_waits_for_the_next_pulse() {
+        let mut meter = PulseMeter::new(input_pin(false)).ok().unwrap();
+        mock::gpio::pulse(4, true);
+        assert!(meter.high_time_us().is_some());
+
+        meter.clear();
+
+        assert_eq!(meter.wait_high_time_us(3), None);
+        assert_eq!(meter.period_us(), None);
+    }
+
+    #[test]
+    fn counter_counts_edges_in_window() {
+        let mut counter = PulseCounter::new(input_pin(false), PulseEdge::Rising).ok().unwrap();
+        for _ in 0..50 {
+            mock::gpio::pulse(4, true);
+            mock::advance_time(20_000);
+        }
+        assert_eq!(counter.edges(), 50);
+
+        let window = counter.take_window();
+
+        assert_eq!(window, PulseWindow { edges: 50, duration_us: 1_000_000, overflowed: false });
+        assert!((window.frequency_hz() - 50.0).abs() < 0.01);
+        assert_eq!(counter.edges(), 0);
+    }
+
+    #[test]
+    fn counter_counts_both_edges() {
+        let counter = PulseCounter::new(input_pin(false), PulseEdge::Both).ok().unwrap();
+
+        mock::gpio::pulse(4, true);
+        mock::gpio::pulse(4, true);
+
+        assert_eq!(counter.edges(), 4);
+    }
+
+    #[test]
+    fn release_removes_handler() {
+        let counter = PulseCounter::new(input_pin(false), PulseEdge::Falling).ok().unwrap();
+
+        let _pin = counter.release();
+
+        assert!(!mock::gpio::has_isr_handler(4));
+        mock::gpio::pulse(4, true);
+    }
+}
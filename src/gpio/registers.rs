@@ -0,0 +1,77 @@
+//! Direct access to the GPIO registers, which are not wrapped by the SDK.
+//!
+//! Only GPIO0-GPIO15 are controlled by GPIO registers - GPIO16 is located in the RTC domain and
+//! is controlled by RTC registers
+#[cfg(not(feature = "mock"))]
+mod imp {
+    use super::RtcRegister;
+    use core::ptr::{read_volatile, write_volatile};
+
+    const GPIO_OUT_REG: *mut u32 = 0x6000_0300 as *mut u32;
+    const GPIO_IN_REG: *const u32 = 0x6000_0318 as *const u32;
+
+    pub(crate) fn read_out() -> u32 {
+        unsafe { read_volatile(GPIO_OUT_REG) }
+    }
+
+    pub(crate) fn write_out(value: u32) {
+        unsafe { write_volatile(GPIO_OUT_REG, value) }
+    }
+
+    pub(crate) fn read_in() -> u32 {
+        unsafe { read_volatile(GPIO_IN_REG) }
+    }
+
+    pub(crate) fn read_rtc(register: RtcRegister) -> u32 {
+        unsafe { read_volatile(register.address()) }
+    }
+
+    pub(crate) fn write_rtc(register: RtcRegister, value: u32) {
+        unsafe { write_volatile(register.address(), value) }
+    }
+
+    impl RtcRegister {
+        fn address(self) -> *mut u32 {
+            let address = match self {
+                RtcRegister::GpioOut => 0x6000_0768,
+                RtcRegister::GpioEnable => 0x6000_0774,
+                RtcRegister::GpioIn => 0x6000_078c,
+                RtcRegister::GpioConf => 0x6000_0790,
+                RtcRegister::PadXpdDcdcConf => 0x6000_07a0,
+            };
+            address as *mut u32
+        }
+    }
+}
+
+#[cfg(feature = "mock")]
+mod imp {
+    pub(crate) use crate::mock::gpio::{
+        read_out_reg as read_out,
+        write_out_reg as write_out,
+        read_in_reg as read_in,
+        read_rtc_reg as read_rtc,
+        write_rtc_reg as write_rtc,
+    };
+}
+
+pub(crate) use imp::*;
+
+/// RTC registers controlling GPIO16. Only bit 0 of GPIO registers is used
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum RtcRegister {
+    GpioOut,
+    GpioEnable,
+    GpioIn,
+    GpioConf,
+    /// Function of the GPIO16 pad, which is shared with the XPD_DCDC signal, and its pull down
+    PadXpdDcdcConf,
+}
+
+/// Function bits of the `PadXpdDcdcConf`
+pub(crate) const RTC_PAD_FUNCTION_MASK: u32 = 0x43;
+/// Pad is driven by the XPD_DCDC signal, which wakes the chip from the deep sleep
+pub(crate) const RTC_PAD_FUNCTION_XPD_DCDC: u32 = 0x0;
+/// Pad is controlled by the RTC GPIO registers
+pub(crate) const RTC_PAD_FUNCTION_GPIO: u32 = 0x1;
+pub(crate) const RTC_PAD_PULL_DOWN: u32 = 1 << 3;
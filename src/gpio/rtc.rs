@@ -0,0 +1,197 @@
+//! Driver of GPIO16, which is located in the RTC domain.
+//!
+//! Unlike other pins, GPIO16 is controlled by RTC registers, has a pull down resistor instead of
+//! a pull up, and doesn't support interrupts or open drain mode. Its pad is shared with the
+//! XPD_DCDC signal, which is driven low by the RTC timer at the end of the deep sleep - when
+//! GPIO16 is connected to the RST pin, it can be dedicated as the deep sleep wake line with
+//! [RtcPin::into_deep_sleep_wake](struct.RtcPin.html#method.into_deep_sleep_wake).
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut wake_line = RtcPin::new(gpio.gpio16.take().unwrap()).into_deep_sleep_wake();
+//!
+//! // Chip is reset after 10 seconds
+//! wake_line.deep_sleep(10_000_000);
+//! ```
+use core::marker::PhantomData;
+
+use crate::critical_section;
+use crate::sys::sleep::esp_deep_sleep;
+use super::{
+    mode::*,
+    registers::{self, RtcRegister},
+    Gpio16, GpioPin, InputPin, OutputPin,
+};
+
+/// Modifies bits of the RTC register selected by the `mask`
+fn modify_rtc_register(register: RtcRegister, mask: u32, value: u32) {
+    critical_section::free(|| {
+        let current = registers::read_rtc(register);
+        registers::write_rtc(register, (current & !mask) | (value & mask));
+    });
+}
+
+fn select_pad_function(function: u32) {
+    modify_rtc_register(RtcRegister::PadXpdDcdcConf, registers::RTC_PAD_FUNCTION_MASK, function);
+}
+
+fn set_pull_down(enabled: bool) {
+    let value = if enabled { registers::RTC_PAD_PULL_DOWN } else { 0 };
+    modify_rtc_register(RtcRegister::PadXpdDcdcConf, registers::RTC_PAD_PULL_DOWN, value);
+}
+
+fn set_output_enabled(enabled: bool) {
+    modify_rtc_register(RtcRegister::GpioEnable, 1, enabled as u32);
+}
+
+/// GPIO16 configured through RTC registers in the specific `Mode`
+pub struct RtcPin<Mode> {
+    _mode: PhantomData<Mode>,
+}
+
+/// GPIO16 dedicated as the deep sleep wake line. Pin is driven by the RTC timer and can't be
+/// used as GPIO until it is released
+pub struct DeepSleepWakePin {
+    _private: (),
+}
+
+impl RtcPin<Disabled> {
+    pub fn new(_pin: Gpio16) -> Self {
+        RtcPin { _mode: PhantomData }
+    }
+}
+
+impl<Mode> RtcPin<Mode> {
+    /// Connects pad to the RTC GPIO and configures its direction
+    fn into_mode<NewMode>(self, output: bool, pull_down: bool) -> RtcPin<NewMode> {
+        select_pad_function(registers::RTC_PAD_FUNCTION_GPIO);
+        modify_rtc_register(RtcRegister::GpioConf, 1, 0);
+        set_pull_down(pull_down);
+        set_output_enabled(output);
+        RtcPin { _mode: PhantomData }
+    }
+
+    pub fn into_floating_input(self) -> RtcPin<Input<Floating>> {
+        self.into_mode(false, false)
+    }
+
+    pub fn into_pull_down_input(self) -> RtcPin<Input<PullDown>> {
+        self.into_mode(false, true)
+    }
+
+    pub fn into_push_pull_output(self) -> RtcPin<Output<PushPull>> {
+        self.into_mode(true, false)
+    }
+
+    /// Connects pad to the XPD_DCDC signal, so the chip can be woken from the deep sleep.
+    /// GPIO16 has to be connected to the RST pin
+    pub fn into_deep_sleep_wake(self) -> DeepSleepWakePin {
+        set_output_enabled(false);
+        set_pull_down(false);
+        select_pad_function(registers::RTC_PAD_FUNCTION_XPD_DCDC);
+        DeepSleepWakePin { _private: () }
+    }
+
+    /// Disables pin and returns its token back, so it can be used by another driver
+    pub fn release(self) -> Gpio16 {
+        let _: RtcPin<Input<Floating>> = self.into_mode(false, false);
+        unsafe { Gpio16::steal() }
+    }
+}
+
+impl<Pull> InputPin for RtcPin<Input<Pull>> {
+    fn get_level(&self) -> bool {
+        registers::read_rtc(RtcRegister::GpioIn) & 1 != 0
+    }
+}
+
+impl RtcPin<Output<PushPull>> {
+    pub fn get_output_level(&self) -> bool {
+        registers::read_rtc(RtcRegister::GpioOut) & 1 != 0
+    }
+}
+
+impl OutputPin for RtcPin<Output<PushPull>> {
+    fn set_level(&mut self, value: bool) {
+        modify_rtc_register(RtcRegister::GpioOut, 1, value as u32);
+    }
+}
+
+impl DeepSleepWakePin {
+    /// Puts the chip into the deep sleep for `time_us` microseconds. Chip is reset when it
+    /// wakes up, so this function never returns
+    pub fn deep_sleep(&mut self, time_us: u64) -> ! {
+        unsafe { esp_deep_sleep(time_us) }
+    }
+
+    /// Connects pad back to the RTC GPIO, so it can be used by another driver
+    pub fn release(self) -> Gpio16 {
+        RtcPin::<Disabled> { _mode: PhantomData }.release()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::{
+        gpio::GpioHardware,
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn rtc_pin() -> RtcPin<Disabled> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        RtcPin::new(gpio.gpio16.take().unwrap())
+    }
+
+    #[test]
+    fn output_drives_pad_through_rtc_registers() {
+        let mut pin = rtc_pin().into_push_pull_output();
+        assert!(!mock::gpio::is_deep_sleep_wake_line());
+
+        pin.set_level(true);
+        assert!(pin.get_output_level());
+        assert!(mock::gpio::pin_state(16).level());
+
+        pin.set_level(false);
+        assert!(!pin.get_output_level());
+        assert!(!mock::gpio::pin_state(16).level());
+    }
+
+    #[test]
+    fn input_reads_pad_with_pull_down() {
+        let pin = rtc_pin().into_pull_down_input();
+        assert!(mock::gpio::pin_state(16).pull_down);
+        assert!(!pin.get_level());
+
+        mock::gpio::set_input_level(16, true);
+        assert!(pin.get_level());
+
+        let _pin = pin.into_floating_input();
+        assert!(!mock::gpio::pin_state(16).pull_down);
+    }
+
+    #[test]
+    fn wake_line_is_released_back_to_gpio() {
+        let mut wake = rtc_pin().into_pull_down_input().into_deep_sleep_wake();
+        assert!(mock::gpio::is_deep_sleep_wake_line());
+        assert!(!mock::gpio::pin_state(16).pull_down);
+
+        let result = catch_unwind(AssertUnwindSafe(|| wake.deep_sleep(1000)));
+        assert!(result.is_err());
+        assert_eq!(mock::calls_of("esp_deep_sleep")[0].args, [1000]);
+
+        let _pin = wake.release();
+        assert!(!mock::gpio::is_deep_sleep_wake_line());
+    }
+}
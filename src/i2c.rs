@@ -0,0 +1,455 @@
+//! Software (bit-banged) I2C master.
+//!
+//! ESP8266 has no I2C hardware, so the bus is driven by two GPIO pins in open drain mode. Master
+//! supports standard (100 kHz) and fast (400 kHz) modes, clock stretching, repeated start and
+//! detects missing acknowledges and lost arbitration. Actual clock frequency is lower than the
+//! nominal one because of the GPIO access overhead.
+//!
+//! Internal pull up resistors of the pins are enabled, but they are too weak for the fast mode -
+//! external pull up resistors (2.2-4.7 kOhm) are recommended.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     i2c::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut initializer = I2cInitializer::new(gpio.gpio5.take().unwrap(), gpio.gpio4.take().unwrap());
+//! initializer.set_speed(I2cSpeed::Fast);
+//! let mut i2c = initializer.initialize();
+//!
+//! // Reads WHO_AM_I register of the sensor
+//! let mut id = [0u8];
+//! i2c.write_read(0x68, &[0x75], &mut id).ok().unwrap();
+//! ```
+use core::iter::Peekable;
+
+use crate::gpio::{
+    mode::*,
+    GpioPin, InitializedPin, InputPin, OpenDrainPinMarker, OutputPin, OutputPinMarker,
+    PinInitializer, PullUpPinMarker,
+};
+use crate::sys::rom::ets_delay_us;
+
+const DEFAULT_CLOCK_STRETCH_TIMEOUT_US: u32 = 10_000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum I2cSpeed {
+    /// 100 kHz
+    Standard,
+    /// 400 kHz
+    Fast,
+}
+
+impl I2cSpeed {
+    fn half_period_us(self) -> u32 {
+        match self {
+            I2cSpeed::Standard => 5,
+            // 1.25 us is rounded down, GPIO access overhead makes up the difference
+            I2cSpeed::Fast => 1,
+        }
+    }
+}
+
+/// Part of the transfer which was not acknowledged
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum I2cNackSource {
+    Address,
+    Data,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum I2cError {
+    /// Slave didn't acknowledge the address or the data byte
+    Nack(I2cNackSource),
+    /// Another master drives SDA low while this master transmits high level
+    ArbitrationLoss,
+    /// Slave holds SCL low for longer than the clock stretch timeout
+    ClockStretchTimeout,
+}
+
+/// Part of the [transaction](struct.I2cMaster.html#method.transaction)
+pub enum I2cOperation<'a> {
+    /// Empty reads are skipped: addressed slave drives SDA until it gets the byte not
+    /// acknowledged, so at least one byte has to be read
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+pub struct I2cInitializer<Scl, Sda> {
+    scl: Scl,
+    sda: Sda,
+    speed: I2cSpeed,
+    clock_stretch_timeout_us: u32,
+}
+
+impl<Scl, Sda> I2cInitializer<Scl, Sda>
+    where Scl: GpioPin + OutputPinMarker + OpenDrainPinMarker + PullUpPinMarker,
+          Sda: GpioPin + OutputPinMarker + OpenDrainPinMarker + PullUpPinMarker,
+{
+    pub fn new(scl: Scl, sda: Sda) -> Self {
+        Self {
+            scl,
+            sda,
+            speed: I2cSpeed::Standard,
+            clock_stretch_timeout_us: DEFAULT_CLOCK_STRETCH_TIMEOUT_US,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: I2cSpeed) -> &mut Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Maximal time the slave can hold SCL low. Default is 10 ms
+    pub fn set_clock_stretch_timeout_us(&mut self, timeout_us: u32) -> &mut Self {
+        self.clock_stretch_timeout_us = timeout_us;
+        self
+    }
+
+    /// Configures pins and releases the bus
+    pub fn initialize(self) -> I2cMaster<Scl, Sda> {
+        let mut scl = PinInitializer::new(self.scl).configure_as_open_drain().enable_pull_up().init();
+        let mut sda = PinInitializer::new(self.sda).configure_as_open_drain().enable_pull_up().init();
+        scl.set_level(true);
+        sda.set_level(true);
+
+        I2cMaster {
+            scl,
+            sda,
+            half_period_us: self.speed.half_period_us(),
+            clock_stretch_timeout_us: self.clock_stretch_timeout_us,
+        }
+    }
+}
+
+pub struct I2cMaster<Scl, Sda>
+    where Scl: GpioPin + OpenDrainPinMarker, Sda: GpioPin + OpenDrainPinMarker
+{
+    scl: InitializedPin<Scl, Output<OpenDrain>>,
+    sda: InitializedPin<Sda, Output<OpenDrain>>,
+    half_period_us: u32,
+    clock_stretch_timeout_us: u32,
+}
+
+impl<Scl, Sda> I2cMaster<Scl, Sda>
+    where Scl: GpioPin + OutputPinMarker + OpenDrainPinMarker,
+          Sda: GpioPin + OutputPinMarker + OpenDrainPinMarker,
+{
+    fn delay(&self) {
+        unsafe { ets_delay_us(self.half_period_us) };
+    }
+
+    /// Releases SCL and waits until the slave stops stretching the clock
+    fn release_scl(&mut self) -> Result<(), I2cError> {
+        self.scl.set_level(true);
+
+        let mut waited_us = 0;
+        while !self.scl.get_level() {
+            if waited_us >= self.clock_stretch_timeout_us {
+                return Err(I2cError::ClockStretchTimeout);
+            }
+
+            unsafe { ets_delay_us(1) };
+            waited_us += 1;
+        }
+        Ok(())
+    }
+
+    /// Generates start condition. Bus is expected to be free or SCL is low after the previous
+    /// byte (repeated start)
+    fn start(&mut self) -> Result<(), I2cError> {
+        self.sda.set_level(true);
+        self.delay();
+        self.release_scl()?;
+        if !self.sda.get_level() {
+            return Err(I2cError::ArbitrationLoss);
+        }
+        self.delay();
+
+        self.sda.set_level(false);
+        self.delay();
+        self.scl.set_level(false);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), I2cError> {
+        self.sda.set_level(false);
+        self.delay();
+        self.release_scl()?;
+        self.delay();
+
+        self.sda.set_level(true);
+        self.delay();
+        if !self.sda.get_level() {
+            return Err(I2cError::ArbitrationLoss);
+        }
+        Ok(())
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), I2cError> {
+        self.sda.set_level(bit);
+        self.delay();
+        self.release_scl()?;
+        if bit && !self.sda.get_level() {
+            return Err(I2cError::ArbitrationLoss);
+        }
+        self.delay();
+        self.scl.set_level(false);
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool, I2cError> {
+        self.sda.set_level(true);
+        self.delay();
+        self.release_scl()?;
+        let bit = self.sda.get_level();
+        self.delay();
+        self.scl.set_level(false);
+        Ok(bit)
+    }
+
+    /// Returns `true` if the byte was acknowledged
+    fn write_byte(&mut self, byte: u8) -> Result<bool, I2cError> {
+        for bit in (0..8).rev() {
+            self.write_bit(byte & (1 << bit) != 0)?;
+        }
+        Ok(!self.read_bit()?)
+    }
+
+    fn read_byte(&mut self, ack: bool) -> Result<u8, I2cError> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = (byte << 1) | self.read_bit()? as u8;
+        }
+        self.write_bit(!ack)?;
+        Ok(byte)
+    }
+
+    fn write_address(&mut self, address: u8, read: bool) -> Result<(), I2cError> {
+        self.start()?;
+        if self.write_byte((address << 1) | read as u8)? {
+            Ok(())
+        } else {
+            Err(I2cError::Nack(I2cNackSource::Address))
+        }
+    }
+
+    fn run_operations<'b, I>(&mut self, address: u8, operations: &mut Peekable<I>) -> Result<(), I2cError>
+        where I: Iterator<Item = I2cOperation<'b>>
+    {
+        // Address is sent before the first operation and each time the direction changes
+        let mut last_was_read = None;
+
+        while let Some(operation) = operations.next() {
+            match operation {
+                I2cOperation::Write(bytes) => {
+                    if last_was_read != Some(false) {
+                        self.write_address(address, false)?;
+                    }
+                    for byte in bytes.iter() {
+                        if !self.write_byte(*byte)? {
+                            return Err(I2cError::Nack(I2cNackSource::Data));
+                        }
+                    }
+                    last_was_read = Some(false);
+                }
+                I2cOperation::Read(buffer) => {
+                    if last_was_read != Some(true) {
+                        self.write_address(address, true)?;
+                    }
+                    // Last byte before the direction change or the stop is not acknowledged
+                    let next_is_read = matches!(operations.peek(), Some(I2cOperation::Read(_)));
+                    let len = buffer.len();
+                    for (index, byte) in buffer.iter_mut().enumerate() {
+                        *byte = self.read_byte(next_is_read || index + 1 < len)?;
+                    }
+                    last_was_read = Some(true);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes `operations` as a single transaction: adjacent operations of the same direction
+    /// are joined, repeated start is generated when the direction changes and the bus is
+    /// released with the stop condition at the end
+    pub(crate) fn run_transaction<'b, I>(&mut self, address: u8, operations: I) -> Result<(), I2cError>
+        where I: Iterator<Item = I2cOperation<'b>>
+    {
+        let mut operations = operations
+            .filter(|operation| !matches!(operation, I2cOperation::Read(buffer) if buffer.is_empty()))
+            .peekable();
+        if operations.peek().is_none() {
+            return Ok(());
+        }
+
+        let result = self.run_operations(address, &mut operations);
+
+        match result {
+            // Bus belongs to another master, lines should be just released
+            Err(I2cError::ArbitrationLoss) => {
+                self.sda.set_level(true);
+                self.scl.set_level(true);
+                result
+            }
+            Err(_) => {
+                // Stop error is less relevant than the original one
+                let _ = self.stop();
+                result
+            }
+            Ok(()) => self.stop(),
+        }
+    }
+
+    pub fn transaction(&mut self, address: u8, operations: &mut [I2cOperation<'_>]) -> Result<(), I2cError> {
+        self.run_transaction(address, operations.iter_mut().map(|operation| match operation {
+            I2cOperation::Read(buffer) => I2cOperation::Read(buffer),
+            I2cOperation::Write(bytes) => I2cOperation::Write(bytes),
+        }))
+    }
+
+    pub fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), I2cError> {
+        self.run_transaction(address, core::iter::once(I2cOperation::Write(bytes)))
+    }
+
+    pub fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), I2cError> {
+        self.run_transaction(address, core::iter::once(I2cOperation::Read(buffer)))
+    }
+
+    /// Writes `bytes` and reads `buffer` after the repeated start
+    pub fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), I2cError> {
+        self.run_transaction(
+            address,
+            core::iter::once(I2cOperation::Write(bytes)).chain(core::iter::once(I2cOperation::Read(buffer))),
+        )
+    }
+
+    /// Disables pins and returns their tokens back
+    pub fn deinitialize(self) -> (Scl, Sda) {
+        (self.scl.release(), self.sda.release())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        gpio::{GpioHardware, Gpio4, Gpio5},
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    const ADDRESS: u8 = 0x68;
+
+    fn master(speed: I2cSpeed) -> I2cMaster<Gpio5, Gpio4> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let mut initializer = I2cInitializer::new(gpio.gpio5.take().unwrap(), gpio.gpio4.take().unwrap());
+        initializer.set_speed(speed);
+        let i2c = initializer.initialize();
+        mock::i2c::attach_slave(5, 4, ADDRESS);
+        i2c
+    }
+
+    #[test]
+    fn write_sets_consecutive_registers() {
+        let mut i2c = master(I2cSpeed::Standard);
+
+        i2c.write(ADDRESS, &[0x10, 0xA5, 0x3C]).unwrap();
+
+        assert_eq!(mock::i2c::register(ADDRESS, 0x10), 0xA5);
+        assert_eq!(mock::i2c::register(ADDRESS, 0x11), 0x3C);
+    }
+
+    #[test]
+    fn write_read_uses_repeated_start() {
+        let mut i2c = master(I2cSpeed::Fast);
+        mock::i2c::set_register(ADDRESS, 0x75, 0x71);
+        mock::i2c::set_register(ADDRESS, 0x76, 0x80);
+
+        let mut buffer = [0u8; 2];
+        i2c.write_read(ADDRESS, &[0x75], &mut buffer).unwrap();
+
+        assert_eq!(buffer, [0x71, 0x80]);
+    }
+
+    #[test]
+    fn transaction_joins_adjacent_reads() {
+        let mut i2c = master(I2cSpeed::Standard);
+        i2c.write(ADDRESS, &[0x20, 1, 2, 3]).unwrap();
+
+        let mut first = [0u8; 1];
+        let mut second = [0u8; 2];
+        i2c.transaction(ADDRESS, &mut [
+            I2cOperation::Write(&[0x20]),
+            I2cOperation::Read(&mut first),
+            I2cOperation::Read(&mut second),
+        ]).unwrap();
+
+        assert_eq!(first, [1]);
+        assert_eq!(second, [2, 3]);
+    }
+
+    #[test]
+    fn empty_read_is_skipped() {
+        let mut i2c = master(I2cSpeed::Standard);
+        mock::i2c::set_register(ADDRESS, 0x30, 0x5A);
+
+        let mut empty = [0u8; 0];
+        let mut buffer = [0u8; 1];
+        i2c.transaction(ADDRESS, &mut [
+            I2cOperation::Write(&[0x30]),
+            I2cOperation::Read(&mut buffer),
+            I2cOperation::Read(&mut empty),
+        ]).unwrap();
+        assert_eq!(buffer, [0x5A]);
+
+        mock::clear_calls();
+        i2c.read(ADDRESS, &mut empty).unwrap();
+        assert!(mock::calls().is_empty());
+
+        // Bus is released properly, so the next transfer succeeds
+        i2c.write(ADDRESS, &[0x31, 0x42]).unwrap();
+        assert_eq!(mock::i2c::register(ADDRESS, 0x31), 0x42);
+    }
+
+    #[test]
+    fn missing_slave_is_not_acknowledged() {
+        let mut i2c = master(I2cSpeed::Fast);
+
+        assert_eq!(i2c.write(0x50, &[1]), Err(I2cError::Nack(I2cNackSource::Address)));
+
+        i2c.write(ADDRESS, &[0x00, 0x42]).unwrap();
+        assert_eq!(mock::i2c::register(ADDRESS, 0x00), 0x42);
+    }
+
+    #[test]
+    fn clock_stretching_is_waited_out_until_timeout() {
+        let mut i2c = master(I2cSpeed::Standard);
+
+        mock::i2c::set_clock_stretch(ADDRESS, 500);
+        let started = mock::time_us();
+        i2c.write(ADDRESS, &[0x01, 0x02]).unwrap();
+        assert!(mock::time_us() - started >= 1000);
+        assert_eq!(mock::i2c::register(ADDRESS, 0x01), 0x02);
+
+        mock::i2c::set_clock_stretch(ADDRESS, 50_000);
+        assert_eq!(i2c.write(ADDRESS, &[0x01, 0x02]), Err(I2cError::ClockStretchTimeout));
+    }
+
+    #[test]
+    fn sda_held_low_loses_arbitration() {
+        let mut i2c = master(I2cSpeed::Standard);
+
+        mock::gpio::set_input_level(4, false);
+        assert_eq!(i2c.write(ADDRESS, &[0x00]), Err(I2cError::ArbitrationLoss));
+
+        mock::gpio::release_input(4);
+        i2c.write(ADDRESS, &[0x00, 0x42]).unwrap();
+    }
+}
@@ -0,0 +1,618 @@
+//! RGB and RGBW LED driver.
+//!
+//! [RgbLed](struct.RgbLed.html) drives the LED connected to three or four
+//! [pwm](../pwm/index.html) channels. Common anode LEDs are driven by the inverted channels, so
+//! the color and brightness have the same meaning for both polarities.
+//!
+//! Perceived brightness of the LED is not proportional to the duty, so the levels are corrected
+//! with the [LedGamma](enum.LedGamma.html) table. Colors can be changed instantly, faded with the
+//! [fade](../pwm/struct.Fade.html) engine or animated with the [LedEffect](enum.LedEffect.html),
+//! which runs in the timer task.
+//!
+//! # Examples
+//! ```no_run
+//! # use core::time::Duration;
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     led::*,
+//! #     pwm::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut pwm = PwmInitializer::new()
+//!     .add_channel(gpio.gpio12.take().unwrap(), 0)
+//!     .add_channel(gpio.gpio13.take().unwrap(), 0)
+//!     .add_channel(gpio.gpio14.take().unwrap(), 0)
+//!     .set_frequency(1000)
+//!     .ok().unwrap()
+//!     .initialize()
+//!     .ok().unwrap();
+//! pwm.start();
+//! let (red, green, blue) = pwm.channels();
+//!
+//! let mut led = RgbLed::new(red, green, blue, LedPolarity::CommonAnode);
+//! led.set_brightness(128).set_hsv(200, 255, 255);
+//!
+//! led.fade_to(Rgb::RED, Duration::from_millis(500)).ok().unwrap().wait();
+//!
+//! let effect = led.start_effect(LedEffect::Breathe {
+//!     color: Rgb::GREEN,
+//!     period: Duration::from_secs(3),
+//! }).ok().unwrap();
+//! // ...
+//! effect.stop();
+//! ```
+use core::{
+    marker::PhantomData,
+    time::Duration,
+};
+
+use crate::{
+    pwm::{Duty, Fade, FadeError, FadeInitializer, PwmChannel},
+    sys::pwm::*,
+    time::Instant,
+    timer::{Timer, TimerError},
+};
+
+/// Interval between color updates of the effects
+const EFFECT_STEP: Duration = Duration::from_millis(20);
+
+/// Table of 16-bit duty fractions for the 8-bit levels
+pub type GammaTable = [u16; 256];
+
+/// Gamma 2.2 correction, which matches the perceived brightness of the most LEDs
+pub static GAMMA_2_2: GammaTable = [
+    0, 0, 2, 4, 7, 11, 17, 24, 32, 42, 53, 65,
+    79, 94, 111, 129, 148, 169, 192, 216, 242, 270, 299, 330,
+    362, 396, 432, 469, 508, 549, 591, 635, 681, 729, 779, 830,
+    883, 938, 995, 1053, 1113, 1175, 1239, 1305, 1373, 1443, 1514, 1587,
+    1663, 1740, 1819, 1900, 1983, 2068, 2155, 2243, 2334, 2427, 2521, 2618,
+    2717, 2817, 2920, 3024, 3131, 3240, 3350, 3463, 3578, 3694, 3813, 3934,
+    4057, 4182, 4309, 4438, 4570, 4703, 4838, 4976, 5115, 5257, 5401, 5547,
+    5695, 5845, 5998, 6152, 6309, 6468, 6629, 6792, 6957, 7124, 7294, 7466,
+    7640, 7816, 7994, 8175, 8358, 8543, 8730, 8919, 9111, 9305, 9501, 9699,
+    9900, 10102, 10307, 10515, 10724, 10936, 11150, 11366, 11585, 11806, 12029, 12254,
+    12482, 12712, 12944, 13179, 13416, 13655, 13896, 14140, 14386, 14635, 14885, 15138,
+    15394, 15652, 15912, 16174, 16439, 16706, 16975, 17247, 17521, 17798, 18077, 18358,
+    18642, 18928, 19216, 19507, 19800, 20095, 20393, 20694, 20996, 21301, 21609, 21919,
+    22231, 22546, 22863, 23182, 23504, 23829, 24156, 24485, 24817, 25151, 25487, 25826,
+    26168, 26512, 26858, 27207, 27558, 27912, 28268, 28627, 28988, 29351, 29717, 30086,
+    30457, 30830, 31206, 31585, 31966, 32349, 32735, 33124, 33514, 33908, 34304, 34702,
+    35103, 35507, 35913, 36321, 36732, 37146, 37562, 37981, 38402, 38825, 39252, 39680,
+    40112, 40546, 40982, 41421, 41862, 42306, 42753, 43202, 43654, 44108, 44565, 45025,
+    45487, 45951, 46418, 46888, 47360, 47835, 48313, 48793, 49275, 49761, 50249, 50739,
+    51232, 51728, 52226, 52727, 53230, 53736, 54245, 54756, 55270, 55787, 56306, 56828,
+    57352, 57879, 58409, 58941, 59476, 60014, 60554, 61097, 61642, 62190, 62741, 63295,
+    63851, 64410, 64971, 65535,
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LedPolarity {
+    /// LED is connected between the pin and the ground, high level turns it on
+    CommonCathode,
+    /// LED is connected between the supply and the pin, low level turns it on
+    CommonAnode,
+}
+
+/// Mapping of the color levels to the duties
+#[derive(Copy, Clone)]
+pub enum LedGamma {
+    /// Duty is proportional to the level
+    Linear,
+    /// [GAMMA_2_2](static.GAMMA_2_2.html) correction
+    Standard,
+    /// Custom table, e.g. to compensate different efficiency of the colors
+    Custom(&'static GammaTable),
+}
+
+impl LedGamma {
+    /// Duty fraction of the `level` in 0..=65535 range
+    fn correct(self, level: u8) -> u16 {
+        match self {
+            LedGamma::Linear => level as u16 * 257,
+            LedGamma::Standard => GAMMA_2_2[level as usize],
+            LedGamma::Custom(table) => table[level as usize],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LedError {
+    /// Durations of the effect should not be zero
+    InvalidDuration,
+    /// Software timer, which drives the effect, can't be started
+    TimerFailed(TimerError),
+    /// Fade can't be started
+    Fade(FadeError),
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+    pub const RED: Rgb = Rgb::new(255, 0, 0);
+    pub const GREEN: Rgb = Rgb::new(0, 255, 0);
+    pub const BLUE: Rgb = Rgb::new(0, 0, 255);
+
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    /// Color of the `hue` in degrees (wrapped to 0..360), `saturation` and `value`
+    pub fn from_hsv(hue: u16, saturation: u8, value: u8) -> Self {
+        let hue = (hue % 360) as u32;
+        let (saturation, value) = (saturation as u32, value as u32);
+
+        let sector = hue / 60;
+        let remainder = (hue % 60) * 255 / 60;
+
+        let p = (value * (255 - saturation) / 255) as u8;
+        let q = (value * (255 - saturation * remainder / 255) / 255) as u8;
+        let t = (value * (255 - saturation * (255 - remainder) / 255) / 255) as u8;
+        let v = value as u8;
+
+        match sector {
+            0 => Rgb::new(v, t, p),
+            1 => Rgb::new(q, v, p),
+            2 => Rgb::new(p, v, t),
+            3 => Rgb::new(p, q, v),
+            4 => Rgb::new(t, p, v),
+            _ => Rgb::new(v, p, q),
+        }
+    }
+
+    /// Color with the levels scaled by `level / 255`
+    pub fn scale(self, level: u8) -> Self {
+        let scale = |component: u8| ((component as u32 * level as u32 + 127) / 255) as u8;
+        Rgb::new(scale(self.red), scale(self.green), scale(self.blue))
+    }
+}
+
+/// Animation, which runs in the timer task until stopped. White channel is off during the effects
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LedEffect {
+    /// LED is on for `on` and off for `off`
+    Blink { color: Rgb, on: Duration, off: Duration },
+    /// LED smoothly brightens and dims once per `period`
+    Breathe { color: Rgb, period: Duration },
+    /// Hue goes around the color wheel once per `period`
+    ColorCycle { period: Duration, saturation: u8, value: u8 },
+}
+
+impl LedEffect {
+    fn cycle(&self) -> Duration {
+        match *self {
+            LedEffect::Blink { on, off, .. } => on + off,
+            LedEffect::Breathe { period, .. } | LedEffect::ColorCycle { period, .. } => period,
+        }
+    }
+
+    /// Color at the `time_us` since the start of the cycle, which lasts `cycle_us`
+    fn color_at(&self, time_us: u64, cycle_us: u64) -> Rgb {
+        match *self {
+            LedEffect::Blink { color, on, .. } => {
+                if (time_us as u128) < on.as_micros() { color } else { Rgb::BLACK }
+            }
+            LedEffect::Breathe { color, .. } => {
+                let phase = time_us * 512 / cycle_us;
+                let level = if phase < 256 { phase } else { 511 - phase };
+                color.scale(level.min(255) as u8)
+            }
+            LedEffect::ColorCycle { saturation, value, .. } => {
+                Rgb::from_hsv((time_us * 360 / cycle_us) as u16, saturation, value)
+            }
+        }
+    }
+}
+
+/// Channels and correction, which convert the colors to the duties
+#[derive(Copy, Clone)]
+struct LedOutput {
+    channels: [u8; 3],
+    white_channel: Option<u8>,
+    period: u32,
+    brightness: u8,
+    gamma: LedGamma,
+    /// Pwm is running, so the updated duties are applied with `pwm_start`
+    running: bool,
+}
+
+impl LedOutput {
+    fn duty(&self, level: u8) -> u32 {
+        let level = ((level as u32 * self.brightness as u32 + 127) / 255) as u8;
+        let fraction = self.gamma.correct(level) as u64;
+        ((fraction * self.period as u64 + 32767) / 65535) as u32
+    }
+
+    fn apply(&self, color: Rgb, white: u8) {
+        let levels = [color.red, color.green, color.blue];
+        unsafe {
+            for (channel, level) in self.channels.iter().zip(levels.iter()) {
+                pwm_set_duty(*channel, self.duty(*level));
+            }
+            if let Some(channel) = self.white_channel {
+                pwm_set_duty(channel, self.duty(white));
+            }
+            if self.running {
+                pwm_start();
+            }
+        }
+    }
+}
+
+/// LED connected to the red, green, blue and optional white channels on the `R`, `G`, `B` and
+/// `W` pins
+pub struct RgbLed<'a, R, G, B, W = ()> {
+    red: PwmChannel<'a, R>,
+    green: PwmChannel<'a, G>,
+    blue: PwmChannel<'a, B>,
+    white: Option<PwmChannel<'a, W>>,
+    color: Rgb,
+    white_level: u8,
+    brightness: u8,
+    gamma: LedGamma,
+}
+
+impl<'a, R, G, B> RgbLed<'a, R, G, B> {
+    /// Creates RGB LED, which is initially off
+    pub fn new(
+        red: PwmChannel<'a, R>,
+        green: PwmChannel<'a, G>,
+        blue: PwmChannel<'a, B>,
+        polarity: LedPolarity,
+    ) -> Self {
+        RgbLed::build(red, green, blue, None, polarity)
+    }
+}
+
+impl<'a, R, G, B, W> RgbLed<'a, R, G, B, W> {
+    /// Creates RGBW LED, which is initially off
+    pub fn with_white(
+        red: PwmChannel<'a, R>,
+        green: PwmChannel<'a, G>,
+        blue: PwmChannel<'a, B>,
+        white: PwmChannel<'a, W>,
+        polarity: LedPolarity,
+    ) -> Self {
+        Self::build(red, green, blue, Some(white), polarity)
+    }
+
+    fn build(
+        mut red: PwmChannel<'a, R>,
+        mut green: PwmChannel<'a, G>,
+        mut blue: PwmChannel<'a, B>,
+        mut white: Option<PwmChannel<'a, W>>,
+        polarity: LedPolarity,
+    ) -> Self {
+        let is_inverted = polarity == LedPolarity::CommonAnode;
+        red.set_inverted(is_inverted);
+        green.set_inverted(is_inverted);
+        blue.set_inverted(is_inverted);
+        if let Some(white) = white.as_mut() {
+            white.set_inverted(is_inverted);
+        }
+
+        let mut led = Self {
+            red,
+            green,
+            blue,
+            white,
+            color: Rgb::BLACK,
+            white_level: 0,
+            brightness: 255,
+            gamma: LedGamma::Standard,
+        };
+        led.update();
+        led
+    }
+
+    fn output(&self) -> LedOutput {
+        LedOutput {
+            channels: [self.red.index(), self.green.index(), self.blue.index()],
+            white_channel: self.white.as_ref().map(|white| white.index()),
+            period: self.red.period(),
+            brightness: self.brightness,
+            gamma: self.gamma,
+            running: self.red.is_running(),
+        }
+    }
+
+    fn update(&mut self) {
+        self.output().apply(self.color, self.white_level);
+    }
+
+    /// Last set color. After [fade_to](#method.fade_to) it's the target of the fade, even if the
+    /// fade is stopped before it completes
+    pub fn color(&self) -> Rgb {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: Rgb) -> &mut Self {
+        self.color = color;
+        self.update();
+        self
+    }
+
+    /// Sets color of the `hue` in degrees, `saturation` and `value`
+    pub fn set_hsv(&mut self, hue: u16, saturation: u8, value: u8) -> &mut Self {
+        self.set_color(Rgb::from_hsv(hue, saturation, value))
+    }
+
+    /// Level of the white channel. Ignored if LED has no white channel
+    pub fn set_white(&mut self, level: u8) -> &mut Self {
+        self.white_level = level;
+        self.update();
+        self
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Scales all levels by `brightness / 255`. Default is 255
+    pub fn set_brightness(&mut self, brightness: u8) -> &mut Self {
+        self.brightness = brightness;
+        self.update();
+        self
+    }
+
+    /// Default is `LedGamma::Standard`
+    pub fn set_gamma(&mut self, gamma: LedGamma) -> &mut Self {
+        self.gamma = gamma;
+        self.update();
+        self
+    }
+
+    /// Turns off all channels, keeping brightness and gamma
+    pub fn off(&mut self) -> &mut Self {
+        self.color = Rgb::BLACK;
+        self.white_level = 0;
+        self.update();
+        self
+    }
+
+    /// Starts fading to the `color` over the `duration`. LED is borrowed by the returned fade
+    /// until it completes or is interrupted. [color](#method.color) is set to the target
+    /// immediately, so a stopped fade leaves the LED at an intermediate color, which isn't tracked
+    pub fn fade_to(&mut self, color: Rgb, duration: Duration) -> Result<Fade<'_>, LedError> {
+        let output = self.output();
+        let target = |level: u8| {
+            Duty::from_ratio(output.duty(level), output.period).unwrap_or(Duty::FULL)
+        };
+
+        let mut fade = FadeInitializer::new()
+            .add_channel(&mut self.red, target(color.red))
+            .add_channel(&mut self.green, target(color.green))
+            .add_channel(&mut self.blue, target(color.blue));
+        if let Some(white) = self.white.as_mut() {
+            fade = fade.add_channel(white, target(self.white_level));
+        }
+
+        let fade = fade.start(duration).map_err(|(err, _)| LedError::Fade(err))?;
+        // Color is kept if the fade can't be started
+        self.color = color;
+        Ok(fade)
+    }
+
+    /// Starts the `effect`, which runs until the returned handle is dropped. LED keeps the last
+    /// color of the effect, until the color is set again
+    pub fn start_effect(&mut self, effect: LedEffect) -> Result<RunningEffect<'_>, LedError> {
+        if let LedEffect::Blink { on, off, .. } = effect {
+            if on == Duration::ZERO || off == Duration::ZERO {
+                return Err(LedError::InvalidDuration);
+            }
+        }
+
+        let cycle_us = effect.cycle().as_micros().min(u64::MAX as u128) as u64;
+        if cycle_us == 0 {
+            return Err(LedError::InvalidDuration);
+        }
+
+        let output = self.output();
+        let started = Instant::now();
+
+        let timer = Timer::periodic(EFFECT_STEP, move || {
+            let time_us = started.elapsed().as_micros() as u64 % cycle_us;
+            output.apply(effect.color_at(time_us, cycle_us), 0);
+        });
+
+        match timer {
+            Ok(timer) => {
+                output.apply(effect.color_at(0, cycle_us), 0);
+                Ok(RunningEffect { _timer: timer, _led: PhantomData })
+            }
+            Err(err) => Err(LedError::TimerFailed(err)),
+        }
+    }
+
+    /// Turns LED off and returns the channels back
+    pub fn release(mut self)
+        -> (PwmChannel<'a, R>, PwmChannel<'a, G>, PwmChannel<'a, B>, Option<PwmChannel<'a, W>>)
+    {
+        self.off();
+        (self.red, self.green, self.blue, self.white)
+    }
+}
+
+/// Handle of the running [LedEffect](enum.LedEffect.html). Effect is stopped when the handle is
+/// dropped or [stopped](#method.stop)
+pub struct RunningEffect<'a> {
+    _timer: Timer,
+    _led: PhantomData<&'a mut ()>,
+}
+
+impl<'a> RunningEffect<'a> {
+    pub fn stop(self) {}
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        gpio::{GpioHardware, Gpio4, Gpio12, Gpio13, Gpio14},
+        mock,
+        peripherals::GpioPeripherals,
+        pwm::{Pwm, PwmInitializer},
+    };
+    use super::*;
+
+    fn pwm() -> Pwm<(Gpio12, Gpio13, Gpio14, Gpio4)> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        PwmInitializer::new()
+            .add_channel(gpio.gpio12.take().unwrap(), 0)
+            .add_channel(gpio.gpio13.take().unwrap(), 0)
+            .add_channel(gpio.gpio14.take().unwrap(), 0)
+            .add_channel(gpio.gpio4.take().unwrap(), 0)
+            .set_period(1000)
+            .ok().unwrap()
+            .initialize()
+            .ok().unwrap()
+    }
+
+    fn duties() -> [u32; 4] {
+        let duties = mock::pwm::state().duties;
+        [duties[0], duties[1], duties[2], duties[3]]
+    }
+
+    #[test]
+    fn hsv_is_converted_to_rgb() {
+        assert_eq!(Rgb::from_hsv(0, 255, 255), Rgb::RED);
+        assert_eq!(Rgb::from_hsv(120, 255, 255), Rgb::GREEN);
+        assert_eq!(Rgb::from_hsv(240, 255, 255), Rgb::BLUE);
+        assert_eq!(Rgb::from_hsv(360, 255, 255), Rgb::RED);
+        assert_eq!(Rgb::from_hsv(60, 255, 255), Rgb::new(255, 255, 0));
+        assert_eq!(Rgb::from_hsv(300, 255, 255), Rgb::new(255, 0, 255));
+        assert_eq!(Rgb::from_hsv(200, 0, 255), Rgb::WHITE);
+        assert_eq!(Rgb::from_hsv(200, 255, 0), Rgb::BLACK);
+    }
+
+    #[test]
+    fn colors_are_scaled() {
+        assert_eq!(Rgb::new(255, 128, 0).scale(128), Rgb::new(128, 64, 0));
+        assert_eq!(Rgb::WHITE.scale(255), Rgb::WHITE);
+        assert_eq!(Rgb::WHITE.scale(0), Rgb::BLACK);
+    }
+
+    #[test]
+    fn levels_are_corrected_and_scaled_by_brightness() {
+        let mut pwm = pwm();
+        let (red, green, blue, white) = pwm.channels();
+        let mut led = RgbLed::with_white(red, green, blue, white, LedPolarity::CommonAnode);
+        assert_eq!(mock::pwm::state().inverted_mask, 0b1111);
+        assert_eq!(duties(), [0; 4]);
+
+        led.set_gamma(LedGamma::Linear).set_color(Rgb::new(255, 128, 0)).set_white(255);
+        assert_eq!(duties(), [1000, 502, 0, 1000]);
+
+        led.set_brightness(128);
+        assert_eq!(duties(), [502, 251, 0, 502]);
+
+        led.set_brightness(255).set_gamma(LedGamma::Standard);
+        assert_eq!(duties(), [1000, 220, 0, 1000]);
+
+        static HALF: GammaTable = [32768; 256];
+        led.set_gamma(LedGamma::Custom(&HALF));
+        assert_eq!(duties(), [500, 500, 500, 500]);
+        led.set_gamma(LedGamma::Linear);
+
+        let (_, _, _, white) = led.release();
+        assert!(white.is_some());
+        assert_eq!(duties(), [0; 4]);
+    }
+
+    #[test]
+    fn fade_sets_target_color() {
+        let mut pwm = pwm();
+        let (red, green, blue, _) = pwm.channels();
+        let mut led = RgbLed::new(red, green, blue, LedPolarity::CommonCathode);
+        led.set_gamma(LedGamma::Linear);
+
+        led.fade_to(Rgb::BLUE, Duration::from_millis(100)).ok().unwrap().wait();
+        assert_eq!(duties()[..3], [0, 0, 1000]);
+        assert_eq!(led.color(), Rgb::BLUE);
+
+        let fade = led.fade_to(Rgb::RED, Duration::from_millis(100)).ok().unwrap();
+        mock::esp_timer::advance(50_000);
+        fade.stop();
+        assert_eq!(duties()[..3], [500, 0, 500]);
+        assert_eq!(led.color(), Rgb::RED);
+
+        mock::inject_error("esp_timer_create", -1);
+        assert!(matches!(led.fade_to(Rgb::GREEN, Duration::from_secs(1)), Err(LedError::Fade(_))));
+        assert_eq!(led.color(), Rgb::RED);
+    }
+
+    #[test]
+    fn blink_switches_color() {
+        let mut pwm = pwm();
+        let (red, green, blue, _) = pwm.channels();
+        let mut led = RgbLed::new(red, green, blue, LedPolarity::CommonCathode);
+        led.set_gamma(LedGamma::Linear);
+
+        let effect = led.start_effect(LedEffect::Blink {
+            color: Rgb::RED,
+            on: Duration::from_millis(100),
+            off: Duration::from_millis(100),
+        }).ok().unwrap();
+        assert_eq!(duties()[0], 1000);
+        mock::esp_timer::advance(120_000);
+        assert_eq!(duties()[0], 0);
+        mock::esp_timer::advance(100_000);
+        assert_eq!(duties()[0], 1000);
+
+        effect.stop();
+        mock::esp_timer::advance(0);
+        assert_eq!(mock::esp_timer::timer_count(), 0);
+    }
+
+    #[test]
+    fn breathe_and_color_cycle_follow_period() {
+        let mut pwm = pwm();
+        let (red, green, blue, _) = pwm.channels();
+        let mut led = RgbLed::new(red, green, blue, LedPolarity::CommonCathode);
+        led.set_gamma(LedGamma::Linear);
+
+        let effect = led.start_effect(LedEffect::Breathe {
+            color: Rgb::WHITE,
+            period: Duration::from_secs(1),
+        }).ok().unwrap();
+        assert_eq!(duties()[..3], [0, 0, 0]);
+        mock::esp_timer::advance(500_000);
+        assert_eq!(duties()[..3], [1000, 1000, 1000]);
+        mock::esp_timer::advance(500_000);
+        assert_eq!(duties()[..3], [0, 0, 0]);
+        effect.stop();
+
+        let effect = led.start_effect(LedEffect::ColorCycle {
+            period: Duration::from_millis(1200),
+            saturation: 255,
+            value: 255,
+        }).ok().unwrap();
+        assert_eq!(duties()[..3], [1000, 0, 0]);
+        mock::esp_timer::advance(400_000);
+        assert_eq!(duties()[..3], [0, 1000, 0]);
+        mock::esp_timer::advance(400_000);
+        assert_eq!(duties()[..3], [0, 0, 1000]);
+        effect.stop();
+    }
+
+    #[test]
+    fn zero_durations_are_rejected() {
+        let mut pwm = pwm();
+        let (red, green, blue, _) = pwm.channels();
+        let mut led = RgbLed::new(red, green, blue, LedPolarity::CommonCathode);
+
+        let blink = LedEffect::Blink { color: Rgb::RED, on: Duration::ZERO, off: Duration::from_secs(1) };
+        assert!(matches!(led.start_effect(blink), Err(LedError::InvalidDuration)));
+        let cycle = LedEffect::ColorCycle { period: Duration::ZERO, saturation: 255, value: 255 };
+        assert!(matches!(led.start_effect(cycle), Err(LedError::InvalidDuration)));
+    }
+}
@@ -20,11 +20,36 @@
 
 extern crate alloc;
 
+#[cfg(feature = "mock")]
+extern crate std;
+
+#[cfg(not(any(feature = "idf-sys", feature = "mock")))]
+compile_error!("Either `idf-sys` or `mock` feature should be enabled");
+
+#[cfg(not(feature = "mock"))]
+mod sys;
+#[cfg(feature = "mock")]
+pub(crate) use mock as sys;
+
 pub mod wifi;
 pub mod peripherals;
 pub mod gpio;
 pub mod pwm;
+pub mod servo;
+pub mod led;
 pub mod uart;
+pub mod i2c;
+pub mod spi;
+pub mod adc;
+pub mod timer;
 pub mod watchdog;
 pub mod nvs;
-pub mod system_event;
\ No newline at end of file
+pub mod system_event;
+pub mod delay;
+pub mod time;
+#[cfg(feature = "mock")]
+pub mod mock;
+
+mod critical_section;
+#[cfg(feature = "embedded-hal")]
+mod embedded_hal_impls;
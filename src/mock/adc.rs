@@ -0,0 +1,136 @@
+//! Simulated ADC.
+//!
+//! TOUT pin has a constant level set with [set_tout](fn.set_tout.html). Samples queued with
+//! [push_tout_samples](fn.push_tout_samples.html) are returned first, which allows to simulate
+//! noise. VDD33 is measured in millivolts.
+use std::collections::VecDeque;
+
+use super::{
+    record, with_chip,
+    error::*,
+    ffi::*,
+};
+
+pub type adc_mode_t = xtensa_uint;
+pub const adc_mode_t_ADC_READ_TOUT_MODE: adc_mode_t = 0;
+pub const adc_mode_t_ADC_READ_VDD_MODE: adc_mode_t = 1;
+pub const adc_mode_t_ADC_READ_MAX_MODE: adc_mode_t = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct adc_config_t {
+    pub mode: adc_mode_t,
+    pub clk_div: u8,
+}
+
+const MAX_RAW_VALUE: u16 = 1023;
+const DEFAULT_VDD33_MV: u16 = 3300;
+
+pub(crate) struct AdcState {
+    config: Option<adc_config_t>,
+    tout: u16,
+    tout_samples: VecDeque<u16>,
+    vdd33_mv: u16,
+}
+
+impl Default for AdcState {
+    fn default() -> Self {
+        Self {
+            config: None,
+            tout: 0,
+            tout_samples: VecDeque::new(),
+            vdd33_mv: DEFAULT_VDD33_MV,
+        }
+    }
+}
+
+impl AdcState {
+    fn sample(&mut self, mode: adc_mode_t) -> u16 {
+        if mode == adc_mode_t_ADC_READ_VDD_MODE {
+            self.vdd33_mv
+        } else {
+            self.tout_samples.pop_front().unwrap_or(self.tout)
+        }
+    }
+}
+
+pub unsafe fn adc_init(config: *mut adc_config_t) -> esp_err_t {
+    let config = *config;
+
+    let err = record("adc_init", &[config.mode as i64, config.clk_div as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if config.mode >= adc_mode_t_ADC_READ_MAX_MODE || config.clk_div < 8 || config.clk_div > 32 {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| chip.adc.config = Some(config));
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn adc_deinit() -> esp_err_t {
+    let err = record("adc_deinit", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.adc.config = None);
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn adc_read(data: *mut u16) -> esp_err_t {
+    let err = record("adc_read", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| match chip.adc.config {
+        Some(config) => {
+            *data = chip.adc.sample(config.mode);
+            esp_err_t_ESP_OK
+        }
+        None => esp_err_t_ESP_ERR_INVALID_STATE,
+    })
+}
+
+pub unsafe fn adc_read_fast(data: *mut u16, len: u16) -> esp_err_t {
+    let err = record("adc_read_fast", &[len as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let data = core::slice::from_raw_parts_mut(data, len as usize);
+    with_chip(|chip| match chip.adc.config {
+        Some(config) if config.mode == adc_mode_t_ADC_READ_TOUT_MODE => {
+            for sample in data.iter_mut() {
+                *sample = chip.adc.sample(config.mode);
+            }
+            esp_err_t_ESP_OK
+        }
+        _ => esp_err_t_ESP_ERR_INVALID_STATE,
+    })
+}
+
+/// Sets raw level of the TOUT pin, which is returned when no queued samples are left
+pub fn set_tout(raw: u16) {
+    assert!(raw <= MAX_RAW_VALUE, "ADC value is out of range");
+    with_chip(|chip| chip.adc.tout = raw);
+}
+
+/// Queues raw TOUT samples, which are returned by the following reads
+pub fn push_tout_samples(samples: &[u16]) {
+    assert!(samples.iter().all(|sample| *sample <= MAX_RAW_VALUE), "ADC value is out of range");
+    with_chip(|chip| chip.adc.tout_samples.extend(samples.iter()));
+}
+
+/// Sets supply voltage, which is 3300 mV by default
+pub fn set_vdd33_mv(mv: u16) {
+    with_chip(|chip| chip.adc.vdd33_mv = mv);
+}
+
+/// Returns configuration of the initialized ADC
+pub fn config() -> Option<adc_config_t> {
+    with_chip(|chip| chip.adc.config)
+}
@@ -0,0 +1,31 @@
+use super::ffi::xtensa_int;
+
+pub type esp_err_t = xtensa_int;
+
+pub const esp_err_t_ESP_OK: esp_err_t = 0;
+pub const esp_err_t_ESP_FAIL: esp_err_t = -1;
+
+pub const esp_err_t_ESP_ERR_NO_MEM: esp_err_t = 0x101;
+pub const esp_err_t_ESP_ERR_INVALID_ARG: esp_err_t = 0x102;
+pub const esp_err_t_ESP_ERR_INVALID_STATE: esp_err_t = 0x103;
+pub const esp_err_t_ESP_ERR_INVALID_SIZE: esp_err_t = 0x104;
+pub const esp_err_t_ESP_ERR_NOT_FOUND: esp_err_t = 0x105;
+pub const esp_err_t_ESP_ERR_NOT_SUPPORTED: esp_err_t = 0x106;
+pub const esp_err_t_ESP_ERR_TIMEOUT: esp_err_t = 0x107;
+
+pub const esp_err_t_ESP_ERR_NVS_BASE: esp_err_t = 0x1100;
+pub const esp_err_t_ESP_ERR_NVS_NOT_INITIALIZED: esp_err_t = esp_err_t_ESP_ERR_NVS_BASE + 0x01;
+pub const esp_err_t_ESP_ERR_NVS_NOT_FOUND: esp_err_t = esp_err_t_ESP_ERR_NVS_BASE + 0x02;
+pub const esp_err_t_ESP_ERR_NVS_NO_FREE_PAGES: esp_err_t = esp_err_t_ESP_ERR_NVS_BASE + 0x0d;
+
+pub const esp_err_t_ESP_ERR_WIFI_BASE: esp_err_t = 0x3000;
+pub const esp_err_t_ESP_ERR_WIFI_NOT_INIT: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 1;
+pub const esp_err_t_ESP_ERR_WIFI_NOT_STARTED: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 2;
+pub const esp_err_t_ESP_ERR_WIFI_NOT_STOPPED: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 3;
+pub const esp_err_t_ESP_ERR_WIFI_IF: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 4;
+pub const esp_err_t_ESP_ERR_WIFI_MODE: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 5;
+pub const esp_err_t_ESP_ERR_WIFI_STATE: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 6;
+pub const esp_err_t_ESP_ERR_WIFI_CONN: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 7;
+pub const esp_err_t_ESP_ERR_WIFI_NVS: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 8;
+pub const esp_err_t_ESP_ERR_WIFI_SSID: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 10;
+pub const esp_err_t_ESP_ERR_WIFI_PASSWORD: esp_err_t = esp_err_t_ESP_ERR_WIFI_BASE + 11;
@@ -0,0 +1,235 @@
+//! Simulated esp_timer.
+//!
+//! Timers follow the simulated clock: callbacks of the expired timers are called by
+//! [advance](fn.advance.html) and `vTaskDelay`, which model the timer task preempting the delayed
+//! task. Busy-wait delays and [advance_time](../fn.advance_time.html) don't call callbacks.
+//!
+//! Like the FreeRTOS timer task, which runs the expired timers before it processes the queued
+//! commands, the timer task calls the callback of the expired timer even if the timer is stopped
+//! or deleted by another task before the callback is called.
+//!
+//! Timer API sends commands to the timer task, so it can't be called in the critical sections and
+//! interrupt handlers; such calls panic.
+use std::vec::Vec;
+
+use super::{
+    freertos, record, time_us, with_chip,
+    error::*,
+    ffi::*,
+};
+
+#[repr(C)]
+pub struct esp_timer {
+    _unused: [u8; 0],
+}
+
+pub type esp_timer_handle_t = *mut esp_timer;
+pub type esp_timer_cb_t = Option<unsafe extern "C" fn(arg: *mut xtensa_void)>;
+
+pub type esp_timer_dispatch_t = xtensa_uint;
+pub const esp_timer_dispatch_t_ESP_TIMER_TASK: esp_timer_dispatch_t = 0;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct esp_timer_create_args_t {
+    pub callback: esp_timer_cb_t,
+    pub arg: *mut xtensa_void,
+    pub dispatch_method: esp_timer_dispatch_t,
+    pub name: *const xtensa_char,
+}
+
+struct SimulatedTimer {
+    callback: unsafe extern "C" fn(arg: *mut xtensa_void),
+    arg: *mut xtensa_void,
+    deadline: Option<u64>,
+    period: Option<u64>,
+}
+
+#[derive(Default)]
+pub(crate) struct EspTimerState {
+    /// Timers indexed by `handle - 1`, deleted timers are `None`
+    timers: Vec<Option<SimulatedTimer>>,
+    /// Callbacks of the expired timers, which were stopped before the timer task called them
+    dispatched: Vec<(unsafe extern "C" fn(arg: *mut xtensa_void), *mut xtensa_void)>,
+    /// Timer task is calling the callback
+    in_timer_task: bool,
+}
+
+/// Keeps the callback of the stopped timer, if it has already expired and the stop is not made by
+/// the timer task itself
+fn keep_dispatched(state: &mut EspTimerState, index: usize, now: u64) {
+    let in_timer_task = state.in_timer_task;
+    if let Some(Some(timer)) = state.timers.get(index) {
+        if !in_timer_task && timer.deadline.is_some_and(|deadline| deadline <= now) {
+            state.dispatched.push((timer.callback, timer.arg));
+        }
+    }
+}
+
+fn assert_task_context(function: &str) {
+    assert!(!freertos::interrupts_masked(), "{} called with interrupts masked", function);
+}
+
+fn timer_index(handle: esp_timer_handle_t) -> usize {
+    (handle as usize).wrapping_sub(1)
+}
+
+fn with_timer<F>(handle: esp_timer_handle_t, f: F) -> esp_err_t
+    where F: FnOnce(&mut SimulatedTimer, u64) -> esp_err_t
+{
+    with_chip(|chip| {
+        let now = chip.time_us;
+        match chip.esp_timer.timers.get_mut(timer_index(handle)) {
+            Some(Some(timer)) => f(timer, now),
+            _ => esp_err_t_ESP_ERR_INVALID_ARG,
+        }
+    })
+}
+
+/// Returns time of the simulated clock
+pub unsafe fn esp_timer_get_time() -> i64 {
+    time_us() as i64
+}
+
+pub unsafe fn esp_timer_create(
+    create_args: *const esp_timer_create_args_t,
+    out_handle: *mut esp_timer_handle_t,
+) -> esp_err_t {
+    let args = *create_args;
+
+    assert_task_context("esp_timer_create");
+    let err = record("esp_timer_create", &[args.dispatch_method as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let callback = match args.callback {
+        Some(callback) => callback,
+        None => return esp_err_t_ESP_ERR_INVALID_ARG,
+    };
+
+    with_chip(|chip| {
+        chip.esp_timer.timers.push(Some(SimulatedTimer {
+            callback,
+            arg: args.arg,
+            deadline: None,
+            period: None,
+        }));
+        *out_handle = chip.esp_timer.timers.len() as esp_timer_handle_t;
+    });
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn esp_timer_start_once(timer: esp_timer_handle_t, timeout_us: u64) -> esp_err_t {
+    assert_task_context("esp_timer_start_once");
+    let err = record("esp_timer_start_once", &[timer as i64, timeout_us as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_timer(timer, |timer, now| {
+        if timer.deadline.is_some() {
+            return esp_err_t_ESP_ERR_INVALID_STATE;
+        }
+
+        timer.deadline = Some(now + timeout_us);
+        timer.period = None;
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn esp_timer_start_periodic(timer: esp_timer_handle_t, period: u64) -> esp_err_t {
+    assert_task_context("esp_timer_start_periodic");
+    let err = record("esp_timer_start_periodic", &[timer as i64, period as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if period == 0 {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_timer(timer, |timer, now| {
+        if timer.deadline.is_some() {
+            return esp_err_t_ESP_ERR_INVALID_STATE;
+        }
+
+        timer.deadline = Some(now + period);
+        timer.period = Some(period);
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn esp_timer_stop(timer: esp_timer_handle_t) -> esp_err_t {
+    assert_task_context("esp_timer_stop");
+    let err = record("esp_timer_stop", &[timer as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| keep_dispatched(&mut chip.esp_timer, timer_index(timer), chip.time_us));
+    with_timer(timer, |timer, _| match timer.deadline.take() {
+        Some(_) => esp_err_t_ESP_OK,
+        None => esp_err_t_ESP_ERR_INVALID_STATE,
+    })
+}
+
+pub unsafe fn esp_timer_delete(timer: esp_timer_handle_t) -> esp_err_t {
+    assert_task_context("esp_timer_delete");
+    let err = record("esp_timer_delete", &[timer as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| match chip.esp_timer.timers.get_mut(timer_index(timer)) {
+        Some(Some(timer)) if timer.deadline.is_some() => esp_err_t_ESP_ERR_INVALID_STATE,
+        Some(slot @ Some(_)) => {
+            *slot = None;
+            esp_err_t_ESP_OK
+        }
+        _ => esp_err_t_ESP_ERR_INVALID_ARG,
+    })
+}
+
+/// Advances simulated clock by `us`, calling callbacks of the timers, which expire meanwhile, in
+/// the order of their deadlines. Clock is set to the deadline of the timer during its callback
+pub fn advance(us: u64) {
+    let target = time_us() + us;
+
+    loop {
+        let expired = with_chip(|chip| {
+            if !chip.esp_timer.dispatched.is_empty() {
+                return Some(chip.esp_timer.dispatched.remove(0));
+            }
+
+            let (index, deadline) = chip.esp_timer.timers.iter()
+                .enumerate()
+                .filter_map(|(index, timer)| Some((index, timer.as_ref()?.deadline?)))
+                .filter(|(_, deadline)| *deadline <= target)
+                .min_by_key(|(_, deadline)| *deadline)?;
+
+            chip.time_us = chip.time_us.max(deadline);
+
+            let timer = chip.esp_timer.timers[index].as_mut()?;
+            timer.deadline = timer.period.map(|period| deadline + period);
+            Some((timer.callback, timer.arg))
+        });
+
+        // Chip should not be borrowed here - callback could call the mock again
+        match expired {
+            Some((callback, arg)) => {
+                with_chip(|chip| chip.esp_timer.in_timer_task = true);
+                unsafe { callback(arg) };
+                with_chip(|chip| chip.esp_timer.in_timer_task = false);
+            }
+            None => break,
+        }
+    }
+
+    with_chip(|chip| chip.time_us = chip.time_us.max(target));
+}
+
+/// Returns number of created and not deleted timers
+pub fn timer_count() -> usize {
+    with_chip(|chip| chip.esp_timer.timers.iter().filter(|timer| timer.is_some()).count())
+}
@@ -0,0 +1,4 @@
+pub type xtensa_void = core::ffi::c_void;
+pub type xtensa_int = i32;
+pub type xtensa_uint = u32;
+pub type xtensa_char = u8;
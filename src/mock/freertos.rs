@@ -0,0 +1,68 @@
+//! Critical sections of the simulated chip are process-wide and reentrant: they exclude all
+//! other threads, which allows to test synchronization of the HAL with std threads.
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::{esp_timer, time_us};
+
+pub type TickType_t = usize;
+
+pub const configTICK_RATE_HZ: u32 = 100;
+pub const portMAX_DELAY: TickType_t = 0xffffffff;
+
+const TICK_PERIOD_US: u64 = 1_000_000 / configTICK_RATE_HZ as u64;
+
+static CRITICAL_SECTION_LOCKED: AtomicBool = AtomicBool::new(false);
+
+std::thread_local! {
+    static CRITICAL_SECTION_NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+pub unsafe fn vPortEnterCritical() {
+    CRITICAL_SECTION_NESTING.with(|nesting| {
+        if nesting.get() == 0 {
+            while CRITICAL_SECTION_LOCKED
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                std::thread::yield_now();
+            }
+        }
+        nesting.set(nesting.get() + 1);
+    });
+}
+
+pub unsafe fn vPortExitCritical() {
+    CRITICAL_SECTION_NESTING.with(|nesting| {
+        assert!(nesting.get() > 0, "vPortExitCritical called outside of critical section");
+
+        nesting.set(nesting.get() - 1);
+        if nesting.get() == 0 {
+            CRITICAL_SECTION_LOCKED.store(false, Ordering::Release);
+        }
+    });
+}
+
+/// Returns whether the current thread is inside the critical section or the interrupt handler
+pub(super) fn interrupts_masked() -> bool {
+    CRITICAL_SECTION_NESTING.with(|nesting| nesting.get() > 0)
+}
+
+/// Calls the interrupt handler with interrupts masked, like the CPU does
+pub(super) fn run_isr<F: FnOnce()>(handler: F) {
+    unsafe { vPortEnterCritical() };
+    handler();
+    unsafe { vPortExitCritical() };
+}
+
+/// Simulated delay is instant: it only advances the clock of the simulated chip and runs
+/// callbacks of the expired esp_timer timers
+pub unsafe fn vTaskDelay(ticks_to_delay: TickType_t) {
+    esp_timer::advance(ticks_to_delay as u64 * TICK_PERIOD_US);
+}
+
+pub unsafe fn xTaskGetTickCount() -> TickType_t {
+    (time_us() / TICK_PERIOD_US) as TickType_t
+}
@@ -0,0 +1,413 @@
+use super::{
+    freertos, i2c, record, with_chip,
+    error::*,
+    ffi::*,
+};
+use crate::gpio::registers::{
+    RtcRegister, RTC_PAD_FUNCTION_MASK, RTC_PAD_FUNCTION_XPD_DCDC, RTC_PAD_PULL_DOWN,
+};
+
+pub type gpio_num_t = xtensa_uint;
+
+pub type gpio_mode_t = xtensa_uint;
+pub const gpio_mode_t_GPIO_MODE_DISABLE: gpio_mode_t = 0;
+pub const gpio_mode_t_GPIO_MODE_INPUT: gpio_mode_t = 1;
+pub const gpio_mode_t_GPIO_MODE_OUTPUT: gpio_mode_t = 2;
+pub const gpio_mode_t_GPIO_MODE_OUTPUT_OD: gpio_mode_t = 6;
+
+pub type gpio_pullup_t = xtensa_uint;
+pub const gpio_pullup_t_GPIO_PULLUP_DISABLE: gpio_pullup_t = 0;
+pub const gpio_pullup_t_GPIO_PULLUP_ENABLE: gpio_pullup_t = 1;
+
+pub type gpio_pulldown_t = xtensa_uint;
+pub const gpio_pulldown_t_GPIO_PULLDOWN_DISABLE: gpio_pulldown_t = 0;
+pub const gpio_pulldown_t_GPIO_PULLDOWN_ENABLE: gpio_pulldown_t = 1;
+
+pub type gpio_int_type_t = xtensa_uint;
+pub const gpio_int_type_t_GPIO_INTR_DISABLE: gpio_int_type_t = 0;
+pub const gpio_int_type_t_GPIO_INTR_POSEDGE: gpio_int_type_t = 1;
+pub const gpio_int_type_t_GPIO_INTR_NEGEDGE: gpio_int_type_t = 2;
+pub const gpio_int_type_t_GPIO_INTR_ANYEDGE: gpio_int_type_t = 3;
+pub const gpio_int_type_t_GPIO_INTR_LOW_LEVEL: gpio_int_type_t = 4;
+pub const gpio_int_type_t_GPIO_INTR_HIGH_LEVEL: gpio_int_type_t = 5;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct gpio_config_t {
+    pub pin_bit_mask: u32,
+    pub mode: gpio_mode_t,
+    pub pull_up_en: gpio_pullup_t,
+    pub pull_down_en: gpio_pulldown_t,
+    pub intr_type: gpio_int_type_t,
+}
+
+pub type gpio_isr_t = Option<unsafe extern "C" fn(arg: *mut xtensa_void)>;
+
+pub(crate) const GPIO_PIN_COUNT: usize = 17;
+
+/// Snapshot of the simulated pin
+#[derive(Copy, Clone, Default, Debug)]
+pub struct PinState {
+    pub mode: gpio_mode_t,
+    pub pull_up: bool,
+    pub pull_down: bool,
+    pub intr_type: gpio_int_type_t,
+    /// Level written by the driver
+    pub output_level: bool,
+    /// Level driven by the external circuit, `None` if pin is floating
+    pub input_level: Option<bool>,
+    /// Pin is pulled low by the simulated I2C slave
+    pub bus_drive_low: bool,
+}
+
+impl PinState {
+    /// Level which is observed on the pin
+    pub fn level(&self) -> bool {
+        // Open drain devices and the external circuit form wired AND
+        let external_level = if self.bus_drive_low { Some(false) } else { self.input_level };
+
+        match self.mode {
+            gpio_mode_t_GPIO_MODE_OUTPUT => self.output_level,
+            gpio_mode_t_GPIO_MODE_OUTPUT_OD => {
+                self.output_level && external_level.unwrap_or(self.pull_up)
+            }
+            _ => external_level.unwrap_or(self.pull_up),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct IsrHandler {
+    handler: unsafe extern "C" fn(arg: *mut xtensa_void),
+    arg: *mut xtensa_void,
+}
+
+#[derive(Default)]
+pub(crate) struct GpioState {
+    pub(crate) pins: [PinState; GPIO_PIN_COUNT],
+    isr_service_installed: bool,
+    isr_handlers: [Option<IsrHandler>; GPIO_PIN_COUNT],
+    rtc_gpio_conf: u32,
+    // Pad is in the XPD_DCDC function after reset
+    pad_xpd_dcdc_conf: u32,
+}
+
+fn interrupt_triggered(intr_type: gpio_int_type_t, old_level: bool, new_level: bool) -> bool {
+    match intr_type {
+        gpio_int_type_t_GPIO_INTR_POSEDGE => !old_level && new_level,
+        gpio_int_type_t_GPIO_INTR_NEGEDGE => old_level && !new_level,
+        gpio_int_type_t_GPIO_INTR_ANYEDGE => old_level != new_level,
+        gpio_int_type_t_GPIO_INTR_LOW_LEVEL => !new_level,
+        gpio_int_type_t_GPIO_INTR_HIGH_LEVEL => new_level,
+        _ => false,
+    }
+}
+
+/// Modifies pin and calls its interrupt handler if the change of the level triggers interrupt
+fn update_pin<F>(pin: u8, f: F)
+    where F: FnOnce(&mut PinState)
+{
+    let handler = with_chip(|chip| {
+        let old_level = chip.gpio.pins[pin as usize].level();
+        f(&mut chip.gpio.pins[pin as usize]);
+        // Simulated I2C slaves could drive the pin in response to the change
+        i2c::update_bus(chip);
+
+        let state = &chip.gpio.pins[pin as usize];
+        if interrupt_triggered(state.intr_type, old_level, state.level()) {
+            chip.gpio.isr_handlers[pin as usize]
+        } else {
+            None
+        }
+    });
+
+    // Chip should not be borrowed here - handler could call the mock again
+    if let Some(isr) = handler {
+        freertos::run_isr(|| unsafe { (isr.handler)(isr.arg) });
+    }
+}
+
+fn with_pin<F>(gpio_num: gpio_num_t, f: F) -> esp_err_t
+    where F: FnOnce(&mut PinState)
+{
+    if gpio_num as usize >= GPIO_PIN_COUNT {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| f(&mut chip.gpio.pins[gpio_num as usize]));
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn gpio_config(config: *const gpio_config_t) -> esp_err_t {
+    let config = *config;
+
+    let err = record("gpio_config", &[
+        config.pin_bit_mask as i64,
+        config.mode as i64,
+        config.pull_up_en as i64,
+        config.pull_down_en as i64,
+        config.intr_type as i64,
+    ]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if config.pin_bit_mask >> GPIO_PIN_COUNT != 0 {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    for gpio_num in 0..GPIO_PIN_COUNT as gpio_num_t {
+        if config.pin_bit_mask & (1 << gpio_num) != 0 {
+            with_pin(gpio_num, |pin| {
+                pin.mode = config.mode;
+                pin.pull_up = config.pull_up_en == gpio_pullup_t_GPIO_PULLUP_ENABLE;
+                pin.pull_down = config.pull_down_en == gpio_pulldown_t_GPIO_PULLDOWN_ENABLE;
+                pin.intr_type = config.intr_type;
+            });
+        }
+    }
+
+    esp_err_t_ESP_OK
+}
+
+macro_rules! define_pin_ffi {
+    ($($name:ident ( $($arg:ident : $arg_type:ty),* ) => |$pin:ident| $body:expr;)+) => {$(
+        pub unsafe fn $name(gpio_num: gpio_num_t $(, $arg: $arg_type)*) -> esp_err_t {
+            let err = record(stringify!($name), &[gpio_num as i64 $(, $arg as i64)*]);
+            if err != esp_err_t_ESP_OK {
+                return err;
+            }
+
+            with_pin(gpio_num, |$pin| { $body; })
+        }
+    )+}
+}
+
+pub unsafe fn gpio_set_level(gpio_num: gpio_num_t, level: u32) -> esp_err_t {
+    let err = record("gpio_set_level", &[gpio_num as i64, level as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if gpio_num as usize >= GPIO_PIN_COUNT {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    update_pin(gpio_num as u8, |pin| pin.output_level = level != 0);
+    esp_err_t_ESP_OK
+}
+
+define_pin_ffi!(
+    gpio_set_direction(mode: gpio_mode_t) => |pin| pin.mode = mode;
+    gpio_set_intr_type(intr_type: gpio_int_type_t) => |pin| pin.intr_type = intr_type;
+    gpio_pullup_en() => |pin| pin.pull_up = true;
+    gpio_pullup_dis() => |pin| pin.pull_up = false;
+    gpio_pulldown_en() => |pin| pin.pull_down = true;
+    gpio_pulldown_dis() => |pin| pin.pull_down = false;
+);
+
+pub unsafe fn gpio_get_level(gpio_num: gpio_num_t) -> xtensa_int {
+    record("gpio_get_level", &[gpio_num as i64]);
+
+    if gpio_num as usize >= GPIO_PIN_COUNT {
+        return 0;
+    }
+
+    // Simulated I2C slaves release stretched clock after the simulated time passes
+    with_chip(|chip| {
+        i2c::update_bus(chip);
+        chip.gpio.pins[gpio_num as usize].level() as xtensa_int
+    })
+}
+
+pub unsafe fn gpio_install_isr_service(no_use: xtensa_int) -> esp_err_t {
+    let err = record("gpio_install_isr_service", &[no_use as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| {
+        if chip.gpio.isr_service_installed {
+            esp_err_t_ESP_ERR_INVALID_STATE
+        } else {
+            chip.gpio.isr_service_installed = true;
+            esp_err_t_ESP_OK
+        }
+    })
+}
+
+pub unsafe fn gpio_uninstall_isr_service() {
+    record("gpio_uninstall_isr_service", &[]);
+
+    with_chip(|chip| {
+        chip.gpio.isr_service_installed = false;
+        chip.gpio.isr_handlers = Default::default();
+    });
+}
+
+pub unsafe fn gpio_isr_handler_add(gpio_num: gpio_num_t, isr_handler: gpio_isr_t, args: *mut xtensa_void)
+    -> esp_err_t
+{
+    let err = record("gpio_isr_handler_add", &[gpio_num as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if gpio_num as usize >= GPIO_PIN_COUNT || isr_handler.is_none() {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| {
+        if !chip.gpio.isr_service_installed {
+            return esp_err_t_ESP_ERR_INVALID_STATE;
+        }
+
+        chip.gpio.isr_handlers[gpio_num as usize] = isr_handler.map(|handler| {
+            IsrHandler { handler, arg: args }
+        });
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn gpio_isr_handler_remove(gpio_num: gpio_num_t) -> esp_err_t {
+    let err = record("gpio_isr_handler_remove", &[gpio_num as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if gpio_num as usize >= GPIO_PIN_COUNT {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| {
+        if !chip.gpio.isr_service_installed {
+            return esp_err_t_ESP_ERR_INVALID_STATE;
+        }
+
+        chip.gpio.isr_handlers[gpio_num as usize] = None;
+        esp_err_t_ESP_OK
+    })
+}
+
+/// Returns snapshot of the simulated pin
+pub fn pin_state(pin: u8) -> PinState {
+    with_chip(|chip| chip.gpio.pins[pin as usize])
+}
+
+/// Drives pin from the external circuit. Calls pin interrupt handler if the change of the level
+/// matches pin interrupt type
+pub fn set_input_level(pin: u8, level: bool) {
+    update_pin(pin, |state| state.input_level = Some(level));
+}
+
+/// Stops driving pin from the external circuit, leaving it floating
+pub fn release_input(pin: u8) {
+    update_pin(pin, |state| state.input_level = None);
+}
+
+/// Simulates pulse of the given level on the pin: drives pin to `level` and back
+pub fn pulse(pin: u8, level: bool) {
+    set_input_level(pin, level);
+    set_input_level(pin, !level);
+}
+
+/// Calls pin interrupt handler regardless of the pin state. Returns `false` if no handler
+/// has been attached
+pub fn trigger_interrupt(pin: u8) -> bool {
+    let handler = with_chip(|chip| chip.gpio.isr_handlers[pin as usize]);
+
+    match handler {
+        Some(isr) => {
+            freertos::run_isr(|| unsafe { (isr.handler)(isr.arg) });
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns whether interrupt handler is attached to the pin
+pub fn has_isr_handler(pin: u8) -> bool {
+    with_chip(|chip| chip.gpio.isr_handlers[pin as usize].is_some())
+}
+
+// Register-level access used by the HAL for GPIO0-GPIO15
+
+pub(crate) fn read_out_reg() -> u32 {
+    with_chip(|chip| {
+        chip.gpio.pins[..16].iter().enumerate()
+            .fold(0, |reg, (id, pin)| reg | ((pin.output_level as u32) << id))
+    })
+}
+
+pub(crate) fn write_out_reg(value: u32) {
+    record("GPIO_OUT", &[value as i64]);
+
+    for id in 0..16 {
+        update_pin(id, |pin| pin.output_level = value & (1 << id) != 0);
+    }
+}
+
+pub(crate) fn read_in_reg() -> u32 {
+    record("GPIO_IN", &[]);
+
+    with_chip(|chip| {
+        chip.gpio.pins[..16].iter().enumerate()
+            .fold(0, |reg, (id, pin)| reg | ((pin.level() as u32) << id))
+    })
+}
+
+// Register-level access used by the HAL for GPIO16
+
+const RTC_GPIO_PIN: u8 = 16;
+
+pub(crate) fn read_rtc_reg(register: RtcRegister) -> u32 {
+    if register == RtcRegister::GpioIn {
+        record("RTC_GPIO_IN", &[]);
+    }
+
+    with_chip(|chip| {
+        let pin = &chip.gpio.pins[RTC_GPIO_PIN as usize];
+        match register {
+            RtcRegister::GpioOut => pin.output_level as u32,
+            RtcRegister::GpioEnable => (pin.mode == gpio_mode_t_GPIO_MODE_OUTPUT) as u32,
+            RtcRegister::GpioIn => pin.level() as u32,
+            RtcRegister::GpioConf => chip.gpio.rtc_gpio_conf,
+            RtcRegister::PadXpdDcdcConf => chip.gpio.pad_xpd_dcdc_conf,
+        }
+    })
+}
+
+pub(crate) fn write_rtc_reg(register: RtcRegister, value: u32) {
+    let name = match register {
+        RtcRegister::GpioOut => "RTC_GPIO_OUT",
+        RtcRegister::GpioEnable => "RTC_GPIO_ENABLE",
+        RtcRegister::GpioIn => "RTC_GPIO_IN",
+        RtcRegister::GpioConf => "RTC_GPIO_CONF",
+        RtcRegister::PadXpdDcdcConf => "PAD_XPD_DCDC_CONF",
+    };
+    record(name, &[value as i64]);
+
+    match register {
+        RtcRegister::GpioOut => update_pin(RTC_GPIO_PIN, |pin| pin.output_level = value & 1 != 0),
+        RtcRegister::GpioEnable => update_pin(RTC_GPIO_PIN, |pin| {
+            pin.mode = if value & 1 != 0 {
+                gpio_mode_t_GPIO_MODE_OUTPUT
+            } else {
+                gpio_mode_t_GPIO_MODE_INPUT
+            };
+        }),
+        // Input register is read only
+        RtcRegister::GpioIn => {}
+        RtcRegister::GpioConf => with_chip(|chip| chip.gpio.rtc_gpio_conf = value),
+        RtcRegister::PadXpdDcdcConf => {
+            with_chip(|chip| chip.gpio.pad_xpd_dcdc_conf = value);
+            update_pin(RTC_GPIO_PIN, |pin| pin.pull_down = value & RTC_PAD_PULL_DOWN != 0);
+        }
+    }
+}
+
+/// Returns `true` if GPIO16 pad is driven by the XPD_DCDC signal, so it wakes the chip from the
+/// deep sleep instead of acting as GPIO
+pub fn is_deep_sleep_wake_line() -> bool {
+    with_chip(|chip| chip.gpio.pad_xpd_dcdc_conf & RTC_PAD_FUNCTION_MASK == RTC_PAD_FUNCTION_XPD_DCDC)
+}
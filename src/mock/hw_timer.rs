@@ -0,0 +1,145 @@
+//! Simulated FRC1 hardware timer.
+//!
+//! Timer doesn't follow the simulated clock - expiration of the armed timer is simulated with
+//! [fire](fn.fire.html), which calls the interrupt handler.
+use super::{
+    record, with_chip,
+    error::*,
+    ffi::*,
+};
+
+pub type hw_timer_callback_t = Option<unsafe extern "C" fn(arg: *mut xtensa_void)>;
+pub type nmi_callback_t = Option<unsafe extern "C" fn()>;
+
+const MIN_ONE_SHOT_US: u32 = 10;
+const MIN_RELOAD_US: u32 = 50;
+const MAX_PERIOD_US: u32 = 0x199999;
+
+pub(crate) struct HwTimerState {
+    callback: hw_timer_callback_t,
+    arg: *mut xtensa_void,
+    pub(crate) nmi_handler: nmi_callback_t,
+    armed: bool,
+    reload: bool,
+    period_us: u32,
+}
+
+impl Default for HwTimerState {
+    fn default() -> Self {
+        Self {
+            callback: None,
+            arg: core::ptr::null_mut(),
+            nmi_handler: None,
+            armed: false,
+            reload: false,
+            period_us: 0,
+        }
+    }
+}
+
+pub unsafe fn hw_timer_init(callback: hw_timer_callback_t, arg: *mut xtensa_void) -> esp_err_t {
+    let err = record("hw_timer_init", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if callback.is_none() {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| {
+        chip.hw_timer.callback = callback;
+        chip.hw_timer.arg = arg;
+        chip.hw_timer.armed = false;
+    });
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn hw_timer_deinit() -> esp_err_t {
+    let err = record("hw_timer_deinit", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| {
+        chip.hw_timer.callback = None;
+        chip.hw_timer.arg = core::ptr::null_mut();
+        chip.hw_timer.armed = false;
+    });
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn hw_timer_alarm_us(value: u32, reload: bool) -> esp_err_t {
+    let err = record("hw_timer_alarm_us", &[value as i64, reload as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let min_us = if reload { MIN_RELOAD_US } else { MIN_ONE_SHOT_US };
+    if value < min_us || value > MAX_PERIOD_US {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| {
+        if chip.hw_timer.callback.is_none() {
+            return esp_err_t_ESP_ERR_INVALID_STATE;
+        }
+
+        chip.hw_timer.armed = true;
+        chip.hw_timer.reload = reload;
+        chip.hw_timer.period_us = value;
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn hw_timer_disarm() -> esp_err_t {
+    let err = record("hw_timer_disarm", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.hw_timer.armed = false);
+    esp_err_t_ESP_OK
+}
+
+/// Simulates expiration of the armed timer: calls NMI handler if it is set, otherwise the FRC1
+/// interrupt handler. One-shot timer is disarmed. Returns `false` if timer is not armed
+pub fn fire() -> bool {
+    let (callback, arg, nmi_handler) = with_chip(|chip| {
+        let timer = &mut chip.hw_timer;
+        if !timer.armed {
+            return (None, core::ptr::null_mut(), None);
+        }
+
+        timer.armed = timer.reload;
+        (timer.callback, timer.arg, timer.nmi_handler)
+    });
+
+    // Chip should not be borrowed here - handler could call the mock again
+    unsafe {
+        match (nmi_handler, callback) {
+            (Some(nmi_handler), Some(_)) => nmi_handler(),
+            (None, Some(callback)) => callback(arg),
+            _ => return false,
+        }
+    }
+    true
+}
+
+pub fn is_armed() -> bool {
+    with_chip(|chip| chip.hw_timer.armed)
+}
+
+pub fn is_reload() -> bool {
+    with_chip(|chip| chip.hw_timer.reload)
+}
+
+/// Period of the last alarm
+pub fn period_us() -> u32 {
+    with_chip(|chip| chip.hw_timer.period_us)
+}
+
+/// Returns whether the timer interrupt is routed to NMI
+pub fn is_nmi() -> bool {
+    with_chip(|chip| chip.hw_timer.nmi_handler.is_some())
+}
@@ -0,0 +1,248 @@
+//! Simulated I2C slaves connected to two GPIO pins of the simulated chip.
+//!
+//! Slaves observe SCL and SDA levels on every pin change and drive the lines like real devices:
+//! they acknowledge their address, receive and send data bits and can stretch the clock.
+//! Each slave has 256 byte-wide registers: the first byte written after the address selects the
+//! register, following bytes are written to the consecutive registers. Reads start from the
+//! selected register and auto-increment.
+//!
+//! Bus lines are open drain, so pins should be configured as open drain outputs with pull ups.
+use std::vec::Vec;
+
+use super::{with_chip, Chip};
+
+const REGISTER_COUNT: usize = 256;
+
+struct Slave {
+    address: u8,
+    registers: [u8; REGISTER_COUNT],
+    register_pointer: u8,
+    pointer_selected: bool,
+    clock_stretch_us: u64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Idle,
+    /// Receiving address byte
+    Address { byte: u8, bits: u8 },
+    /// Acknowledging received byte; SDA is driven low after the 8th clock
+    SlaveAck { slave: usize, read: bool, driving: bool },
+    /// Receiving data byte from the master
+    Write { slave: usize, byte: u8, bits: u8 },
+    /// Sending data byte to the master, `bits` have been already sampled
+    Read { slave: usize, byte: u8, bits: u8 },
+    /// Waiting for the master acknowledge of the sent byte
+    MasterAck { slave: usize, sampled: Option<bool> },
+    /// Transfer is not addressed to the simulated slaves
+    Ignore,
+}
+
+pub(crate) struct I2cBus {
+    scl: u8,
+    sda: u8,
+    scl_level: bool,
+    sda_level: bool,
+    slaves: Vec<Slave>,
+    phase: Phase,
+    stretch_until: Option<u64>,
+}
+
+#[derive(Default)]
+pub(crate) struct I2cState {
+    bus: Option<I2cBus>,
+}
+
+impl I2cBus {
+    fn levels(chip: &Chip, scl: u8, sda: u8) -> (bool, bool) {
+        (chip.gpio.pins[scl as usize].level(), chip.gpio.pins[sda as usize].level())
+    }
+
+    /// Pulls `pin` low or releases it. Drive is combined with the levels of the pin and the
+    /// external circuit
+    fn drive(chip: &mut Chip, pin: u8, low: bool) {
+        chip.gpio.pins[pin as usize].bus_drive_low = low;
+    }
+
+    fn on_scl_rising(&mut self, sda_level: bool) {
+        let bit = sda_level as u8;
+        self.phase = match self.phase {
+            Phase::Address { byte, bits } => {
+                let byte = (byte << 1) | bit;
+                if bits + 1 < 8 {
+                    Phase::Address { byte, bits: bits + 1 }
+                } else {
+                    match self.slaves.iter().position(|slave| slave.address == byte >> 1) {
+                        Some(slave) => Phase::SlaveAck { slave, read: byte & 1 != 0, driving: false },
+                        None => Phase::Ignore,
+                    }
+                }
+            }
+            Phase::Write { slave, byte, bits } => {
+                let byte = (byte << 1) | bit;
+                if bits + 1 < 8 {
+                    Phase::Write { slave, byte, bits: bits + 1 }
+                } else {
+                    let slave_state = &mut self.slaves[slave];
+                    if slave_state.pointer_selected {
+                        slave_state.registers[slave_state.register_pointer as usize] = byte;
+                        slave_state.register_pointer = slave_state.register_pointer.wrapping_add(1);
+                    } else {
+                        slave_state.register_pointer = byte;
+                        slave_state.pointer_selected = true;
+                    }
+                    Phase::SlaveAck { slave, read: false, driving: false }
+                }
+            }
+            Phase::Read { slave, byte, bits } => {
+                if bits + 1 < 8 {
+                    Phase::Read { slave, byte, bits: bits + 1 }
+                } else {
+                    Phase::MasterAck { slave, sampled: None }
+                }
+            }
+            Phase::MasterAck { slave, .. } => Phase::MasterAck { slave, sampled: Some(!sda_level) },
+            phase => phase,
+        };
+    }
+
+    /// Returns new SDA drive of the slave, `None` if it is unchanged
+    fn on_scl_falling(&mut self, now: u64) -> Option<bool> {
+        match self.phase {
+            Phase::SlaveAck { slave, read, driving: false } => {
+                self.phase = Phase::SlaveAck { slave, read, driving: true };
+                Some(true)
+            }
+            Phase::SlaveAck { slave, read, driving: true } => {
+                let stretch_us = self.slaves[slave].clock_stretch_us;
+                if stretch_us != 0 {
+                    self.stretch_until = Some(now + stretch_us);
+                }
+
+                if read {
+                    Some(self.start_read_byte(slave))
+                } else {
+                    self.phase = Phase::Write { slave, byte: 0, bits: 0 };
+                    Some(false)
+                }
+            }
+            Phase::Read { byte, bits, .. } if bits > 0 => Some(byte & (0x80 >> bits) == 0),
+            // Release SDA, so the master can acknowledge
+            Phase::MasterAck { sampled: None, .. } => Some(false),
+            Phase::MasterAck { slave, sampled: Some(true) } => Some(self.start_read_byte(slave)),
+            Phase::MasterAck { sampled: Some(false), .. } => {
+                self.phase = Phase::Ignore;
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+
+    /// Loads the next register and returns drive of its first bit
+    fn start_read_byte(&mut self, slave: usize) -> bool {
+        let slave_state = &mut self.slaves[slave];
+        let byte = slave_state.registers[slave_state.register_pointer as usize];
+        slave_state.register_pointer = slave_state.register_pointer.wrapping_add(1);
+
+        self.phase = Phase::Read { slave, byte, bits: 0 };
+        byte & 0x80 == 0
+    }
+}
+
+/// Processes changes of the bus lines. Called by the simulated GPIO after each pin change and
+/// before each level read
+pub(crate) fn update_bus(chip: &mut Chip) {
+    let mut bus = match chip.i2c.bus.take() {
+        Some(bus) => bus,
+        None => return,
+    };
+
+    if let Some(until) = bus.stretch_until {
+        if chip.time_us >= until {
+            bus.stretch_until = None;
+            I2cBus::drive(chip, bus.scl, false);
+        } else {
+            I2cBus::drive(chip, bus.scl, true);
+        }
+    }
+
+    let (scl, sda) = I2cBus::levels(chip, bus.scl, bus.sda);
+
+    if scl != bus.scl_level {
+        if scl {
+            bus.on_scl_rising(sda);
+        } else if let Some(low) = bus.on_scl_falling(chip.time_us) {
+            I2cBus::drive(chip, bus.sda, low);
+            if bus.stretch_until.is_some() {
+                I2cBus::drive(chip, bus.scl, true);
+            }
+        }
+    } else if scl && sda != bus.sda_level {
+        if sda {
+            // Stop condition
+            bus.phase = Phase::Idle;
+        } else {
+            // Start or repeated start condition
+            bus.phase = Phase::Address { byte: 0, bits: 0 };
+            for slave in bus.slaves.iter_mut() {
+                slave.pointer_selected = false;
+            }
+        }
+        I2cBus::drive(chip, bus.sda, false);
+    }
+
+    let (scl, sda) = I2cBus::levels(chip, bus.scl, bus.sda);
+    bus.scl_level = scl;
+    bus.sda_level = sda;
+    chip.i2c.bus = Some(bus);
+}
+
+fn with_slave<F, R>(address: u8, f: F) -> R
+    where F: FnOnce(&mut Slave) -> R
+{
+    with_chip(|chip| {
+        let slave = chip.i2c.bus.as_mut()
+            .and_then(|bus| bus.slaves.iter_mut().find(|slave| slave.address == address))
+            .expect("I2C slave with the given address is not attached");
+        f(slave)
+    })
+}
+
+/// Connects simulated slave with 7-bit `address` to the bus on `scl` and `sda` pins. All slaves
+/// of the simulated chip share the same bus
+pub fn attach_slave(scl: u8, sda: u8, address: u8) {
+    with_chip(|chip| {
+        let (scl_level, sda_level) = I2cBus::levels(chip, scl, sda);
+        let bus = chip.i2c.bus.get_or_insert_with(|| I2cBus {
+            scl,
+            sda,
+            scl_level,
+            sda_level,
+            slaves: Vec::new(),
+            phase: Phase::Idle,
+            stretch_until: None,
+        });
+        assert!(bus.scl == scl && bus.sda == sda, "Simulated I2C bus is already attached to other pins");
+
+        bus.slaves.push(Slave {
+            address,
+            registers: [0; REGISTER_COUNT],
+            register_pointer: 0,
+            pointer_selected: false,
+            clock_stretch_us: 0,
+        });
+    });
+}
+
+pub fn register(address: u8, register: u8) -> u8 {
+    with_slave(address, |slave| slave.registers[register as usize])
+}
+
+pub fn set_register(address: u8, register: u8, value: u8) {
+    with_slave(address, |slave| slave.registers[register as usize] = value);
+}
+
+/// Makes slave hold SCL low for `us` after each acknowledge
+pub fn set_clock_stretch(address: u8, us: u64) {
+    with_slave(address, |slave| slave.clock_stretch_us = us);
+}
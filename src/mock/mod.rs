@@ -0,0 +1,243 @@
+//! In-memory simulated chip, which replaces `idf-sys` when the `mock` feature is enabled.
+//!
+//! Each submodule mirrors the corresponding `idf-sys` module: it provides the same FFI
+//! functions, types and constants, and additionally exposes functions to inspect and drive the
+//! simulated hardware (e.g. [gpio::set_input_level](gpio/fn.set_input_level.html) or
+//! [uart::push_rx](uart/fn.push_rx.html)). Every FFI call is recorded and can be checked with
+//! [calls](fn.calls.html).
+//!
+//! Simulated chip has its own clock, which is advanced only by the simulated delays and
+//! [advance_time](fn.advance_time.html), so timing-dependent code runs instantly and
+//! deterministically.
+//!
+//! Each thread gets its own simulated chip, so tests which are run in parallel by the test
+//! harness do not observe each other. Critical sections are the only process-wide state.
+//!
+//! To build the crate against the mock, disable default features:
+//! `cargo test --no-default-features --features mock`
+//!
+//! # Examples
+//! ```rust
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     mock,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = unsafe { Peripherals::steal() };
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut led = PinInitializer::new(gpio.gpio4.take().unwrap())
+//!     .configure_as_output()
+//!     .init();
+//! led.set_level(true);
+//!
+//! assert!(mock::gpio::pin_state(4).output_level);
+//! assert!(mock::calls().contains(&mock::Call::new("gpio_set_level", &[4, 1])));
+//! ```
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    cell::RefCell,
+    vec::Vec,
+};
+
+use error::*;
+
+pub mod adc;
+pub mod ffi;
+pub mod error;
+pub mod esp_timer;
+pub mod freertos;
+pub mod gpio;
+pub mod hw_timer;
+pub mod i2c;
+pub mod pwm;
+pub mod uart;
+pub mod nvs;
+pub mod wifi;
+pub mod network_adapter;
+pub mod rom;
+pub mod sleep;
+pub mod spi;
+pub mod system_event;
+pub mod watchdog;
+pub mod xtensa;
+
+/// FFI call recorded by the simulated chip
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Call {
+    /// Name of the called idf function
+    pub function: &'static str,
+    /// Integer arguments of the call. Pointer arguments are omitted
+    pub args: Vec<i64>,
+}
+
+impl Call {
+    pub fn new(function: &'static str, args: &[i64]) -> Self {
+        Self { function, args: args.to_vec() }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Chip {
+    calls: Vec<Call>,
+    time_us: u64,
+    injected_errors: Vec<(&'static str, esp_err_t)>,
+
+    pub(crate) adc: adc::AdcState,
+    pub(crate) esp_timer: esp_timer::EspTimerState,
+    pub(crate) gpio: gpio::GpioState,
+    pub(crate) hw_timer: hw_timer::HwTimerState,
+    pub(crate) i2c: i2c::I2cState,
+    pub(crate) pwm: pwm::PwmState,
+    pub(crate) spi: spi::SpiState,
+    pub(crate) uart: uart::UartState,
+    pub(crate) nvs: nvs::NvsState,
+    pub(crate) wifi: wifi::WiFiState,
+    pub(crate) system_event: system_event::SystemEventState,
+}
+
+std::thread_local! {
+    static CHIP: RefCell<Chip> = RefCell::new(Chip::default());
+}
+
+/// Provides access to the simulated chip of the current thread.
+///
+/// Closure should not call user code, because it could re-enter the mock
+pub(crate) fn with_chip<F, R>(f: F) -> R
+    where F: FnOnce(&mut Chip) -> R
+{
+    CHIP.with(|chip| f(&mut chip.borrow_mut()))
+}
+
+/// Records FFI call and returns error injected for it with
+/// [inject_error](fn.inject_error.html), or `ESP_OK`
+pub(crate) fn record(function: &'static str, args: &[i64]) -> esp_err_t {
+    with_chip(|chip| {
+        chip.calls.push(Call::new(function, args));
+
+        match chip.injected_errors.iter().position(|(f, _)| *f == function) {
+            Some(index) => chip.injected_errors.remove(index).1,
+            None => esp_err_t_ESP_OK,
+        }
+    })
+}
+
+/// Returns all FFI calls recorded since the last [reset](fn.reset.html) or
+/// [clear_calls](fn.clear_calls.html)
+pub fn calls() -> Vec<Call> {
+    with_chip(|chip| chip.calls.clone())
+}
+
+/// Returns recorded calls of the specific FFI function
+pub fn calls_of(function: &str) -> Vec<Call> {
+    with_chip(|chip| chip.calls.iter().filter(|c| c.function == function).cloned().collect())
+}
+
+/// Clears recorded calls, keeping the simulated hardware state
+pub fn clear_calls() {
+    with_chip(|chip| chip.calls.clear());
+}
+
+/// Makes next call of `function` fail with `error` without touching the simulated hardware.
+/// Can be called several times to fail several consecutive calls
+pub fn inject_error(function: &'static str, error: esp_err_t) {
+    with_chip(|chip| chip.injected_errors.push((function, error)));
+}
+
+/// Returns time of the simulated clock in microseconds. Clock is advanced only by simulated
+/// delays and [advance_time](fn.advance_time.html)
+pub fn time_us() -> u64 {
+    with_chip(|chip| chip.time_us)
+}
+
+/// Advances simulated clock
+pub fn advance_time(us: u64) {
+    with_chip(|chip| chip.time_us += us);
+}
+
+/// Resets simulated chip of the current thread to the power-on state
+pub fn reset() {
+    with_chip(|chip| *chip = Chip::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use gpio::*;
+
+    #[test]
+    fn ffi_calls_are_recorded_in_order() {
+        unsafe {
+            gpio_set_direction(4, gpio_mode_t_GPIO_MODE_OUTPUT);
+            gpio_set_level(4, 1);
+        }
+
+        assert_eq!(calls(), [
+            Call::new("gpio_set_direction", &[4, gpio_mode_t_GPIO_MODE_OUTPUT as i64]),
+            Call::new("gpio_set_level", &[4, 1]),
+        ]);
+        assert_eq!(calls_of("gpio_set_level"), [Call::new("gpio_set_level", &[4, 1])]);
+
+        clear_calls();
+        assert!(calls().is_empty());
+        assert!(pin_state(4).output_level);
+    }
+
+    #[test]
+    fn injected_error_fails_only_next_call() {
+        inject_error("gpio_set_level", esp_err_t_ESP_FAIL);
+
+        unsafe {
+            gpio_set_direction(4, gpio_mode_t_GPIO_MODE_OUTPUT);
+            assert_eq!(gpio_set_level(4, 1), esp_err_t_ESP_FAIL);
+            assert!(!pin_state(4).output_level);
+
+            assert_eq!(gpio_set_level(4, 1), esp_err_t_ESP_OK);
+            assert!(pin_state(4).output_level);
+        }
+        assert_eq!(calls_of("gpio_set_level").len(), 2);
+    }
+
+    #[test]
+    fn reset_restores_power_on_state() {
+        unsafe { gpio_set_level(4, 1) };
+        advance_time(100);
+        inject_error("gpio_set_level", esp_err_t_ESP_FAIL);
+
+        reset();
+
+        assert!(calls().is_empty());
+        assert_eq!(time_us(), 0);
+        assert!(!pin_state(4).output_level);
+        assert_eq!(unsafe { gpio_set_level(4, 1) }, esp_err_t_ESP_OK);
+    }
+
+    #[test]
+    fn simulated_delays_advance_clock() {
+        unsafe {
+            rom::ets_delay_us(30);
+            freertos::vTaskDelay(2);
+        }
+        advance_time(5);
+
+        assert_eq!(time_us(), 30 + 2 * 10_000 + 5);
+    }
+
+    #[test]
+    fn threads_have_separate_chips() {
+        unsafe { gpio_set_level(4, 1) };
+
+        thread::spawn(|| {
+            assert!(calls().is_empty());
+            assert!(!pin_state(4).output_level);
+            unsafe { gpio_set_level(5, 1) };
+        }).join().unwrap();
+
+        assert!(pin_state(4).output_level);
+        assert!(!pin_state(5).output_level);
+    }
+}
@@ -0,0 +1,5 @@
+use super::record;
+
+pub unsafe fn tcpip_adapter_init() {
+    record("tcpip_adapter_init", &[]);
+}
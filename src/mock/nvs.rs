@@ -0,0 +1,47 @@
+//! Simulated NVS flash.
+//!
+//! The HAL only initializes and erases the default partition (e.g. before WiFi is started), and
+//! doesn't expose the NVS key API, so only the partition state is simulated: there is no
+//! key/value store to inspect.
+use super::{
+    record, with_chip,
+    error::*,
+};
+
+#[derive(Default)]
+pub(crate) struct NvsState {
+    initialized: bool,
+    erase_count: usize,
+}
+
+pub unsafe fn nvs_flash_init() -> esp_err_t {
+    let err = record("nvs_flash_init", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.nvs.initialized = true);
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn nvs_flash_erase() -> esp_err_t {
+    let err = record("nvs_flash_erase", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| {
+        chip.nvs.initialized = false;
+        chip.nvs.erase_count += 1;
+    });
+    esp_err_t_ESP_OK
+}
+
+pub fn is_initialized() -> bool {
+    with_chip(|chip| chip.nvs.initialized)
+}
+
+/// Returns how many times default partition has been erased
+pub fn erase_count() -> usize {
+    with_chip(|chip| chip.nvs.erase_count)
+}
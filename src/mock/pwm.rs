@@ -0,0 +1,195 @@
+use super::{
+    record, with_chip,
+    error::*,
+};
+
+pub(crate) const MAX_PWM_CHANNELS: usize = 8;
+
+/// Snapshot of the simulated pwm controller
+#[derive(Copy, Clone, Default, Debug)]
+pub struct PwmState {
+    pub initialized: bool,
+    pub running: bool,
+    pub period: u32,
+    pub channel_count: u8,
+    pub pins: [u32; MAX_PWM_CHANNELS],
+    pub duties: [u32; MAX_PWM_CHANNELS],
+    pub phases: [i16; MAX_PWM_CHANNELS],
+    pub inverted_mask: u16,
+    pub stop_level_mask: u32,
+}
+
+fn checked_channel(channel_num: u8) -> Result<usize, esp_err_t> {
+    with_chip(|chip| {
+        if !chip.pwm.initialized {
+            Err(esp_err_t_ESP_ERR_INVALID_STATE)
+        } else if channel_num >= chip.pwm.channel_count {
+            Err(esp_err_t_ESP_ERR_INVALID_ARG)
+        } else {
+            Ok(channel_num as usize)
+        }
+    })
+}
+
+pub unsafe fn pwm_init(
+    period: u32,
+    duties: *const u32,
+    channel_num: u8,
+    pin_num: *const u32,
+) -> esp_err_t {
+    let err = record("pwm_init", &[period as i64, channel_num as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if channel_num as usize > MAX_PWM_CHANNELS || period < 10 {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    let duties = core::slice::from_raw_parts(duties, channel_num as usize);
+    let pins = core::slice::from_raw_parts(pin_num, channel_num as usize);
+
+    with_chip(|chip| {
+        chip.pwm = PwmState::default();
+        chip.pwm.initialized = true;
+        chip.pwm.period = period;
+        chip.pwm.channel_count = channel_num;
+        chip.pwm.duties[..duties.len()].copy_from_slice(duties);
+        chip.pwm.pins[..pins.len()].copy_from_slice(pins);
+    });
+
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn pwm_deinit() -> esp_err_t {
+    let err = record("pwm_deinit", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.pwm = PwmState::default());
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn pwm_set_period(period: u32) -> esp_err_t {
+    let err = record("pwm_set_period", &[period as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if period < 10 {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| chip.pwm.period = period);
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn pwm_get_period(period_p: *mut u32) -> esp_err_t {
+    let err = record("pwm_get_period", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    *period_p = with_chip(|chip| chip.pwm.period);
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn pwm_set_duty(channel_num: u8, duty: u32) -> esp_err_t {
+    let err = record("pwm_set_duty", &[channel_num as i64, duty as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    match checked_channel(channel_num) {
+        Ok(channel) => {
+            with_chip(|chip| chip.pwm.duties[channel] = duty);
+            esp_err_t_ESP_OK
+        }
+        Err(err) => err,
+    }
+}
+
+pub unsafe fn pwm_get_duty(channel_num: u8, duty_p: *mut u32) -> esp_err_t {
+    let err = record("pwm_get_duty", &[channel_num as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    match checked_channel(channel_num) {
+        Ok(channel) => {
+            *duty_p = with_chip(|chip| chip.pwm.duties[channel]);
+            esp_err_t_ESP_OK
+        }
+        Err(err) => err,
+    }
+}
+
+pub unsafe fn pwm_set_phase(channel_num: u8, phase: i16) -> esp_err_t {
+    let err = record("pwm_set_phase", &[channel_num as i64, phase as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    match checked_channel(channel_num) {
+        Ok(channel) => {
+            with_chip(|chip| chip.pwm.phases[channel] = phase);
+            esp_err_t_ESP_OK
+        }
+        Err(err) => err,
+    }
+}
+
+pub unsafe fn pwm_set_channel_invert(channel_mask: u16) -> esp_err_t {
+    let err = record("pwm_set_channel_invert", &[channel_mask as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.pwm.inverted_mask |= channel_mask);
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn pwm_clear_channel_invert(channel_mask: u16) -> esp_err_t {
+    let err = record("pwm_clear_channel_invert", &[channel_mask as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.pwm.inverted_mask &= !channel_mask);
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn pwm_start() -> esp_err_t {
+    let err = record("pwm_start", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| {
+        if chip.pwm.initialized {
+            chip.pwm.running = true;
+            esp_err_t_ESP_OK
+        } else {
+            esp_err_t_ESP_ERR_INVALID_STATE
+        }
+    })
+}
+
+pub unsafe fn pwm_stop(stop_level_mask: u32) -> esp_err_t {
+    let err = record("pwm_stop", &[stop_level_mask as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| {
+        chip.pwm.running = false;
+        chip.pwm.stop_level_mask = stop_level_mask;
+    });
+    esp_err_t_ESP_OK
+}
+
+/// Returns snapshot of the simulated pwm controller
+pub fn state() -> PwmState {
+    with_chip(|chip| chip.pwm)
+}
@@ -0,0 +1,19 @@
+use super::{
+    advance_time, record, with_chip,
+    hw_timer::nmi_callback_t,
+};
+
+pub unsafe fn ets_delay_us(us: u32) {
+    advance_time(us as u64);
+}
+
+/// Simulated CPU runs at 80 MHz
+pub unsafe fn ets_get_cpu_frequency() -> u32 {
+    80
+}
+
+/// Routes FRC1 timer interrupt to NMI and sets its handler. `None` restores the regular interrupt
+pub unsafe fn NmiTimSetFunc(func: nmi_callback_t) {
+    record("NmiTimSetFunc", &[func.is_some() as i64]);
+    with_chip(|chip| chip.hw_timer.nmi_handler = func);
+}
@@ -0,0 +1,12 @@
+use super::record;
+
+/// Message of the panic, which simulates the chip entering the deep sleep
+pub const DEEP_SLEEP_PANIC_MESSAGE: &str = "simulated chip entered deep sleep";
+
+/// Chip never returns from the deep sleep - it is reset instead. Simulated chip panics with
+/// [DEEP_SLEEP_PANIC_MESSAGE](constant.DEEP_SLEEP_PANIC_MESSAGE.html), so tests can catch it
+/// and check recorded calls
+pub unsafe fn esp_deep_sleep(time_in_us: u64) -> ! {
+    record("esp_deep_sleep", &[time_in_us as i64]);
+    panic!("{}", DEEP_SLEEP_PANIC_MESSAGE);
+}
@@ -0,0 +1,398 @@
+//! Simulated HSPI controller.
+//!
+//! In master mode a single slave device is connected to the controller. Slave is selected while
+//! GPIO15 (HSPI CS) is low: it receives bytes sent by the master and replies with bytes queued by
+//! [push_miso](fn.push_miso.html). Unselected slave doesn't drive MISO, so the master reads `0xFF`
+//! from the pulled up line. Slave shifts bytes MSB first, so the bytes sent and received by the
+//! master with LSB first bit order are bit-reversed. Data lines, which are not enabled in the
+//! interface configuration, are not connected: slave doesn't receive bytes without MOSI and the
+//! master reads `0xFF` without MISO.
+//!
+//! In slave mode frames of the ESP SPI slave protocol are sent by the simulated master with
+//! [master_write_buffer](fn.master_write_buffer.html) and similar functions, which call the event
+//! callback like the interrupt handler of the driver.
+use std::{
+    collections::VecDeque,
+    vec::Vec,
+};
+
+use super::{
+    record, with_chip,
+    error::*,
+    ffi::*,
+};
+
+pub type spi_host_t = xtensa_uint;
+pub const spi_host_t_CSPI_HOST: spi_host_t = 0;
+pub const spi_host_t_HSPI_HOST: spi_host_t = 1;
+
+pub type spi_clk_div_t = xtensa_uint;
+pub const spi_clk_div_t_SPI_2MHz_DIV: spi_clk_div_t = 40;
+pub const spi_clk_div_t_SPI_4MHz_DIV: spi_clk_div_t = 20;
+pub const spi_clk_div_t_SPI_5MHz_DIV: spi_clk_div_t = 16;
+pub const spi_clk_div_t_SPI_8MHz_DIV: spi_clk_div_t = 10;
+pub const spi_clk_div_t_SPI_10MHz_DIV: spi_clk_div_t = 8;
+pub const spi_clk_div_t_SPI_16MHz_DIV: spi_clk_div_t = 5;
+pub const spi_clk_div_t_SPI_20MHz_DIV: spi_clk_div_t = 4;
+pub const spi_clk_div_t_SPI_40MHz_DIV: spi_clk_div_t = 2;
+pub const spi_clk_div_t_SPI_80MHz_DIV: spi_clk_div_t = 1;
+
+pub type spi_mode_t = xtensa_uint;
+pub const spi_mode_t_SPI_MASTER_MODE: spi_mode_t = 0;
+pub const spi_mode_t_SPI_SLAVE_MODE: spi_mode_t = 1;
+
+pub type spi_event_t = xtensa_uint;
+pub const spi_event_t_SPI_INIT_EVENT: spi_event_t = 0;
+pub const spi_event_t_SPI_TRANS_START_EVENT: spi_event_t = 1;
+pub const spi_event_t_SPI_TRANS_DONE_EVENT: spi_event_t = 2;
+pub const spi_event_t_SPI_DEINIT_EVENT: spi_event_t = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union spi_interface_t {
+    pub val: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union spi_intr_enable_t {
+    pub val: u32,
+}
+
+pub type spi_event_callback_t = Option<unsafe extern "C" fn(event: xtensa_int, arg: *mut xtensa_void)>;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct spi_config_t {
+    pub interface: spi_interface_t,
+    pub intr_enable: spi_intr_enable_t,
+    pub event_cb: spi_event_callback_t,
+    pub mode: spi_mode_t,
+    pub clk_div: spi_clk_div_t,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union spi_trans_t__bindgen_ty_1 {
+    pub val: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct spi_trans_t {
+    pub cmd: *mut u16,
+    pub addr: *mut u32,
+    pub mosi: *mut u32,
+    pub miso: *mut u32,
+    pub bits: spi_trans_t__bindgen_ty_1,
+}
+
+// Bits of `spi_interface_t`
+const INTERFACE_CPOL: u32 = 1 << 0;
+const INTERFACE_CPHA: u32 = 1 << 1;
+const INTERFACE_BIT_TX_ORDER: u32 = 1 << 2;
+const INTERFACE_BIT_RX_ORDER: u32 = 1 << 3;
+const INTERFACE_BYTE_TX_ORDER: u32 = 1 << 4;
+const INTERFACE_BYTE_RX_ORDER: u32 = 1 << 5;
+const INTERFACE_MOSI_EN: u32 = 1 << 6;
+const INTERFACE_MISO_EN: u32 = 1 << 7;
+const INTERFACE_CS_EN: u32 = 1 << 8;
+
+const HSPI_CS_PIN: usize = 15;
+const MAX_DATA_BITS: u32 = 512;
+const SLAVE_BUFFER_BYTES: usize = 32;
+
+// Bits of the `SPI_TRANS_DONE_EVENT` status
+const SLAVE_READ_BUFFER_DONE: u32 = 1 << 0;
+const SLAVE_WRITE_BUFFER_DONE: u32 = 1 << 1;
+const SLAVE_READ_STATUS_DONE: u32 = 1 << 2;
+const SLAVE_WRITE_STATUS_DONE: u32 = 1 << 3;
+const TRANS_DONE: u32 = 1 << 4;
+
+/// Decoded `spi_interface_t` of the initialized controller
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SpiInterface {
+    pub cpol: bool,
+    pub cpha: bool,
+    /// Bits are sent LSB first
+    pub bit_tx_lsb_first: bool,
+    /// Bits are received LSB first
+    pub bit_rx_lsb_first: bool,
+    /// Bytes of the words are sent in the big endian order
+    pub byte_tx_big_endian: bool,
+    /// Bytes of the words are received in the big endian order
+    pub byte_rx_big_endian: bool,
+    pub mosi_en: bool,
+    pub miso_en: bool,
+    /// Hardware CS on GPIO15 is driven by the controller
+    pub cs_en: bool,
+}
+
+impl SpiInterface {
+    fn decode(val: u32) -> Self {
+        Self {
+            cpol: val & INTERFACE_CPOL != 0,
+            cpha: val & INTERFACE_CPHA != 0,
+            bit_tx_lsb_first: val & INTERFACE_BIT_TX_ORDER != 0,
+            bit_rx_lsb_first: val & INTERFACE_BIT_RX_ORDER != 0,
+            byte_tx_big_endian: val & INTERFACE_BYTE_TX_ORDER != 0,
+            byte_rx_big_endian: val & INTERFACE_BYTE_RX_ORDER != 0,
+            mosi_en: val & INTERFACE_MOSI_EN != 0,
+            miso_en: val & INTERFACE_MISO_EN != 0,
+            cs_en: val & INTERFACE_CS_EN != 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SpiState {
+    config: Option<spi_config_t>,
+    mosi: Vec<u8>,
+    miso: VecDeque<u8>,
+    slave: SlaveState,
+}
+
+/// Registers of the controller in slave mode
+#[derive(Default)]
+struct SlaveState {
+    /// Data written by the master
+    rx_buffer: [u8; SLAVE_BUFFER_BYTES],
+    /// Data read by the master
+    tx_buffer: [u8; SLAVE_BUFFER_BYTES],
+    address: u32,
+    /// Status written by the master
+    rx_status: u32,
+    /// Status read by the master
+    tx_status: u32,
+}
+
+fn data_bits(bits: u32, offset: u32) -> u32 {
+    (bits >> offset) & 0x3FF
+}
+
+fn words(bits: u32) -> usize {
+    (bits as usize).div_ceil(32)
+}
+
+pub unsafe fn spi_init(host: spi_host_t, config: *mut spi_config_t) -> esp_err_t {
+    let config = *config;
+
+    let err = record("spi_init", &[
+        host as i64,
+        config.mode as i64,
+        config.clk_div as i64,
+        config.interface.val as i64,
+    ]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if host != spi_host_t_HSPI_HOST {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| {
+        chip.spi = SpiState::default();
+        chip.spi.config = Some(config);
+    });
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn spi_deinit(host: spi_host_t) -> esp_err_t {
+    let err = record("spi_deinit", &[host as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.spi = SpiState::default());
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn spi_trans(host: spi_host_t, trans: *mut spi_trans_t) -> esp_err_t {
+    let trans = &mut *trans;
+    let mosi_bits = data_bits(trans.bits.val, 12);
+    let miso_bits = data_bits(trans.bits.val, 22);
+
+    let err = record("spi_trans", &[host as i64, mosi_bits as i64, miso_bits as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if host != spi_host_t_HSPI_HOST || mosi_bits > MAX_DATA_BITS || miso_bits > MAX_DATA_BITS {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| {
+        match chip.spi.config {
+            None => return esp_err_t_ESP_ERR_INVALID_STATE,
+            Some(config) if config.mode == spi_mode_t_SPI_SLAVE_MODE => {
+                return slave_trans(&mut chip.spi.slave, trans, mosi_bits, miso_bits);
+            }
+            Some(_) => {}
+        }
+
+        let interface = SpiInterface::decode(chip.spi.config.map_or(0, |config| config.interface.val));
+        let selected = !chip.gpio.pins[HSPI_CS_PIN].level();
+        if !selected {
+            if !trans.miso.is_null() {
+                core::slice::from_raw_parts_mut(trans.miso, words(miso_bits)).fill(u32::MAX);
+            }
+            return esp_err_t_ESP_OK;
+        }
+
+        if !trans.mosi.is_null() && interface.mosi_en {
+            let words = core::slice::from_raw_parts(trans.mosi, words(mosi_bits));
+            let bytes = words.iter().flat_map(|word| word.to_le_bytes().to_vec());
+            let bytes = bytes.map(|byte| if interface.bit_tx_lsb_first { byte.reverse_bits() } else { byte });
+            chip.spi.mosi.extend(bytes.take(mosi_bits as usize / 8));
+        }
+
+        // Slave shifts out a byte for each clocked byte, even if the master doesn't read it
+        let clocked_bytes = (mosi_bits.max(miso_bits) / 8) as usize;
+        let mut miso = [0xFF; MAX_DATA_BITS as usize / 8];
+        for byte in miso[..clocked_bytes].iter_mut() {
+            let sent = chip.spi.miso.pop_front().unwrap_or(0xFF);
+            *byte = match (interface.miso_en, interface.bit_rx_lsb_first) {
+                (false, _) => 0xFF,
+                (true, false) => sent,
+                (true, true) => sent.reverse_bits(),
+            };
+        }
+
+        if !trans.miso.is_null() {
+            let words = core::slice::from_raw_parts_mut(trans.miso, words(miso_bits));
+            for (word, bytes) in words.iter_mut().zip(miso.chunks(4)) {
+                *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+        }
+
+        esp_err_t_ESP_OK
+    })
+}
+
+/// Slave mode transaction loads the transmit buffer and reads the receive buffer
+unsafe fn slave_trans(slave: &mut SlaveState, trans: &mut spi_trans_t, mosi_bits: u32, miso_bits: u32)
+    -> esp_err_t
+{
+    let mosi_bytes = mosi_bits as usize / 8;
+    let miso_bytes = miso_bits as usize / 8;
+    if mosi_bytes > SLAVE_BUFFER_BYTES || miso_bytes > SLAVE_BUFFER_BYTES {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    if !trans.addr.is_null() {
+        *trans.addr = slave.address;
+    }
+
+    if !trans.mosi.is_null() {
+        let words = core::slice::from_raw_parts(trans.mosi, words(mosi_bits));
+        let bytes = words.iter().flat_map(|word| word.to_le_bytes().to_vec());
+        for (target, byte) in slave.tx_buffer[..mosi_bytes].iter_mut().zip(bytes) {
+            *target = byte;
+        }
+    }
+
+    if !trans.miso.is_null() {
+        let words = core::slice::from_raw_parts_mut(trans.miso, words(miso_bits));
+        for (word, bytes) in words.iter_mut().zip(slave.rx_buffer.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn spi_slave_get_status(host: spi_host_t, status: *mut u32) -> esp_err_t {
+    let err = record("spi_slave_get_status", &[host as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    *status = with_chip(|chip| chip.spi.slave.rx_status);
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn spi_slave_set_status(host: spi_host_t, status: *mut u32) -> esp_err_t {
+    let status = *status;
+
+    let err = record("spi_slave_set_status", &[host as i64, status as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.spi.slave.tx_status = status);
+    esp_err_t_ESP_OK
+}
+
+/// Calls the event callback for the completed slave frame, if its interrupt is enabled
+fn dispatch_slave_event(done: u32) {
+    let (callback, enabled) = with_chip(|chip| {
+        let config = chip.spi.config.expect("SPI is not initialized");
+        assert!(config.mode == spi_mode_t_SPI_SLAVE_MODE, "SPI is not in slave mode");
+        (config.event_cb, unsafe { config.intr_enable.val })
+    });
+
+    // Chip should not be borrowed here - callback could call the mock again
+    if let Some(callback) = callback {
+        if enabled & done != 0 {
+            let mut status = done | TRANS_DONE;
+            unsafe {
+                callback(spi_event_t_SPI_TRANS_DONE_EVENT as xtensa_int, &mut status as *mut u32 as *mut xtensa_void)
+            };
+        }
+    }
+}
+
+/// Simulated master writes the status of the slave
+pub fn master_write_status(status: u32) {
+    with_chip(|chip| chip.spi.slave.rx_status = status);
+    dispatch_slave_event(SLAVE_WRITE_STATUS_DONE);
+}
+
+/// Simulated master reads the status of the slave
+pub fn master_read_status() -> u32 {
+    let status = with_chip(|chip| chip.spi.slave.tx_status);
+    dispatch_slave_event(SLAVE_READ_STATUS_DONE);
+    status
+}
+
+/// Simulated master writes the receive buffer of the slave
+pub fn master_write_buffer(address: u32, data: &[u8]) {
+    assert!(data.len() <= SLAVE_BUFFER_BYTES, "Data doesn't fit into the slave buffer");
+    with_chip(|chip| {
+        chip.spi.slave.address = address;
+        chip.spi.slave.rx_buffer[..data.len()].copy_from_slice(data);
+    });
+    dispatch_slave_event(SLAVE_WRITE_BUFFER_DONE);
+}
+
+/// Simulated master reads `len` bytes of the transmit buffer of the slave
+pub fn master_read_buffer(address: u32, len: usize) -> Vec<u8> {
+    assert!(len <= SLAVE_BUFFER_BYTES, "Slave buffer is shorter than requested");
+    let data = with_chip(|chip| {
+        chip.spi.slave.address = address;
+        chip.spi.slave.tx_buffer[..len].to_vec()
+    });
+    dispatch_slave_event(SLAVE_READ_BUFFER_DONE);
+    data
+}
+
+/// Queues bytes which are sent by the simulated slave in the following transfers
+pub fn push_miso(data: &[u8]) {
+    with_chip(|chip| chip.spi.miso.extend(data.iter()));
+}
+
+/// Takes all bytes received by the simulated slave
+pub fn take_mosi() -> Vec<u8> {
+    with_chip(|chip| core::mem::take(&mut chip.spi.mosi))
+}
+
+/// Returns configuration of the initialized controller
+pub fn config() -> Option<spi_config_t> {
+    with_chip(|chip| chip.spi.config)
+}
+
+/// Returns decoded interface configuration of the initialized controller
+pub fn interface() -> Option<SpiInterface> {
+    config().map(|config| SpiInterface::decode(unsafe { config.interface.val }))
+}
@@ -0,0 +1,107 @@
+use super::{
+    record, with_chip,
+    error::*,
+    ffi::*,
+    wifi::wifi_err_reason_t,
+};
+
+pub type system_event_id_t = xtensa_uint;
+pub const system_event_id_t_SYSTEM_EVENT_WIFI_READY: system_event_id_t = 0;
+pub const system_event_id_t_SYSTEM_EVENT_SCAN_DONE: system_event_id_t = 1;
+pub const system_event_id_t_SYSTEM_EVENT_STA_START: system_event_id_t = 2;
+pub const system_event_id_t_SYSTEM_EVENT_STA_STOP: system_event_id_t = 3;
+pub const system_event_id_t_SYSTEM_EVENT_STA_CONNECTED: system_event_id_t = 4;
+pub const system_event_id_t_SYSTEM_EVENT_STA_DISCONNECTED: system_event_id_t = 5;
+pub const system_event_id_t_SYSTEM_EVENT_STA_AUTHMODE_CHANGE: system_event_id_t = 6;
+pub const system_event_id_t_SYSTEM_EVENT_STA_GOT_IP: system_event_id_t = 7;
+pub const system_event_id_t_SYSTEM_EVENT_STA_LOST_IP: system_event_id_t = 8;
+pub const system_event_id_t_SYSTEM_EVENT_AP_START: system_event_id_t = 13;
+pub const system_event_id_t_SYSTEM_EVENT_AP_STOP: system_event_id_t = 14;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct system_event_sta_disconnected_t {
+    pub ssid: [u8; 32],
+    pub ssid_len: u8,
+    pub bssid: [u8; 6],
+    pub reason: u8,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union system_event_info_t {
+    pub disconnected: system_event_sta_disconnected_t,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct system_event_t {
+    pub event_id: system_event_id_t,
+    pub event_info: system_event_info_t,
+}
+
+impl system_event_t {
+    /// Creates event without additional info
+    pub fn new(event_id: system_event_id_t) -> Self {
+        Self {
+            event_id,
+            event_info: system_event_info_t {
+                disconnected: system_event_sta_disconnected_t {
+                    ssid: [0; 32],
+                    ssid_len: 0,
+                    bssid: [0; 6],
+                    reason: 0,
+                }
+            }
+        }
+    }
+}
+
+pub type system_event_cb_t = Option<
+    unsafe extern "C" fn(ctx: *mut xtensa_void, event: *mut system_event_t) -> esp_err_t
+>;
+
+pub(crate) struct SystemEventState {
+    handler: system_event_cb_t,
+    ctx: *mut xtensa_void,
+}
+
+impl Default for SystemEventState {
+    fn default() -> Self {
+        Self { handler: None, ctx: core::ptr::null_mut() }
+    }
+}
+
+pub unsafe fn esp_event_loop_init(cb: system_event_cb_t, ctx: *mut xtensa_void) -> esp_err_t {
+    let err = record("esp_event_loop_init", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| {
+        if chip.system_event.handler.is_some() {
+            esp_err_t_ESP_FAIL
+        } else {
+            chip.system_event.handler = cb;
+            chip.system_event.ctx = ctx;
+            esp_err_t_ESP_OK
+        }
+    })
+}
+
+/// Delivers event to the handler registered with `esp_event_loop_init`.
+///
+/// Returns handler result, or `None` if no event loop has been initialized
+pub fn dispatch_event(mut event: system_event_t) -> Option<esp_err_t> {
+    let (handler, ctx) = with_chip(|chip| (chip.system_event.handler, chip.system_event.ctx));
+
+    // Chip should not be borrowed here - handler could call the mock again
+    handler.map(|handler| unsafe { handler(ctx, &mut event) })
+}
+
+/// Delivers `SYSTEM_EVENT_STA_DISCONNECTED` with the given reason to the event loop
+pub fn dispatch_sta_disconnected(reason: wifi_err_reason_t) -> Option<esp_err_t> {
+    let mut event = system_event_t::new(system_event_id_t_SYSTEM_EVENT_STA_DISCONNECTED);
+    event.event_info.disconnected.reason = reason as u8;
+    dispatch_event(event)
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::VecDeque,
+    vec::Vec,
+};
+
+use super::{
+    record, with_chip,
+    error::*,
+    ffi::*,
+};
+
+pub use super::freertos::TickType_t;
+pub type QueueHandle_t = *mut xtensa_void;
+
+pub type uart_port_t = xtensa_uint;
+pub const uart_port_t_UART_NUM_0: uart_port_t = 0;
+pub const uart_port_t_UART_NUM_1: uart_port_t = 1;
+
+pub type uart_word_length_t = xtensa_uint;
+pub const uart_word_length_t_UART_DATA_5_BITS: uart_word_length_t = 0;
+pub const uart_word_length_t_UART_DATA_6_BITS: uart_word_length_t = 1;
+pub const uart_word_length_t_UART_DATA_7_BITS: uart_word_length_t = 2;
+pub const uart_word_length_t_UART_DATA_8_BITS: uart_word_length_t = 3;
+
+pub type uart_parity_t = xtensa_uint;
+pub const uart_parity_t_UART_PARITY_DISABLE: uart_parity_t = 0;
+pub const uart_parity_t_UART_PARITY_EVEN: uart_parity_t = 2;
+pub const uart_parity_t_UART_PARITY_ODD: uart_parity_t = 3;
+
+pub type uart_stop_bits_t = xtensa_uint;
+pub const uart_stop_bits_t_UART_STOP_BITS_1: uart_stop_bits_t = 1;
+pub const uart_stop_bits_t_UART_STOP_BITS_1_5: uart_stop_bits_t = 2;
+pub const uart_stop_bits_t_UART_STOP_BITS_2: uart_stop_bits_t = 3;
+
+pub type uart_hw_flowcontrol_t = xtensa_uint;
+pub const uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_DISABLE: uart_hw_flowcontrol_t = 0;
+pub const uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_RTS: uart_hw_flowcontrol_t = 1;
+pub const uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS: uart_hw_flowcontrol_t = 2;
+pub const uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS_RTS: uart_hw_flowcontrol_t = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct uart_config_t {
+    pub baud_rate: xtensa_int,
+    pub data_bits: uart_word_length_t,
+    pub parity: uart_parity_t,
+    pub stop_bits: uart_stop_bits_t,
+    pub flow_ctrl: uart_hw_flowcontrol_t,
+    pub rx_flow_ctrl_thresh: u8,
+}
+
+pub(crate) const UART_PORT_COUNT: usize = 2;
+
+#[derive(Default)]
+pub(crate) struct UartPortState {
+    config: Option<uart_config_t>,
+    driver_installed: bool,
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+}
+
+#[derive(Default)]
+pub(crate) struct UartState {
+    pub(crate) ports: [UartPortState; UART_PORT_COUNT],
+}
+
+fn with_port<F, R>(uart_num: uart_port_t, f: F) -> Option<R>
+    where F: FnOnce(&mut UartPortState) -> R
+{
+    if uart_num as usize >= UART_PORT_COUNT {
+        return None;
+    }
+
+    Some(with_chip(|chip| f(&mut chip.uart.ports[uart_num as usize])))
+}
+
+pub unsafe fn uart_param_config(uart_num: uart_port_t, config: *const uart_config_t)
+    -> esp_err_t
+{
+    let config = *config;
+
+    let err = record("uart_param_config", &[
+        uart_num as i64,
+        config.baud_rate as i64,
+        config.data_bits as i64,
+        config.parity as i64,
+        config.stop_bits as i64,
+        config.flow_ctrl as i64,
+        config.rx_flow_ctrl_thresh as i64,
+    ]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_port(uart_num, |port| port.config = Some(config))
+        .map_or(esp_err_t_ESP_ERR_INVALID_ARG, |_| esp_err_t_ESP_OK)
+}
+
+pub unsafe fn uart_driver_install(
+    uart_num: uart_port_t,
+    rx_buffer_size: isize,
+    tx_buffer_size: isize,
+    queue_size: xtensa_int,
+    _uart_queue: *mut QueueHandle_t,
+) -> esp_err_t {
+    let err = record("uart_driver_install", &[
+        uart_num as i64, rx_buffer_size as i64, tx_buffer_size as i64, queue_size as i64
+    ]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_port(uart_num, |port| {
+        if port.driver_installed {
+            esp_err_t_ESP_FAIL
+        } else {
+            port.driver_installed = true;
+            esp_err_t_ESP_OK
+        }
+    }).unwrap_or(esp_err_t_ESP_ERR_INVALID_ARG)
+}
+
+pub unsafe fn uart_driver_delete(uart_num: uart_port_t) -> esp_err_t {
+    let err = record("uart_driver_delete", &[uart_num as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_port(uart_num, |port| {
+        port.driver_installed = false;
+        port.rx.clear();
+    }).map_or(esp_err_t_ESP_ERR_INVALID_ARG, |_| esp_err_t_ESP_OK)
+}
+
+pub unsafe fn uart_write_bytes(uart_num: uart_port_t, src: *const xtensa_char, size: usize)
+    -> xtensa_int
+{
+    record("uart_write_bytes", &[uart_num as i64, size as i64]);
+
+    let data = core::slice::from_raw_parts(src, size);
+    with_port(uart_num, |port| {
+        if port.driver_installed {
+            port.tx.extend_from_slice(data);
+            size as xtensa_int
+        } else {
+            -1
+        }
+    }).unwrap_or(-1)
+}
+
+pub unsafe fn uart_wait_tx_done(uart_num: uart_port_t, ticks_to_wait: TickType_t) -> esp_err_t {
+    let err = record("uart_wait_tx_done", &[uart_num as i64, ticks_to_wait as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    // Simulated transmission is instant
+    with_port(uart_num, |port| {
+        if port.driver_installed { esp_err_t_ESP_OK } else { esp_err_t_ESP_FAIL }
+    }).unwrap_or(esp_err_t_ESP_ERR_INVALID_ARG)
+}
+
+pub unsafe fn uart_read_bytes(
+    uart_num: uart_port_t,
+    buf: *mut u8,
+    length: u32,
+    ticks_to_wait: TickType_t,
+) -> xtensa_int {
+    record("uart_read_bytes", &[uart_num as i64, length as i64, ticks_to_wait as i64]);
+
+    let buffer = core::slice::from_raw_parts_mut(buf, length as usize);
+    with_port(uart_num, |port| {
+        if !port.driver_installed {
+            return -1;
+        }
+
+        let mut read = 0;
+        while read < buffer.len() {
+            match port.rx.pop_front() {
+                Some(byte) => buffer[read] = byte,
+                None => break,
+            }
+            read += 1;
+        }
+        read as xtensa_int
+    }).unwrap_or(-1)
+}
+
+/// Puts bytes to the receive FIFO of the simulated port
+pub fn push_rx(uart_num: uart_port_t, data: &[u8]) {
+    with_port(uart_num, |port| port.rx.extend(data.iter())).expect("Invalid uart port");
+}
+
+/// Takes all bytes transmitted by the simulated port
+pub fn take_tx(uart_num: uart_port_t) -> Vec<u8> {
+    with_port(uart_num, |port| core::mem::take(&mut port.tx)).expect("Invalid uart port")
+}
+
+/// Returns last configuration applied to the simulated port
+pub fn config(uart_num: uart_port_t) -> Option<uart_config_t> {
+    with_port(uart_num, |port| port.config).expect("Invalid uart port")
+}
+
+pub fn is_driver_installed(uart_num: uart_port_t) -> bool {
+    with_port(uart_num, |port| port.driver_installed).expect("Invalid uart port")
+}
@@ -0,0 +1,5 @@
+use super::{record, error::esp_err_t};
+
+pub unsafe fn esp_task_wdt_reset() -> esp_err_t {
+    record("esp_task_wdt_reset", &[])
+}
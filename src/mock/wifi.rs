@@ -0,0 +1,350 @@
+use super::{
+    record, with_chip,
+    error::*,
+    ffi::*,
+    system_event::*,
+};
+
+pub type wifi_mode_t = xtensa_uint;
+pub const wifi_mode_t_WIFI_MODE_NULL: wifi_mode_t = 0;
+pub const wifi_mode_t_WIFI_MODE_STA: wifi_mode_t = 1;
+pub const wifi_mode_t_WIFI_MODE_AP: wifi_mode_t = 2;
+pub const wifi_mode_t_WIFI_MODE_APSTA: wifi_mode_t = 3;
+
+pub type esp_interface_t = xtensa_uint;
+pub const esp_interface_t_ESP_IF_WIFI_STA: esp_interface_t = 0;
+pub const esp_interface_t_ESP_IF_WIFI_AP: esp_interface_t = 1;
+
+pub type wifi_auth_mode_t = xtensa_uint;
+pub const wifi_auth_mode_t_WIFI_AUTH_OPEN: wifi_auth_mode_t = 0;
+pub const wifi_auth_mode_t_WIFI_AUTH_WEP: wifi_auth_mode_t = 1;
+pub const wifi_auth_mode_t_WIFI_AUTH_WPA_PSK: wifi_auth_mode_t = 2;
+pub const wifi_auth_mode_t_WIFI_AUTH_WPA2_PSK: wifi_auth_mode_t = 3;
+pub const wifi_auth_mode_t_WIFI_AUTH_WPA_WPA2_PSK: wifi_auth_mode_t = 4;
+pub const wifi_auth_mode_t_WIFI_AUTH_WPA2_ENTERPRISE: wifi_auth_mode_t = 5;
+
+pub type wifi_scan_method_t = xtensa_uint;
+pub const wifi_scan_method_t_WIFI_FAST_SCAN: wifi_scan_method_t = 0;
+pub const wifi_scan_method_t_WIFI_ALL_CHANNEL_SCAN: wifi_scan_method_t = 1;
+
+pub type wifi_sort_method_t = xtensa_uint;
+pub const wifi_sort_method_t_WIFI_CONNECT_AP_BY_SIGNAL: wifi_sort_method_t = 0;
+pub const wifi_sort_method_t_WIFI_CONNECT_AP_BY_SECURITY: wifi_sort_method_t = 1;
+
+pub type wifi_err_reason_t = xtensa_uint;
+pub const wifi_err_reason_t_WIFI_REASON_UNSPECIFIED: wifi_err_reason_t = 1;
+pub const wifi_err_reason_t_WIFI_REASON_AUTH_EXPIRE: wifi_err_reason_t = 2;
+pub const wifi_err_reason_t_WIFI_REASON_BEACON_TIMEOUT: wifi_err_reason_t = 200;
+pub const wifi_err_reason_t_WIFI_REASON_NO_AP_FOUND: wifi_err_reason_t = 201;
+pub const wifi_err_reason_t_WIFI_REASON_AUTH_FAIL: wifi_err_reason_t = 202;
+pub const wifi_err_reason_t_WIFI_REASON_ASSOC_FAIL: wifi_err_reason_t = 203;
+pub const wifi_err_reason_t_WIFI_REASON_HANDSHAKE_TIMEOUT: wifi_err_reason_t = 204;
+pub const wifi_err_reason_t_WIFI_REASON_BASIC_RATE_NOT_SUPPORT: wifi_err_reason_t = 205;
+
+pub const WIFI_PROTOCOL_11B: u32 = 1;
+pub const WIFI_PROTOCOL_11G: u32 = 2;
+pub const WIFI_PROTOCOL_11N: u32 = 4;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct wifi_scan_threshold_t {
+    pub rssi: i8,
+    pub authmode: wifi_auth_mode_t,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct wifi_ap_config_t {
+    pub ssid: [u8; 32],
+    pub password: [u8; 64],
+    pub ssid_len: u8,
+    pub channel: u8,
+    pub authmode: wifi_auth_mode_t,
+    pub ssid_hidden: u8,
+    pub max_connection: u8,
+    pub beacon_interval: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct wifi_sta_config_t {
+    pub ssid: [u8; 32],
+    pub password: [u8; 64],
+    pub scan_method: wifi_scan_method_t,
+    pub bssid_set: bool,
+    pub bssid: [u8; 6],
+    pub channel: u8,
+    pub listen_interval: u16,
+    pub sort_method: wifi_sort_method_t,
+    pub threshold: wifi_scan_threshold_t,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union wifi_config_t {
+    pub ap: wifi_ap_config_t,
+    pub sta: wifi_sta_config_t,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct wifi_init_config_t {
+    pub magic: xtensa_int,
+}
+
+pub const WIFI_INIT_CONFIG_MAGIC: xtensa_int = 0x1F2F3F4F;
+
+#[allow(non_snake_case)]
+pub fn WIFI_INIT_CONFIG_DEFAULT() -> wifi_init_config_t {
+    wifi_init_config_t { magic: WIFI_INIT_CONFIG_MAGIC }
+}
+
+/// Snapshot of the simulated WiFi adapter
+#[derive(Copy, Clone, Default)]
+pub struct WiFiState {
+    pub initialized: bool,
+    pub started: bool,
+    pub connected: bool,
+    pub mode: wifi_mode_t,
+    pub ap_config: Option<wifi_ap_config_t>,
+    pub sta_config: Option<wifi_sta_config_t>,
+    /// Protocol bitmap of STA and AP interfaces
+    pub protocols: [u8; 2],
+}
+
+fn mode_has_interface(mode: wifi_mode_t, interface: esp_interface_t) -> bool {
+    match interface {
+        esp_interface_t_ESP_IF_WIFI_STA => {
+            mode == wifi_mode_t_WIFI_MODE_STA || mode == wifi_mode_t_WIFI_MODE_APSTA
+        }
+        esp_interface_t_ESP_IF_WIFI_AP => {
+            mode == wifi_mode_t_WIFI_MODE_AP || mode == wifi_mode_t_WIFI_MODE_APSTA
+        }
+        _ => false,
+    }
+}
+
+/// Runs `f` if WiFi is initialized, otherwise returns `ESP_ERR_WIFI_NOT_INIT`
+fn with_initialized_wifi<F>(f: F) -> esp_err_t
+    where F: FnOnce(&mut WiFiState) -> esp_err_t
+{
+    with_chip(|chip| {
+        if chip.wifi.initialized {
+            f(&mut chip.wifi)
+        } else {
+            esp_err_t_ESP_ERR_WIFI_NOT_INIT
+        }
+    })
+}
+
+pub unsafe fn esp_wifi_init(config: *const wifi_init_config_t) -> esp_err_t {
+    let err = record("esp_wifi_init", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if (*config).magic != WIFI_INIT_CONFIG_MAGIC {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_chip(|chip| {
+        chip.wifi = WiFiState::default();
+        chip.wifi.initialized = true;
+        chip.wifi.protocols = [
+            (WIFI_PROTOCOL_11B | WIFI_PROTOCOL_11G | WIFI_PROTOCOL_11N) as u8;
+            2
+        ];
+    });
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn esp_wifi_deinit() -> esp_err_t {
+    let err = record("esp_wifi_deinit", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_chip(|chip| chip.wifi = WiFiState::default());
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn esp_wifi_set_mode(mode: wifi_mode_t) -> esp_err_t {
+    let err = record("esp_wifi_set_mode", &[mode as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if mode > wifi_mode_t_WIFI_MODE_APSTA {
+        return esp_err_t_ESP_ERR_INVALID_ARG;
+    }
+
+    with_initialized_wifi(|wifi| {
+        wifi.mode = mode;
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn esp_wifi_get_mode(mode: *mut wifi_mode_t) -> esp_err_t {
+    let err = record("esp_wifi_get_mode", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_initialized_wifi(|wifi| {
+        *mode = wifi.mode;
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn esp_wifi_set_config(interface: esp_interface_t, conf: *mut wifi_config_t)
+    -> esp_err_t
+{
+    let err = record("esp_wifi_set_config", &[interface as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let conf = *conf;
+    with_initialized_wifi(|wifi| {
+        if !mode_has_interface(wifi.mode, interface) {
+            return esp_err_t_ESP_ERR_WIFI_MODE;
+        }
+
+        match interface {
+            esp_interface_t_ESP_IF_WIFI_STA => wifi.sta_config = Some(conf.sta),
+            _ => wifi.ap_config = Some(conf.ap),
+        }
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn esp_wifi_set_protocol(interface: esp_interface_t, protocol_bitmap: u8)
+    -> esp_err_t
+{
+    let err = record("esp_wifi_set_protocol", &[interface as i64, protocol_bitmap as i64]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    with_initialized_wifi(|wifi| {
+        if !mode_has_interface(wifi.mode, interface) {
+            return esp_err_t_ESP_ERR_WIFI_IF;
+        }
+
+        wifi.protocols[interface as usize] = protocol_bitmap;
+        esp_err_t_ESP_OK
+    })
+}
+
+pub unsafe fn esp_wifi_start() -> esp_err_t {
+    let err = record("esp_wifi_start", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let mut mode = wifi_mode_t_WIFI_MODE_NULL;
+    let err = with_initialized_wifi(|wifi| {
+        wifi.started = true;
+        mode = wifi.mode;
+        esp_err_t_ESP_OK
+    });
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if mode_has_interface(mode, esp_interface_t_ESP_IF_WIFI_STA) {
+        dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_STA_START));
+    }
+    if mode_has_interface(mode, esp_interface_t_ESP_IF_WIFI_AP) {
+        dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_AP_START));
+    }
+
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn esp_wifi_stop() -> esp_err_t {
+    let err = record("esp_wifi_stop", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let mut mode = wifi_mode_t_WIFI_MODE_NULL;
+    let err = with_initialized_wifi(|wifi| {
+        wifi.started = false;
+        wifi.connected = false;
+        mode = wifi.mode;
+        esp_err_t_ESP_OK
+    });
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if mode_has_interface(mode, esp_interface_t_ESP_IF_WIFI_STA) {
+        dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_STA_STOP));
+    }
+    if mode_has_interface(mode, esp_interface_t_ESP_IF_WIFI_AP) {
+        dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_AP_STOP));
+    }
+
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn esp_wifi_connect() -> esp_err_t {
+    let err = record("esp_wifi_connect", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let err = with_initialized_wifi(|wifi| {
+        if !wifi.started {
+            esp_err_t_ESP_ERR_WIFI_NOT_STARTED
+        } else if !mode_has_interface(wifi.mode, esp_interface_t_ESP_IF_WIFI_STA) {
+            esp_err_t_ESP_ERR_WIFI_MODE
+        } else if wifi.sta_config.is_none() {
+            esp_err_t_ESP_ERR_WIFI_SSID
+        } else {
+            wifi.connected = true;
+            esp_err_t_ESP_OK
+        }
+    });
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_STA_CONNECTED));
+    esp_err_t_ESP_OK
+}
+
+pub unsafe fn esp_wifi_disconnect() -> esp_err_t {
+    let err = record("esp_wifi_disconnect", &[]);
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    let mut was_connected = false;
+    let err = with_initialized_wifi(|wifi| {
+        was_connected = wifi.connected;
+        wifi.connected = false;
+        esp_err_t_ESP_OK
+    });
+    if err != esp_err_t_ESP_OK {
+        return err;
+    }
+
+    if was_connected {
+        dispatch_sta_disconnected(wifi_err_reason_t_WIFI_REASON_UNSPECIFIED);
+    }
+    esp_err_t_ESP_OK
+}
+
+/// Returns snapshot of the simulated WiFi adapter
+pub fn state() -> WiFiState {
+    with_chip(|chip| chip.wifi)
+}
+
+/// Simulates connection loss: marks station as disconnected and delivers
+/// `SYSTEM_EVENT_STA_DISCONNECTED` with the given reason to the event loop
+pub fn drop_connection(reason: wifi_err_reason_t) {
+    with_chip(|chip| chip.wifi.connected = false);
+    dispatch_sta_disconnected(reason);
+}
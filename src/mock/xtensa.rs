@@ -0,0 +1,7 @@
+use super::{rom::ets_get_cpu_frequency, time_us};
+
+/// Returns CPU cycle counter derived from the simulated clock. Counter wraps around like the
+/// real 32-bit CCOUNT register
+pub unsafe fn xthal_get_ccount() -> u32 {
+    (time_us() * ets_get_cpu_frequency() as u64) as u32
+}
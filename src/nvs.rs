@@ -1,4 +1,4 @@
-use idf_sys::{
+use crate::sys::{
     nvs::*,
     error::*,
 };
@@ -71,4 +71,45 @@ impl Nvs {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::mock;
+    use super::*;
+
+    #[test]
+    fn default_partition_is_initialized_and_erased() {
+        let mut nvs = Nvs::init(NvsPeripherals {});
+
+        let partition = nvs.init_partition(PartitionId::default()).ok().unwrap();
+        assert!(mock::nvs::is_initialized());
+        nvs.deinit_partition(partition);
+
+        assert!(nvs.erase_partition(PartitionId::default()).is_ok());
+        assert!(!mock::nvs::is_initialized());
+        assert_eq!(mock::nvs::erase_count(), 1);
+    }
+
+    #[test]
+    fn idf_errors_are_mapped() {
+        let mut nvs = Nvs::init(NvsPeripherals {});
+
+        mock::inject_error("nvs_flash_init", esp_err_t_ESP_ERR_NVS_NO_FREE_PAGES);
+        let result = nvs.init_partition(PartitionId::default());
+        assert!(matches!(result, Err(NvsError::PartitionCorrupted)));
+
+        mock::inject_error("nvs_flash_init", esp_err_t_ESP_ERR_NVS_NOT_FOUND);
+        let result = nvs.init_partition(PartitionId::default());
+        assert!(matches!(result, Err(NvsError::PartitionNotFound)));
+
+        mock::inject_error("nvs_flash_init", esp_err_t_ESP_FAIL);
+        let result = nvs.init_partition(PartitionId::default());
+        assert!(matches!(result, Err(NvsError::IdfError(esp_err_t_ESP_FAIL))));
+        assert!(!mock::nvs::is_initialized());
+
+        mock::inject_error("nvs_flash_erase", esp_err_t_ESP_ERR_NVS_NOT_FOUND);
+        let result = nvs.erase_partition(PartitionId::default());
+        assert!(matches!(result, Err(NvsError::PartitionNotFound)));
+        assert_eq!(mock::nvs::erase_count(), 0);
+    }
+}
@@ -1,9 +1,9 @@
 //! This module provides access to `Peripherals` struct, which can be
 //! used to get access to owned peripherals instance.
 //!
-//! **NOTE:** In the current implementation
-//! [Peripherals::take()](struct.Peripherals.html#method.take) is not hread-safe. Please avoid
-//! calling this method after from multiple threads until issue will be fixed
+//! [Peripherals::take()](struct.Peripherals.html#method.take) is guarded by a critical section,
+//! so it is safe to call it concurrently from several tasks - only one of them will receive
+//! the peripherals.
 //!
 //! # Examples:
 //! ```rust
@@ -15,7 +15,7 @@
 //! ```
 use core::marker::PhantomData;
 
-// TODO: Implement atomic singleton when atomics will be available in LLVM-rs
+use crate::critical_section;
 
 /// Represents owned wifi peripherals
 #[non_exhaustive]
@@ -32,6 +32,14 @@ pub struct UartPeripherals {}
 #[non_exhaustive]
 pub struct NvsPeripherals {}
 
+/// Represents owned adc peripherals
+#[non_exhaustive]
+pub struct AdcPeripherals {}
+
+/// Represents owned FRC1 hardware timer
+#[non_exhaustive]
+pub struct HwTimerPeripherals {}
+
 /// Represents owned idf peripherals. Can be deconstructed on the parts with the public fields
 /// for more granular access
 pub struct OwnedPeripherals {
@@ -40,16 +48,18 @@ pub struct OwnedPeripherals {
     pub gpio: GpioPeripherals,
     pub uart: UartPeripherals,
     pub nvs: NvsPeripherals,
+    pub adc: AdcPeripherals,
+    pub hw_timer: HwTimerPeripherals,
 
     _data : PhantomData<()>,
 }
 
 /// Provides access to IDF peripherals
-pub struct Peripherals {
-    data: Option<OwnedPeripherals>,
-}
+#[non_exhaustive]
+pub struct Peripherals;
 
-static mut PERIPHERALS_SINGLETON : Peripherals = Peripherals::new();
+// Should only be accessed inside of the critical section
+static mut PERIPHERALS_TAKEN : bool = false;
 
 impl OwnedPeripherals {
     const fn new() -> OwnedPeripherals {
@@ -58,24 +68,62 @@ impl OwnedPeripherals {
             gpio: GpioPeripherals {},
             uart: UartPeripherals {},
             nvs: NvsPeripherals {},
+            adc: AdcPeripherals {},
+            hw_timer: HwTimerPeripherals {},
             _data: PhantomData,
         }
     }
 }
 
 impl Peripherals {
-    const fn new() -> Peripherals {
-        Peripherals {
-            data: Some(OwnedPeripherals::new()),
-        }
-    }
-
     /// Owns idf peripherals
     /// returns [OwnedPeripherals](struct.OwnedPeripherals.html) on success or `None` if peripherals
     /// were already taken
     pub fn take() -> Option<OwnedPeripherals> {
-        unsafe {
-            PERIPHERALS_SINGLETON.data.take()
-        }
+        critical_section::free(|| unsafe {
+            if PERIPHERALS_TAKEN {
+                None
+            } else {
+                PERIPHERALS_TAKEN = true;
+                Some(OwnedPeripherals::new())
+            }
+        })
+    }
+
+    /// Returns peripherals regardless of whether they were already taken. Subsequent calls to
+    /// [take](#method.take) will return `None`.
+    ///
+    /// Intended for code paths which can't receive peripherals from the application, such as
+    /// interrupt or panic handlers.
+    ///
+    /// # Safety
+    /// Caller must ensure that peripheral parts obtained this way are not used concurrently with
+    /// the parts owned by the rest of the application
+    pub unsafe fn steal() -> OwnedPeripherals {
+        critical_section::free(|| PERIPHERALS_TAKEN = true);
+        OwnedPeripherals::new()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::{thread, vec::Vec};
+
+    use super::*;
+
+    #[test]
+    fn take_returns_peripherals_only_once() {
+        const THREADS: usize = 8;
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| thread::spawn(|| Peripherals::take().is_some()))
+            .collect();
+        let taken = threads.into_iter()
+            .map(|thread| thread.join().unwrap())
+            .filter(|taken| *taken)
+            .count();
+
+        assert_eq!(taken, 1);
+        assert!(Peripherals::take().is_none());
     }
 }
@@ -1,73 +1,190 @@
+//! PWM driver.
+//!
+//! SDK generates PWM on up to 8 pins with the common period. Period and duties are measured in
+//! microseconds, so the duty of the channel has `period_us + 1` distinct values. Frequency and
+//! [Duty](struct.Duty.html) in relative units are converted to the nearest raw values.
+//!
+//! Type of the [PwmInitializer](struct.PwmInitializer.html) and [Pwm](struct.Pwm.html) tracks
+//! pins of the added channels, so each channel is controlled through its own typed
+//! [PwmChannel](struct.PwmChannel.html) handle instead of the index.
+//!
+//! Channels can be smoothly faded to the new duties with the
+//! [FadeInitializer](struct.FadeInitializer.html).
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     pwm::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut pwm = PwmInitializer::new()
+//!     .add_channel_with_duty(gpio.gpio4.take().unwrap(), Duty::from_percent(25).ok().unwrap())
+//!     .add_channel(gpio.gpio5.take().unwrap(), 0)
+//!     .set_frequency(1000)
+//!     .ok().unwrap()
+//!     .initialize()
+//!     .ok().unwrap();
+//! pwm.start();
+//!
+//! // 1 kHz is achieved exactly with 1000 us period
+//! assert_eq!(pwm.period_us(), 1000);
+//!
+//! let (mut red, mut green): (PwmChannel<Gpio4>, PwmChannel<Gpio5>) = pwm.channels();
+//! red.set_relative_duty(Duty::from_fraction(0.75).ok().unwrap()).ok().unwrap();
+//! green.set_inverted(true).set_phase(90).ok().unwrap();
+//! ```
+use alloc::boxed::Box;
+use core::{
+    cell::Cell,
+    marker::PhantomData,
+};
+
+mod fade;
+pub use fade::*;
+
 use crate::gpio::*;
 
-use idf_sys::{
+use crate::sys::{
     error::*,
     pwm::*
 };
 
 const MAX_PWM_CHANNELS : usize = 8;
+const MIN_PERIOD_US: u32 = 10;
+const US_PER_SECOND: u32 = 1_000_000;
+const FRACTION_DENOMINATOR: u32 = 1_000_000;
 
 #[derive(Copy, Clone)]
-struct PwmChannel {
+struct ChannelConfig {
     pin: PinId,
     duty: u32,
+    relative_duty: Option<Duty>,
+    release_pin: fn(&mut GpioHardware),
 }
 
+fn release_nothing(_gpio_hw: &mut GpioHardware) {}
+
 #[derive(Copy, Clone)]
 pub enum PwmInitializationError {
-    TooManyChannels,
     TooShortPeriod,
     DutyExceedsPeriod,
     PeriodNotSet,
+    /// Frequency should be in 1..=100000 Hz range
+    InvalidFrequency,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum PwmConfigurationError {
     InvalidChannel,
     TooShortPeriod,
     DutyExceedsPeriod,
     InvalidPhase,
+    /// Frequency should be in 1..=100000 Hz range
+    InvalidFrequency,
+    /// Relative duty should be in 0..=1 range
+    InvalidDuty,
 }
 
-pub struct PwmConfiguration {
-    pub(crate) channel_count: u8,
-    pub(crate) period: u32,
-    pub(crate) stop_level: u8,
+/// Duty cycle relative to the period, from 0 (always low) to 1 (always high)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Duty {
+    numerator: u32,
+    denominator: u32,
 }
 
-impl PwmConfiguration {
-    fn assert_channel(&self, channel: u8) -> Result<(), PwmConfigurationError> {
-        if channel >= self.channel_count {
-            Err(PwmConfigurationError::InvalidChannel)
+impl Duty {
+    /// Output is always low
+    pub const OFF: Duty = Duty { numerator: 0, denominator: 1 };
+    /// Output is always high
+    pub const FULL: Duty = Duty { numerator: 1, denominator: 1 };
+
+    /// Duty of `numerator / denominator`
+    pub fn from_ratio(numerator: u32, denominator: u32) -> Result<Self, PwmConfigurationError> {
+        if denominator == 0 || numerator > denominator {
+            Err(PwmConfigurationError::InvalidDuty)
         } else {
-            Ok(())
+            Ok(Self { numerator, denominator })
         }
     }
 
-    pub fn set_stop_level(&mut self, channel: u8, level: bool)
-        -> Result<&mut Self, PwmConfigurationError>
-    {
-        self.assert_channel(channel)?;
+    /// Duty in 0..=100 percent range
+    pub fn from_percent(percent: u8) -> Result<Self, PwmConfigurationError> {
+        Self::from_ratio(percent as u32, 100)
+    }
 
-        let bit_mask = 1u8 << channel;
-        let bit_value = (level as u8) << channel;
+    /// Duty in 0..=1000 permille range
+    pub fn from_permille(permille: u16) -> Result<Self, PwmConfigurationError> {
+        Self::from_ratio(permille as u32, 1000)
+    }
 
-        self.stop_level &= !bit_mask;
-        self.stop_level |= bit_value;
+    /// Duty in 0.0..=1.0 range with 1 ppm precision
+    pub fn from_fraction(fraction: f32) -> Result<Self, PwmConfigurationError> {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(PwmConfigurationError::InvalidDuty);
+        }
 
-        Ok(self)
+        let numerator = (fraction * FRACTION_DENOMINATOR as f32 + 0.5) as u32;
+        Self::from_ratio(numerator.min(FRACTION_DENOMINATOR), FRACTION_DENOMINATOR)
     }
 
-    pub fn set_inverted_state(&mut self, channel: u8, is_inverted: bool)
-                          -> Result<&mut Self, PwmConfigurationError>
-    {
-        self.assert_channel(channel)?;
-        if is_inverted {
-            unsafe { pwm_set_channel_invert(1u16 << channel as u16) };
-        } else {
-            unsafe { pwm_clear_channel_invert(1u16 << channel as u16) };
-        }
-        Ok(self)
+    pub fn as_fraction(&self) -> f32 {
+        self.numerator as f32 / self.denominator as f32
+    }
+
+    /// Raw duty for the `period_us` period, rounded to the nearest microsecond
+    pub fn to_raw(&self, period_us: u32) -> u32 {
+        let numerator = self.numerator as u64 * period_us as u64;
+        let denominator = self.denominator as u64;
+        ((numerator + denominator / 2) / denominator) as u32
+    }
+}
+
+/// Period, which is the nearest to the `frequency_hz`
+fn frequency_to_period(frequency_hz: u32) -> Option<u32> {
+    if frequency_hz == 0 || frequency_hz > US_PER_SECOND / MIN_PERIOD_US {
+        return None;
+    }
+
+    Some((US_PER_SECOND + frequency_hz / 2) / frequency_hz)
+}
+
+fn period_to_frequency(period_us: u32) -> f32 {
+    US_PER_SECOND as f32 / period_us as f32
+}
+
+/// Settings shared by all channels. Channels are configured through their
+/// [PwmChannel](struct.PwmChannel.html) handles
+pub struct PwmConfiguration {
+    pub(crate) channel_count: u8,
+    pub(crate) period: u32,
+    pub(crate) stop_level: Cell<u8>,
+    /// Pwm has been started and not stopped since
+    pub(crate) running: Cell<bool>,
+}
+
+impl PwmConfiguration {
+    /// Period in microseconds, which is also the maximal raw duty
+    pub fn period_us(&self) -> u32 {
+        self.period
+    }
+
+    /// Frequency, which is actually generated with the current period
+    pub fn frequency_hz(&self) -> f32 {
+        period_to_frequency(self.period)
+    }
+
+    /// Sets period, which is the nearest to `frequency_hz`. Raw duties are not changed, so
+    /// relative duties should be set again. Actual frequency is returned by
+    /// [frequency_hz](#method.frequency_hz)
+    pub fn set_frequency(&mut self, frequency_hz: u32) -> Result<&mut Self, PwmConfigurationError> {
+        let period = frequency_to_period(frequency_hz)
+            .ok_or(PwmConfigurationError::InvalidFrequency)?;
+        self.set_period(period)
     }
 
     pub fn set_period(&mut self, period: u32) -> Result<&mut Self, PwmConfigurationError> {
@@ -78,44 +195,91 @@ impl PwmConfiguration {
         self.period = period;
         Ok(self)
     }
+}
 
-    pub fn set_duty(&mut self, channel: u8, duty: u32) -> Result<&mut Self, PwmConfigurationError> {
-        if duty > self.period {
-            return Err(PwmConfigurationError::DutyExceedsPeriod);
-        }
-        if unsafe { pwm_set_duty(channel, duty) } != esp_err_t_ESP_OK {
-            return Err(PwmConfigurationError::InvalidChannel);
-        }
-        Ok(self)
-    }
+/// Pins of the pwm channels in the order they were added, e.g. `(Gpio4, Gpio5)`
+pub trait PwmChannelList {}
 
-    pub fn set_phase(&mut self, channel: u8, phase: i16)
-        -> Result<&mut Self, PwmConfigurationError>
-    {
-        if phase < -180 || phase > 180 {
-            return Err(PwmConfigurationError::InvalidPhase);
-        }
+/// Channel list with one more channel on the `Pin`. Implemented for lists of up to 7 pins
+pub trait PushPwmChannel<Pin>: PwmChannelList {
+    type Output: PwmChannelList;
+}
 
-        if unsafe { pwm_set_phase(channel, phase) } != esp_err_t_ESP_OK {
-            return Err(PwmConfigurationError::InvalidChannel);
+/// Typed handles of the channels in the list
+pub trait PwmChannelHandles<'a>: PwmChannelList {
+    type Handles;
+
+    #[doc(hidden)]
+    fn handles(configuration: &'a PwmConfiguration) -> Self::Handles;
+}
+
+impl PwmChannelList for () {}
+
+impl<Pin> PushPwmChannel<Pin> for () {
+    type Output = (Pin,);
+}
+
+macro_rules! impl_pwm_channel_lists {
+    ($([$($pin:ident : $index:expr),+]),+) => {$(
+        impl<$($pin,)+> PwmChannelList for ($($pin,)+) {}
+
+        impl<'a, $($pin: 'a,)+> PwmChannelHandles<'a> for ($($pin,)+) {
+            type Handles = ($(PwmChannel<'a, $pin>,)+);
+
+            fn handles(configuration: &'a PwmConfiguration) -> Self::Handles {
+                ($(PwmChannel::new(configuration, $index),)+)
+            }
         }
+    )+}
+}
 
-        Ok(self)
-    }
+macro_rules! impl_push_pwm_channel {
+    ($([$($pin:ident),+]),+) => {$(
+        impl<$($pin,)+ Pin> PushPwmChannel<Pin> for ($($pin,)+) {
+            type Output = ($($pin,)+ Pin,);
+        }
+    )+}
 }
 
-pub struct Pwm {
+impl_pwm_channel_lists!(
+    [A: 0],
+    [A: 0, B: 1],
+    [A: 0, B: 1, C: 2],
+    [A: 0, B: 1, C: 2, D: 3],
+    [A: 0, B: 1, C: 2, D: 3, E: 4],
+    [A: 0, B: 1, C: 2, D: 3, E: 4, F: 5],
+    [A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6],
+    [A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7]
+);
+
+impl_push_pwm_channel!(
+    [A],
+    [A, B],
+    [A, B, C],
+    [A, B, C, D],
+    [A, B, C, D, E],
+    [A, B, C, D, E, F],
+    [A, B, C, D, E, F, G]
+);
+
+/// Initialized pwm controller with the channels on the pins of the `Channels` list
+pub struct Pwm<Channels = ()> {
     configuration: PwmConfiguration,
+    channels: Box<[ChannelConfig; MAX_PWM_CHANNELS]>,
+    _channels: PhantomData<Channels>,
 }
 
-impl Pwm {
-    pub(crate) fn new(channel_count: u8, period: u32) -> Self {
+impl<Channels: PwmChannelList> Pwm<Channels> {
+    fn new(channel_count: u8, period: u32, channels: Box<[ChannelConfig; MAX_PWM_CHANNELS]>) -> Self {
         Self {
             configuration: PwmConfiguration {
                 channel_count,
                 period,
-                stop_level: 0,
-            }
+                stop_level: Cell::new(0),
+                running: Cell::new(false),
+            },
+            channels,
+            _channels: PhantomData,
         }
     }
 
@@ -126,54 +290,220 @@ impl Pwm {
         Ok(self)
     }
 
+    pub fn period_us(&self) -> u32 {
+        self.configuration.period_us()
+    }
+
+    /// Frequency, which is actually generated with the current period
+    pub fn frequency_hz(&self) -> f32 {
+        self.configuration.frequency_hz()
+    }
+
+    /// Starts pwm with the current duties, phases and period
     pub fn start(&mut self) -> &mut Self {
         unsafe { pwm_start(); }
+        self.configuration.running.set(true);
         self
     }
 
+    /// Stops pwm, setting channel outputs to their stop levels. Changes of the channels are kept
+    /// until the next [start](#method.start)
     pub fn stop(&mut self) -> &mut Self {
-        unsafe { pwm_stop(self.configuration.stop_level as u32); }
+        unsafe { pwm_stop(self.configuration.stop_level.get() as u32); }
+        self.configuration.running.set(false);
         self
     }
 
-    pub fn deinitialize(mut self) {
+    /// Returns typed handles of all channels in the order they were added, e.g.
+    /// `(PwmChannel<Gpio4>, PwmChannel<Gpio5>)`. Pwm is borrowed by the handles, so there is only
+    /// one handle of each channel and the period can't be changed while they exist
+    pub fn channels<'a>(&'a mut self) -> <Channels as PwmChannelHandles<'a>>::Handles
+        where Channels: PwmChannelHandles<'a>
+    {
+        Channels::handles(&self.configuration)
+    }
+
+    /// Stops pwm, uninstalls the driver and returns channel pins to `gpio_hw`
+    pub fn deinitialize(mut self, gpio_hw: &mut GpioHardware) {
         self.stop();
         unsafe { pwm_deinit() };
+
+        for channel in &self.channels[..self.configuration.channel_count as usize] {
+            (channel.release_pin)(gpio_hw);
+        }
     }
 }
 
-pub struct PwmInitializer {
+/// Handle to the channel on the `Pin` of the initialized [Pwm](struct.Pwm.html).
+///
+/// Changes of the duty, phase and inversion are applied immediately if pwm is running, otherwise
+/// they take effect when pwm is [started](struct.Pwm.html#method.start)
+pub struct PwmChannel<'a, Pin> {
+    configuration: &'a PwmConfiguration,
+    channel: u8,
+    _pin: PhantomData<Pin>,
+}
+
+impl<'a, Pin> PwmChannel<'a, Pin> {
+    fn new(configuration: &'a PwmConfiguration, channel: u8) -> Self {
+        Self { configuration, channel, _pin: PhantomData }
+    }
+
+    /// Index of the channel in the SDK driver
+    pub(crate) fn index(&self) -> u8 {
+        self.channel
+    }
+
+    /// Whether changes of the channel should be applied with `pwm_start`
+    pub(crate) fn is_running(&self) -> bool {
+        self.configuration.running.get()
+    }
+
+    /// SDK applies new duties and phases only when pwm is started again
+    fn apply(&self) {
+        if self.is_running() {
+            unsafe { pwm_start() };
+        }
+    }
+
+    /// Period in microseconds, which is also the maximal raw duty
+    pub fn period(&self) -> u32 {
+        self.configuration.period
+    }
+
+    /// Raw duty in microseconds
+    pub fn duty(&self) -> u32 {
+        let mut duty = 0;
+        unsafe { pwm_get_duty(self.channel, &mut duty) };
+        duty
+    }
+
+    /// Sets raw duty in microseconds
+    pub fn set_duty(&mut self, duty: u32) -> Result<&mut Self, PwmConfigurationError> {
+        if duty > self.configuration.period {
+            return Err(PwmConfigurationError::DutyExceedsPeriod);
+        }
+        if unsafe { pwm_set_duty(self.channel, duty) } != esp_err_t_ESP_OK {
+            return Err(PwmConfigurationError::InvalidChannel);
+        }
+
+        self.apply();
+        Ok(self)
+    }
+
+    /// Sets duty relative to the period
+    pub fn set_relative_duty(&mut self, duty: Duty) -> Result<&mut Self, PwmConfigurationError> {
+        self.set_duty(duty.to_raw(self.period()))
+    }
+
+    /// Shifts the channel by `phase` degrees in -180..=180 range
+    pub fn set_phase(&mut self, phase: i16) -> Result<&mut Self, PwmConfigurationError> {
+        if !(-180..=180).contains(&phase) {
+            return Err(PwmConfigurationError::InvalidPhase);
+        }
+        if unsafe { pwm_set_phase(self.channel, phase) } != esp_err_t_ESP_OK {
+            return Err(PwmConfigurationError::InvalidChannel);
+        }
+
+        self.apply();
+        Ok(self)
+    }
+
+    pub fn set_inverted(&mut self, is_inverted: bool) -> &mut Self {
+        let mask = 1u16 << self.channel;
+        unsafe {
+            if is_inverted {
+                pwm_set_channel_invert(mask);
+            } else {
+                pwm_clear_channel_invert(mask);
+            }
+        }
+        self.apply();
+        self
+    }
+
+    /// Level of the channel output while pwm is [stopped](struct.Pwm.html#method.stop)
+    pub fn set_stop_level(&mut self, level: bool) -> &mut Self {
+        let stop_level = &self.configuration.stop_level;
+        let bit_mask = 1u8 << self.channel;
+
+        stop_level.set((stop_level.get() & !bit_mask) | ((level as u8) << self.channel));
+        self
+    }
+}
+
+pub struct PwmInitializer<Channels = ()> {
     channels_count: u8,
-    channels: [PwmChannel; MAX_PWM_CHANNELS],
+    // Boxed to keep the initializer, which is returned back on errors, small
+    channels: Box<[ChannelConfig; MAX_PWM_CHANNELS]>,
     period: Option<u32>,
+    _channels: PhantomData<Channels>,
 }
 
-impl PwmInitializer {
+impl PwmInitializer<()> {
     pub fn new() -> Self {
         Self {
             channels_count: 0,
-            channels: [PwmChannel { pin: 0, duty: 0 }; MAX_PWM_CHANNELS],
+            channels: Box::new([
+                ChannelConfig { pin: 0, duty: 0, relative_duty: None, release_pin: release_nothing };
+                MAX_PWM_CHANNELS
+            ]),
             period: None,
+            _channels: PhantomData,
         }
     }
+}
 
-    pub fn add_channel<Pin : GpioPin + PwmPinMarker>(mut self, _pin: Pin, duty: u32)
-        -> Result<Self, PwmInitializationError>
+impl<Channels: PwmChannelList> PwmInitializer<Channels> {
+    /// Adds pwm channel on the `Pin`. Pin is returned to the `GpioHardware` when pwm is
+    /// [deinitialized](struct.Pwm.html#method.deinitialize)
+    pub fn add_channel<Pin>(self, _pin: Pin, duty: u32) -> PwmInitializer<Channels::Output>
+        where Pin: GpioPin + PwmPinMarker + CaptureGpioPin,
+              Channels: PushPwmChannel<Pin>
     {
-        if self.channels_count < 8 {
-            self.channels[self.channels_count as usize] = PwmChannel {
-                pin: Pin::get_pin_id(),
-                duty
-            };
-            self.channels_count += 1;
-            Ok(self)
-        } else {
-            Err(PwmInitializationError::TooManyChannels)
+        self.push_channel::<Pin>(duty, None)
+    }
+
+    /// Adds pwm channel on the `Pin` with `duty` relative to the period, which is set on
+    /// initialization
+    pub fn add_channel_with_duty<Pin>(self, _pin: Pin, duty: Duty) -> PwmInitializer<Channels::Output>
+        where Pin: GpioPin + PwmPinMarker + CaptureGpioPin,
+              Channels: PushPwmChannel<Pin>
+    {
+        self.push_channel::<Pin>(0, Some(duty))
+    }
+
+    fn push_channel<Pin>(self, duty: u32, relative_duty: Option<Duty>) -> PwmInitializer<Channels::Output>
+        where Pin: GpioPin + CaptureGpioPin,
+              Channels: PushPwmChannel<Pin>
+    {
+        let mut channels = self.channels;
+        channels[self.channels_count as usize] = ChannelConfig {
+            pin: Pin::get_pin_id(),
+            duty,
+            relative_duty,
+            release_pin: Pin::release_pin,
+        };
+
+        PwmInitializer {
+            channels_count: self.channels_count + 1,
+            channels,
+            period: self.period,
+            _channels: PhantomData,
+        }
+    }
+
+    /// Sets period, which is the nearest to `frequency_hz`. Actual frequency is reported by
+    /// initialized [Pwm](struct.Pwm.html#method.frequency_hz)
+    pub fn set_frequency(self, frequency_hz: u32) -> Result<Self, PwmInitializationError> {
+        match frequency_to_period(frequency_hz) {
+            Some(period) => self.set_period(period),
+            None => Err(PwmInitializationError::InvalidFrequency),
         }
     }
 
     pub fn set_period(mut self, period: u32) -> Result<Self, PwmInitializationError> {
-        if period < 10 {
+        if period < MIN_PERIOD_US {
             Err(PwmInitializationError::TooShortPeriod)
         } else {
             self.period = Some(period);
@@ -181,17 +511,21 @@ impl PwmInitializer {
         }
     }
 
-    pub fn initialize(self) -> Result<Pwm, (PwmInitializationError, Self)> {
-        if let None = self.period {
-            return Err((PwmInitializationError::PeriodNotSet, self));
-        }
-
+    /// Initializes pwm. Handles of the channels are returned by
+    /// [Pwm::channels](struct.Pwm.html#method.channels)
+    pub fn initialize(mut self) -> Result<Pwm<Channels>, (PwmInitializationError, Self)> {
+        let period = match self.period {
+            Some(period) => period,
+            None => return Err((PwmInitializationError::PeriodNotSet, self)),
+        };
 
-        let period = self.period.unwrap();
         let mut duties : [u32; MAX_PWM_CHANNELS] = [0; MAX_PWM_CHANNELS];
         let mut pins : [u32; MAX_PWM_CHANNELS] = [0; MAX_PWM_CHANNELS];
 
         for i in 0..MAX_PWM_CHANNELS {
+            if let Some(duty) = self.channels[i].relative_duty {
+                self.channels[i].duty = duty.to_raw(period);
+            }
             if self.channels[i].duty > period {
                 return Err((PwmInitializationError::DutyExceedsPeriod, self));
             }
@@ -202,8 +536,144 @@ impl PwmInitializer {
 
         unsafe { pwm_init(period, duties.as_mut_ptr(), self.channels_count, pins.as_mut_ptr()) };
 
-        Ok(Pwm::new(self.channels_count, period))
+        Ok(Pwm::new(self.channels_count, period, self.channels))
     }
 }
 
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn pwm(gpio: &mut GpioHardware) -> Pwm<(Gpio4, Gpio5)> {
+        PwmInitializer::new()
+            .add_channel_with_duty(gpio.gpio4.take().unwrap(), Duty::from_percent(25).ok().unwrap())
+            .add_channel(gpio.gpio5.take().unwrap(), 7)
+            .set_frequency(3000)
+            .ok().unwrap()
+            .initialize()
+            .ok().unwrap()
+    }
+
+    #[test]
+    fn frequency_is_converted_to_nearest_period() {
+        assert_eq!(frequency_to_period(3000), Some(333));
+        assert_eq!(frequency_to_period(1), Some(1_000_000));
+        assert_eq!(frequency_to_period(100_000), Some(10));
+        assert_eq!(frequency_to_period(0), None);
+        assert_eq!(frequency_to_period(100_001), None);
+    }
+
+    #[test]
+    fn relative_duties_are_rounded_to_nearest_microsecond() {
+        assert_eq!(Duty::from_percent(25).ok().unwrap().to_raw(333), 83);
+        assert_eq!(Duty::from_permille(1).ok().unwrap().to_raw(1500), 2);
+        assert_eq!(Duty::from_fraction(0.5).ok().unwrap().to_raw(1000), 500);
+        assert_eq!(Duty::FULL.to_raw(1000), 1000);
+        assert_eq!(Duty::OFF.to_raw(1000), 0);
+    }
+
+    #[test]
+    fn invalid_duties_are_rejected() {
+        assert!(Duty::from_percent(101).is_err());
+        assert!(Duty::from_permille(1001).is_err());
+        assert!(Duty::from_fraction(f32::NAN).is_err());
+        assert!(Duty::from_fraction(-0.1).is_err());
+        assert!(Duty::from_ratio(1, 0).is_err());
+    }
 
+    #[test]
+    fn initializer_converts_relative_duties() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let pwm = pwm(&mut gpio);
+
+        let state = mock::pwm::state();
+        assert_eq!(pwm.period_us(), 333);
+        assert!((pwm.frequency_hz() - 3003.003).abs() < 0.01);
+        assert_eq!(state.period, 333);
+        assert_eq!(&state.duties[..2], [83, 7]);
+        assert_eq!(&state.pins[..2], [4, 5]);
+        assert!(matches!(
+            PwmInitializer::new().set_frequency(0),
+            Err(PwmInitializationError::InvalidFrequency)
+        ));
+    }
+
+    #[test]
+    fn typed_handles_control_their_channels() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let mut pwm = pwm(&mut gpio);
+        pwm.configure(|configuration| configuration.set_period(10).map(|_| ())).ok().unwrap();
+
+        let (mut first, mut second): (PwmChannel<Gpio4>, PwmChannel<Gpio5>) = pwm.channels();
+        first.set_relative_duty(Duty::FULL).ok().unwrap();
+        assert!(matches!(second.set_duty(11), Err(PwmConfigurationError::DutyExceedsPeriod)));
+        second.set_inverted(true).set_stop_level(true).set_phase(-90).ok().unwrap();
+        assert!(matches!(second.set_phase(181), Err(PwmConfigurationError::InvalidPhase)));
+
+        let state = mock::pwm::state();
+        assert_eq!(first.duty(), 10);
+        assert_eq!(&state.duties[..2], [10, 7]);
+        assert_eq!(state.inverted_mask, 0b10);
+        assert_eq!(&state.phases[..2], [0, -90]);
+
+        pwm.stop();
+        assert_eq!(mock::pwm::state().stop_level_mask, 0b10);
+    }
+
+    #[test]
+    fn channel_changes_are_applied_only_while_running() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let mut pwm = pwm(&mut gpio);
+        pwm.start();
+        {
+            let (mut first, _) = pwm.channels();
+            mock::clear_calls();
+            first.set_duty(100).ok().unwrap();
+            assert_eq!(mock::calls_of("pwm_start").len(), 1);
+        }
+
+        pwm.stop();
+        {
+            let (mut first, mut second) = pwm.channels();
+            first.set_duty(200).ok().unwrap();
+            second.set_phase(90).ok().unwrap().set_inverted(true);
+        }
+
+        assert!(!mock::pwm::state().running);
+        assert_eq!(mock::pwm::state().duties[0], 200);
+    }
+
+    #[test]
+    fn deinitialize_returns_pins() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let pwm = pwm(&mut gpio);
+
+        pwm.deinitialize(&mut gpio);
+
+        assert!(gpio.gpio4.is_some() && gpio.gpio5.is_some());
+        assert!(!mock::pwm::state().initialized);
+    }
+
+    #[cfg(feature = "embedded-hal")]
+    #[test]
+    fn duty_cycle_is_scaled_to_period() {
+        use embedded_hal::pwm::SetDutyCycle;
+
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let mut pwm = PwmInitializer::new()
+            .add_channel(gpio.gpio12.take().unwrap(), 0)
+            .set_period(1000)
+            .ok().unwrap()
+            .initialize()
+            .ok().unwrap();
+        let (mut channel,) = pwm.channels();
+
+        channel.set_duty_cycle_percent(50).unwrap();
+
+        assert_eq!(mock::pwm::state().duties[0], 500);
+    }
+}
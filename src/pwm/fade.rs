@@ -0,0 +1,452 @@
+//! Smooth duty transitions.
+//!
+//! [FadeInitializer](struct.FadeInitializer.html) ramps one or more channels from their current
+//! duties to the target duties over the given duration. Duties are updated by the
+//! [software timer](../timer/struct.Timer.html) in the timer task, so the calling task is not
+//! blocked. Channels are borrowed by the running [Fade](struct.Fade.html) and can't be changed
+//! until it completes or is interrupted.
+//!
+//! # Examples
+//! ```no_run
+//! # use core::time::Duration;
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     pwm::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut pwm = PwmInitializer::new()
+//!     .add_channel(gpio.gpio4.take().unwrap(), 0)
+//!     .set_frequency(1000)
+//!     .ok().unwrap()
+//!     .initialize()
+//!     .ok().unwrap();
+//! pwm.start();
+//! let (mut led,) = pwm.channels();
+//!
+//! let fade = FadeInitializer::new()
+//!     .add_channel(&mut led, Duty::FULL)
+//!     .set_curve(FadeCurve::Gamma)
+//!     .start(Duration::from_secs(2))
+//!     .ok().unwrap();
+//!
+//! // Blocks until the LED is fully on
+//! fade.wait();
+//! ```
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    time::Duration,
+};
+
+use crate::{
+    critical_section,
+    sys::{
+        freertos::vTaskDelay,
+        pwm::*,
+    },
+    time::Instant,
+    timer::{Timer, TimerError},
+};
+use super::{Duty, PwmChannel};
+
+/// Fixed point 1.0 of the fade progress
+const ONE: u64 = 1 << 16;
+
+const DEFAULT_STEP: Duration = Duration::from_millis(10);
+
+type FadeCallback = Box<dyn FnOnce() + Send + 'static>;
+
+/// Shape of the transition from the current duty to the target duty
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FadeCurve {
+    /// Duty changes at the constant rate
+    Linear,
+    /// Perceived LED brightness changes at the constant rate. Brightness is approximated as the
+    /// square root of the duty (gamma 2.0)
+    Gamma,
+    /// Starts slowly and accelerates
+    EaseIn,
+    /// Starts quickly and decelerates
+    EaseOut,
+    /// Accelerates in the first half and decelerates in the second half
+    EaseInOut,
+}
+
+impl FadeCurve {
+    /// Applies easing to the linear `progress` in 0..=ONE range
+    fn ease(self, progress: u64) -> u64 {
+        match self {
+            FadeCurve::Linear | FadeCurve::Gamma => progress,
+            FadeCurve::EaseIn => progress * progress / ONE,
+            FadeCurve::EaseOut => ONE - (ONE - progress) * (ONE - progress) / ONE,
+            FadeCurve::EaseInOut => progress * progress / ONE * (3 * ONE - 2 * progress) / ONE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FadeError {
+    /// Fade should have at least one channel
+    NoChannels,
+    /// Software timer, which drives the fade, can't be started
+    TimerFailed(TimerError),
+}
+
+#[derive(Copy, Clone)]
+struct FadeChannel {
+    channel: u8,
+    period: u32,
+    from: u32,
+    to: u32,
+}
+
+impl FadeChannel {
+    fn duty_at(&self, curve: FadeCurve, progress: u64) -> u32 {
+        let progress = curve.ease(progress) as i64;
+
+        if curve == FadeCurve::Gamma {
+            let from = brightness(self.from, self.period) as i64;
+            let to = brightness(self.to, self.period) as i64;
+            let level = interpolate(from, to, progress) as u64;
+            ((level * level * self.period as u64 + (1 << 31)) >> 32) as u32
+        } else {
+            interpolate(self.from as i64, self.to as i64, progress) as u32
+        }
+    }
+}
+
+/// Value between `from` and `to` at `progress` in 0..=ONE range, rounded to the nearest integer
+fn interpolate(from: i64, to: i64, progress: i64) -> i64 {
+    (from * ONE as i64 + (to - from) * progress + ONE as i64 / 2) / ONE as i64
+}
+
+/// Square root of the duty fraction in 0..=ONE range
+fn brightness(duty: u32, period: u32) -> u64 {
+    isqrt(((duty as u64) << 32) / period.max(1) as u64)
+}
+
+fn isqrt(value: u64) -> u64 {
+    let mut root = 0u64;
+    let mut bit = 1u64 << 62;
+    let mut rest = value;
+
+    while bit > value {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rest >= root + bit {
+            rest -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+struct FadeState {
+    done: bool,
+    callback: Option<FadeCallback>,
+}
+
+/// State shared between the timer task and the fade owner. Accessed only inside critical
+/// sections
+struct FadeShared(UnsafeCell<FadeState>);
+
+// State is accessed only inside critical sections
+unsafe impl Sync for FadeShared {}
+
+impl FadeShared {
+    fn with<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut FadeState) -> R
+    {
+        critical_section::free(|| f(unsafe { &mut *self.0.get() }))
+    }
+
+    fn is_done(&self) -> bool {
+        self.with(|state| state.done)
+    }
+
+    /// Marks the fade as done and returns its callback, which should be called outside of the
+    /// critical section
+    fn complete(&self) -> Option<FadeCallback> {
+        self.with(|state| {
+            state.done = true;
+            state.callback.take()
+        })
+    }
+}
+
+/// Collects channels with their target duties and starts the [Fade](struct.Fade.html)
+pub struct FadeInitializer<'a> {
+    channels: Vec<FadeChannel>,
+    curve: FadeCurve,
+    step: Duration,
+    callback: Option<FadeCallback>,
+    /// Pwm is running, so the updated duties are applied with `pwm_start`
+    running: bool,
+    _channels: PhantomData<&'a mut ()>,
+}
+
+impl<'a> Default for FadeInitializer<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> FadeInitializer<'a> {
+    pub fn new() -> Self {
+        Self {
+            channels: Vec::new(),
+            curve: FadeCurve::Linear,
+            step: DEFAULT_STEP,
+            callback: None,
+            running: false,
+            _channels: PhantomData,
+        }
+    }
+
+    /// Fades `channel` from its current duty to the `target` duty
+    pub fn add_channel<Pin>(mut self, channel: &'a mut PwmChannel<'_, Pin>, target: Duty) -> Self {
+        let period = channel.period();
+        self.channels.push(FadeChannel {
+            channel: channel.index(),
+            period,
+            from: channel.duty().min(period),
+            to: target.to_raw(period),
+        });
+        // Pwm can't be started or stopped while its channels are borrowed
+        self.running = channel.is_running();
+        self
+    }
+
+    /// Default is `FadeCurve::Linear`
+    pub fn set_curve(mut self, curve: FadeCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Interval between duty updates. Default is 10 ms, which is the FreeRTOS tick
+    pub fn set_step(mut self, step: Duration) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Sets `callback`, which is called from the timer task when the fade completes. Callback is
+    /// not called if the fade is interrupted
+    pub fn set_callback<F>(mut self, callback: F) -> Self
+        where F: FnOnce() + Send + 'static
+    {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Starts the fade, which reaches target duties after `duration`. Returns error and
+    /// initializer back if fade can't be started
+    pub fn start(mut self, duration: Duration) -> Result<Fade<'a>, (FadeError, Self)> {
+        if self.channels.is_empty() {
+            return Err((FadeError::NoChannels, self));
+        }
+
+        let shared = Arc::new(FadeShared(UnsafeCell::new(FadeState {
+            done: false,
+            callback: self.callback.take(),
+        })));
+        let timer_shared = shared.clone();
+
+        let channels = self.channels.clone();
+        let curve = self.curve;
+        let running = self.running;
+        let duration_us = duration.as_micros().max(1).min(u64::MAX as u128) as u64;
+        let started = Instant::now();
+
+        let timer = Timer::periodic(self.step.max(Duration::from_micros(1)), move || {
+            let shared = &*timer_shared;
+            if shared.is_done() {
+                return;
+            }
+
+            let elapsed_us = started.elapsed().as_micros().min(duration_us as u128) as u64;
+            let progress = elapsed_us * ONE / duration_us;
+
+            for channel in &channels {
+                let duty = if progress >= ONE { channel.to } else { channel.duty_at(curve, progress) };
+                unsafe { pwm_set_duty(channel.channel, duty) };
+            }
+            if running {
+                unsafe { pwm_start() };
+            }
+
+            if progress >= ONE {
+                if let Some(callback) = shared.complete() {
+                    callback();
+                }
+            }
+        });
+
+        match timer {
+            Ok(timer) => Ok(Fade { _timer: timer, shared, _channels: PhantomData }),
+            Err(err) => {
+                self.callback = shared.with(|state| state.callback.take());
+                Err((FadeError::TimerFailed(err), self))
+            }
+        }
+    }
+}
+
+/// Running fade. Dropping the fade, e.g. with [stop](#method.stop), interrupts it, leaving the
+/// channels at their current duties
+pub struct Fade<'a> {
+    _timer: Timer,
+    shared: Arc<FadeShared>,
+    _channels: PhantomData<&'a mut ()>,
+}
+
+impl<'a> Fade<'a> {
+    /// Returns `true` if the channels have reached their target duties
+    pub fn is_done(&self) -> bool {
+        self.shared.is_done()
+    }
+
+    /// Blocks until the fade completes and releases the channels
+    pub fn wait(self) {
+        while !self.is_done() {
+            unsafe { vTaskDelay(1) };
+        }
+    }
+
+    /// Interrupts the fade and releases the channels
+    pub fn stop(self) {}
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use crate::{
+        gpio::{GpioHardware, Gpio4, Gpio5},
+        mock,
+        peripherals::GpioPeripherals,
+        pwm::{Pwm, PwmInitializer},
+    };
+    use super::*;
+
+    fn pwm() -> Pwm<(Gpio4, Gpio5)> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        PwmInitializer::new()
+            .add_channel(gpio.gpio4.take().unwrap(), 0)
+            .add_channel(gpio.gpio5.take().unwrap(), 1000)
+            .set_period(1000)
+            .ok().unwrap()
+            .initialize()
+            .ok().unwrap()
+    }
+
+    fn duties() -> (u32, u32) {
+        let state = mock::pwm::state();
+        (state.duties[0], state.duties[1])
+    }
+
+    fn fade_halfway(curve: FadeCurve, from: Duty, to: Duty) -> u32 {
+        let mut pwm = pwm();
+        let (mut channel, _) = pwm.channels();
+        channel.set_relative_duty(from).ok().unwrap();
+
+        let _fade = FadeInitializer::new()
+            .add_channel(&mut channel, to)
+            .set_curve(curve)
+            .start(Duration::from_millis(100))
+            .ok().unwrap();
+        mock::esp_timer::advance(50_000);
+        duties().0
+    }
+
+    #[test]
+    fn channels_progress_linearly_and_complete() {
+        let mut pwm = pwm();
+        let (mut first, mut second) = pwm.channels();
+        let completed = Arc::new(AtomicU32::new(0));
+        let callback_completed = completed.clone();
+
+        let fade = FadeInitializer::new()
+            .add_channel(&mut first, Duty::FULL)
+            .add_channel(&mut second, Duty::OFF)
+            .set_callback(move || { callback_completed.fetch_add(1, Ordering::SeqCst); })
+            .start(Duration::from_millis(100))
+            .ok().unwrap();
+
+        mock::esp_timer::advance(20_000);
+        assert_eq!(duties(), (200, 800));
+        assert!(!fade.is_done());
+
+        fade.wait();
+        assert_eq!(duties(), (1000, 0));
+        assert_eq!(completed.load(Ordering::SeqCst), 1);
+
+        mock::esp_timer::advance(100_000);
+        assert_eq!(completed.load(Ordering::SeqCst), 1);
+        assert_eq!(mock::esp_timer::timer_count(), 0);
+    }
+
+    #[test]
+    fn curves_shape_the_transition() {
+        assert_eq!(fade_halfway(FadeCurve::Linear, Duty::OFF, Duty::FULL), 500);
+        // Half of the brightness is a quarter of the duty
+        assert_eq!(fade_halfway(FadeCurve::Gamma, Duty::OFF, Duty::FULL), 250);
+        assert_eq!(fade_halfway(FadeCurve::EaseIn, Duty::OFF, Duty::FULL), 250);
+        assert_eq!(fade_halfway(FadeCurve::EaseOut, Duty::OFF, Duty::FULL), 750);
+        assert_eq!(fade_halfway(FadeCurve::EaseInOut, Duty::FULL, Duty::OFF), 500);
+    }
+
+    #[test]
+    fn stopped_fade_keeps_current_duties_and_skips_callback() {
+        let mut pwm = pwm();
+        let (mut channel, _) = pwm.channels();
+        let completed = Arc::new(AtomicU32::new(0));
+        let callback_completed = completed.clone();
+
+        let fade = FadeInitializer::new()
+            .add_channel(&mut channel, Duty::FULL)
+            .set_callback(move || { callback_completed.fetch_add(1, Ordering::SeqCst); })
+            .start(Duration::from_millis(100))
+            .ok().unwrap();
+        mock::esp_timer::advance(70_000);
+        fade.stop();
+
+        mock::esp_timer::advance(100_000);
+        assert_eq!(duties().0, 700);
+        assert_eq!(completed.load(Ordering::SeqCst), 0);
+        assert_eq!(Arc::strong_count(&completed), 1);
+
+        // Channel is released by the stopped fade
+        channel.set_duty(3).ok().unwrap();
+    }
+
+    #[test]
+    fn fade_without_channels_is_rejected() {
+        assert!(matches!(FadeInitializer::new().start(Duration::from_secs(1)), Err((FadeError::NoChannels, _))));
+    }
+
+    #[test]
+    fn failed_timer_returns_callback() {
+        let mut pwm = pwm();
+        let (mut channel, _) = pwm.channels();
+
+        mock::inject_error("esp_timer_create", crate::sys::error::esp_err_t_ESP_ERR_NO_MEM);
+        let result = FadeInitializer::new()
+            .add_channel(&mut channel, Duty::FULL)
+            .set_callback(|| {})
+            .start(Duration::from_millis(100));
+
+        match result {
+            Err((FadeError::TimerFailed(_), initializer)) => assert!(initializer.callback.is_some()),
+            _ => panic!("fade should not start"),
+        }
+    }
+}
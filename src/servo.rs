@@ -0,0 +1,381 @@
+//! Hobby servo driver.
+//!
+//! [Servo](struct.Servo.html) controls the servo connected to the [pwm](../pwm/index.html)
+//! channel. Servos expect the pulse every 20 ms (50 Hz), pulse width sets the angle. Each servo
+//! has its own [ServoCalibration](struct.ServoCalibration.html) of the pulse range, because the
+//! range differs between the models and even between the servos of the same model.
+//!
+//! Servo can be moved instantly or with the limited speed. Slow motion is driven by the
+//! [fade](../pwm/struct.Fade.html), which runs in the background.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     pwm::*,
+//! #     servo::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut pwm = PwmInitializer::new()
+//!     .add_channel(gpio.gpio14.take().unwrap(), 0)
+//!     .set_frequency(SERVO_FREQUENCY_HZ)
+//!     .ok().unwrap()
+//!     .initialize()
+//!     .ok().unwrap();
+//! pwm.start();
+//! let (channel,) = pwm.channels();
+//!
+//! let mut calibration = ServoCalibration::new();
+//! calibration.set_pulse_range(600, 2400).ok().unwrap();
+//! let mut servo = Servo::new(channel, calibration).ok().unwrap();
+//!
+//! servo.set_angle(90.0).ok().unwrap();
+//! // Turns to 0 degrees in 1.5 seconds
+//! servo.move_to(0.0, 60.0).ok().unwrap().wait();
+//! // Servo doesn't hold the position anymore
+//! servo.detach();
+//! ```
+use core::time::Duration;
+
+use crate::pwm::{
+    Duty, Fade, FadeError, FadeInitializer, PwmChannel, PwmConfigurationError,
+};
+
+/// PWM frequency expected by the most servos
+pub const SERVO_FREQUENCY_HZ: u32 = 50;
+
+const DEFAULT_MIN_PULSE_US: u32 = 500;
+const DEFAULT_MAX_PULSE_US: u32 = 2500;
+const DEFAULT_ANGLE_RANGE: f32 = 180.0;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ServoConfigError {
+    /// Minimal pulse should be positive and shorter than the maximal pulse
+    InvalidPulseRange,
+    /// Angle range should be positive
+    InvalidAngleRange,
+}
+
+#[derive(Debug)]
+pub enum ServoError {
+    /// Maximal pulse of the calibration is longer than the pwm period
+    PulseExceedsPeriod,
+    /// Angle is outside of the calibrated range
+    InvalidAngle,
+    /// Speed should be positive
+    InvalidSpeed,
+    /// Pwm rejected the pulse
+    Pwm(PwmConfigurationError),
+    /// Slow motion can't be started
+    Fade(FadeError),
+}
+
+/// Mapping of the servo angles to the pulse widths. Default is 500 - 2500 us pulses for
+/// 0 - 180 degrees
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ServoCalibration {
+    min_pulse_us: u32,
+    max_pulse_us: u32,
+    angle_range: f32,
+}
+
+impl Default for ServoCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServoCalibration {
+    pub fn new() -> Self {
+        Self {
+            min_pulse_us: DEFAULT_MIN_PULSE_US,
+            max_pulse_us: DEFAULT_MAX_PULSE_US,
+            angle_range: DEFAULT_ANGLE_RANGE,
+        }
+    }
+
+    /// Pulse widths of the 0 degrees and of the maximal angle. Zero pulse is reserved for the
+    /// detached servo
+    pub fn set_pulse_range(&mut self, min_pulse_us: u32, max_pulse_us: u32)
+        -> Result<&mut Self, ServoConfigError>
+    {
+        if min_pulse_us == 0 || min_pulse_us >= max_pulse_us {
+            return Err(ServoConfigError::InvalidPulseRange);
+        }
+
+        self.min_pulse_us = min_pulse_us;
+        self.max_pulse_us = max_pulse_us;
+        Ok(self)
+    }
+
+    /// Maximal angle in degrees, e.g. 270 for the wide range servos
+    pub fn set_angle_range(&mut self, degrees: f32) -> Result<&mut Self, ServoConfigError> {
+        if degrees.is_nan() || degrees <= 0.0 {
+            return Err(ServoConfigError::InvalidAngleRange);
+        }
+
+        self.angle_range = degrees;
+        Ok(self)
+    }
+
+    pub fn min_pulse_us(&self) -> u32 {
+        self.min_pulse_us
+    }
+
+    pub fn max_pulse_us(&self) -> u32 {
+        self.max_pulse_us
+    }
+
+    pub fn angle_range(&self) -> f32 {
+        self.angle_range
+    }
+
+    /// Pulse width of the angle. `None` if angle is outside of the calibrated range
+    pub fn angle_to_pulse_us(&self, degrees: f32) -> Option<u32> {
+        if !(0.0..=self.angle_range).contains(&degrees) {
+            return None;
+        }
+
+        let span = (self.max_pulse_us - self.min_pulse_us) as f32;
+        Some(self.min_pulse_us + (span * degrees / self.angle_range + 0.5) as u32)
+    }
+
+    /// Angle of the pulse width, clamped to the calibrated range
+    pub fn pulse_us_to_angle(&self, pulse_us: u32) -> f32 {
+        let pulse_us = pulse_us.max(self.min_pulse_us).min(self.max_pulse_us);
+        let span = (self.max_pulse_us - self.min_pulse_us) as f32;
+        (pulse_us - self.min_pulse_us) as f32 * self.angle_range / span
+    }
+}
+
+/// Servo connected to the pwm channel on the `Pin`. Pwm frequency should be set to
+/// [SERVO_FREQUENCY_HZ](constant.SERVO_FREQUENCY_HZ.html), unless the servo supports higher
+/// refresh rate
+pub struct Servo<'a, Pin> {
+    channel: PwmChannel<'a, Pin>,
+    calibration: ServoCalibration,
+}
+
+impl<'a, Pin> Servo<'a, Pin> {
+    /// Creates detached servo. Returns error and channel back if the pulses of the calibration
+    /// don't fit into the pwm period
+    pub fn new(channel: PwmChannel<'a, Pin>, calibration: ServoCalibration)
+        -> Result<Self, (ServoError, PwmChannel<'a, Pin>)>
+    {
+        if calibration.max_pulse_us > channel.period() {
+            return Err((ServoError::PulseExceedsPeriod, channel));
+        }
+
+        let mut servo = Self { channel, calibration };
+        servo.detach();
+        Ok(servo)
+    }
+
+    pub fn calibration(&self) -> &ServoCalibration {
+        &self.calibration
+    }
+
+    /// Changes calibration, e.g. during the manual adjustment. Position is not updated until the
+    /// next move
+    pub fn set_calibration(&mut self, calibration: ServoCalibration) -> Result<&mut Self, ServoError> {
+        if calibration.max_pulse_us > self.channel.period() {
+            return Err(ServoError::PulseExceedsPeriod);
+        }
+
+        self.calibration = calibration;
+        Ok(self)
+    }
+
+    /// Sets pulse width directly. Zero pulse detaches the servo
+    pub fn set_pulse_us(&mut self, pulse_us: u32) -> Result<&mut Self, ServoError> {
+        self.channel.set_duty(pulse_us).map_err(ServoError::Pwm)?;
+        Ok(self)
+    }
+
+    /// Current pulse width, zero if the servo is detached
+    pub fn pulse_us(&self) -> u32 {
+        self.channel.duty()
+    }
+
+    /// Moves servo to the angle in degrees as fast as possible
+    pub fn set_angle(&mut self, degrees: f32) -> Result<&mut Self, ServoError> {
+        let pulse_us = self.calibration.angle_to_pulse_us(degrees).ok_or(ServoError::InvalidAngle)?;
+        self.set_pulse_us(pulse_us)
+    }
+
+    /// Angle of the last position, `None` if the servo is detached
+    pub fn angle(&self) -> Option<f32> {
+        match self.pulse_us() {
+            0 => None,
+            pulse_us => Some(self.calibration.pulse_us_to_angle(pulse_us)),
+        }
+    }
+
+    /// Starts moving servo to the angle with `degrees_per_second` speed. Servo is borrowed by the
+    /// returned fade until the motion completes or is interrupted. Detached servo is first moved
+    /// to the angle instantly, because its position is unknown
+    pub fn move_to(&mut self, degrees: f32, degrees_per_second: f32) -> Result<Fade<'_>, ServoError> {
+        if degrees_per_second.is_nan() || degrees_per_second <= 0.0 {
+            return Err(ServoError::InvalidSpeed);
+        }
+
+        let target_us = self.calibration.angle_to_pulse_us(degrees).ok_or(ServoError::InvalidAngle)?;
+        let distance = match self.angle() {
+            Some(angle) if angle > degrees => angle - degrees,
+            Some(angle) => degrees - angle,
+            None => {
+                self.set_pulse_us(target_us)?;
+                0.0
+            }
+        };
+
+        let duration = Duration::from_micros((distance / degrees_per_second * 1_000_000.0) as u64);
+        let target = Duty::from_ratio(target_us, self.channel.period()).map_err(ServoError::Pwm)?;
+
+        FadeInitializer::new()
+            .add_channel(&mut self.channel, target)
+            .start(duration)
+            .map_err(|(err, _)| ServoError::Fade(err))
+    }
+
+    /// Stops the pulses, so the servo doesn't hold its position and doesn't consume power
+    pub fn detach(&mut self) -> &mut Self {
+        // Zero duty never exceeds the period
+        let _ = self.channel.set_duty(0);
+        self
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.pulse_us() != 0
+    }
+
+    /// Detaches servo and returns the pwm channel back
+    pub fn release(mut self) -> PwmChannel<'a, Pin> {
+        self.detach();
+        self.channel
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        gpio::{GpioHardware, Gpio14},
+        mock,
+        peripherals::GpioPeripherals,
+        pwm::{Pwm, PwmInitializer},
+    };
+    use super::*;
+
+    fn pwm() -> Pwm<(Gpio14,)> {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        PwmInitializer::new()
+            .add_channel(gpio.gpio14.take().unwrap(), 0)
+            .set_frequency(SERVO_FREQUENCY_HZ)
+            .ok().unwrap()
+            .initialize()
+            .ok().unwrap()
+    }
+
+    fn calibration(min_pulse_us: u32, max_pulse_us: u32) -> ServoCalibration {
+        let mut calibration = ServoCalibration::new();
+        calibration.set_pulse_range(min_pulse_us, max_pulse_us).ok().unwrap();
+        calibration
+    }
+
+    #[test]
+    fn angles_are_mapped_to_calibrated_pulses() {
+        let mut calibration = calibration(1000, 2000);
+        assert_eq!(calibration.angle_to_pulse_us(0.0), Some(1000));
+        assert_eq!(calibration.angle_to_pulse_us(90.0), Some(1500));
+        assert_eq!(calibration.angle_to_pulse_us(180.0), Some(2000));
+        assert_eq!(calibration.angle_to_pulse_us(180.1), None);
+        assert_eq!(calibration.angle_to_pulse_us(-1.0), None);
+        assert_eq!(calibration.pulse_us_to_angle(1250), 45.0);
+        assert_eq!(calibration.pulse_us_to_angle(500), 0.0);
+
+        calibration.set_angle_range(270.0).ok().unwrap();
+        assert_eq!(calibration.angle_to_pulse_us(135.0), Some(1500));
+        assert_eq!(calibration.pulse_us_to_angle(2000), 270.0);
+    }
+
+    #[test]
+    fn invalid_calibration_is_rejected() {
+        let mut calibration = ServoCalibration::new();
+        assert_eq!(calibration.set_pulse_range(2000, 1000).err(), Some(ServoConfigError::InvalidPulseRange));
+        assert_eq!(calibration.set_pulse_range(0, 1000).err(), Some(ServoConfigError::InvalidPulseRange));
+        assert_eq!(calibration.set_angle_range(f32::NAN).err(), Some(ServoConfigError::InvalidAngleRange));
+        assert_eq!(calibration.set_angle_range(0.0).err(), Some(ServoConfigError::InvalidAngleRange));
+        assert_eq!(calibration, ServoCalibration::new());
+    }
+
+    #[test]
+    fn calibration_should_fit_into_period() {
+        let mut pwm = pwm();
+        let (channel,) = pwm.channels();
+
+        let result = Servo::new(channel, calibration(500, 20_001));
+        assert!(matches!(result, Err((ServoError::PulseExceedsPeriod, _))));
+
+        let (_, channel) = result.err().unwrap();
+        let mut servo = Servo::new(channel, ServoCalibration::new()).ok().unwrap();
+        assert!(matches!(servo.set_calibration(calibration(500, 25_000)), Err(ServoError::PulseExceedsPeriod)));
+    }
+
+    #[test]
+    fn servo_is_created_detached() {
+        let mut pwm = pwm();
+        let (channel,) = pwm.channels();
+        let mut servo = Servo::new(channel, calibration(1000, 2000)).ok().unwrap();
+        assert!(!servo.is_attached());
+        assert_eq!(servo.angle(), None);
+
+        servo.set_angle(90.0).ok().unwrap();
+        assert_eq!(mock::pwm::state().duties[0], 1500);
+        assert_eq!(servo.angle(), Some(90.0));
+        assert!(matches!(servo.set_angle(181.0), Err(ServoError::InvalidAngle)));
+
+        servo.detach();
+        assert!(!servo.is_attached());
+        assert_eq!(mock::pwm::state().duties[0], 0);
+    }
+
+    #[test]
+    fn move_duration_follows_speed() {
+        let mut pwm = pwm();
+        let (channel,) = pwm.channels();
+        let mut servo = Servo::new(channel, calibration(1000, 2000)).ok().unwrap();
+        servo.set_angle(90.0).ok().unwrap();
+
+        // 90 degrees at 90 degrees per second
+        let fade = servo.move_to(0.0, 90.0).ok().unwrap();
+        mock::esp_timer::advance(500_000);
+        assert_eq!(mock::pwm::state().duties[0], 1250);
+        mock::esp_timer::advance(499_000);
+        assert!(!fade.is_done());
+        mock::esp_timer::advance(1000);
+        assert!(fade.is_done());
+        drop(fade);
+
+        assert_eq!(servo.pulse_us(), 1000);
+        assert_eq!(servo.angle(), Some(0.0));
+    }
+
+    #[test]
+    fn detached_servo_jumps_to_target() {
+        let mut pwm = pwm();
+        let (channel,) = pwm.channels();
+        let mut servo = Servo::new(channel, calibration(1000, 2000)).ok().unwrap();
+        assert!(matches!(servo.move_to(0.0, 0.0), Err(ServoError::InvalidSpeed)));
+
+        let fade = servo.move_to(180.0, 10.0).ok().unwrap();
+        assert_eq!(mock::pwm::state().duties[0], 2000);
+        fade.wait();
+
+        let channel = servo.release();
+        assert_eq!(channel.duty(), 0);
+    }
+}
@@ -0,0 +1,526 @@
+//! HSPI master and slave drivers.
+//!
+//! HSPI controller is connected to the fixed pins: MISO (GPIO12), MOSI (GPIO13), CLK (GPIO14) and
+//! CS (GPIO15), so [SpiInitializer](struct.SpiInitializer.html) consumes tokens of all of them.
+//! Single hardware transaction is limited to 64 bytes; longer buffers are split into several
+//! transactions. CS is driven by the driver as a regular GPIO, so it stays low during the whole
+//! transfer or [transaction](struct.SpiMaster.html#method.transaction), not only during one
+//! hardware transaction.
+//!
+//! GPIO15 is the boot strapping pin, which should be low at boot, so the slave should not pull
+//! CS up.
+//!
+//! In slave mode ([SpiSlaveInitializer](struct.SpiSlaveInitializer.html)) CS is driven by the
+//! master and the controller implements the ESP SPI slave protocol.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     spi::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut initializer = SpiInitializer::new(
+//!     gpio.gpio12.take().unwrap(),
+//!     gpio.gpio13.take().unwrap(),
+//!     gpio.gpio14.take().unwrap(),
+//!     gpio.gpio15.take().unwrap(),
+//! );
+//! initializer.set_clock(SpiClock::Mhz10).set_mode(SpiMode::Mode0);
+//! let mut spi = initializer.initialize().ok().unwrap();
+//!
+//! // Reads JEDEC ID of the SPI flash
+//! let mut id = [0u8; 3];
+//! spi.transaction(&mut [SpiOperation::Write(&[0x9F]), SpiOperation::Read(&mut id)]).ok().unwrap();
+//! ```
+use core::ptr::null_mut;
+
+mod slave;
+pub use slave::*;
+
+use crate::{
+    delay::Delay,
+    gpio::{mode::*, Gpio12, Gpio13, Gpio14, Gpio15, InitializedPin, OutputPin, PinInitializer},
+    sys::{
+        error::*,
+        spi::*,
+    },
+};
+
+/// Maximal data length of the single hardware transaction
+const MAX_TRANSACTION_BYTES: usize = 64;
+const MAX_TRANSACTION_WORDS: usize = MAX_TRANSACTION_BYTES / 4;
+
+// Bits of `spi_interface_t`
+const INTERFACE_CPOL: u32 = 1 << 0;
+const INTERFACE_CPHA: u32 = 1 << 1;
+const INTERFACE_BIT_TX_ORDER: u32 = 1 << 2;
+const INTERFACE_BIT_RX_ORDER: u32 = 1 << 3;
+const INTERFACE_MOSI_EN: u32 = 1 << 6;
+const INTERFACE_MISO_EN: u32 = 1 << 7;
+
+// Offsets of data lengths in `spi_trans_t::bits`
+const TRANS_MOSI_BITS_OFFSET: u32 = 12;
+const TRANS_MISO_BITS_OFFSET: u32 = 22;
+
+/// SPI clock frequency, which is derived from 80 MHz APB clock with the fixed dividers
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpiClock {
+    Mhz2,
+    Mhz4,
+    Mhz5,
+    Mhz8,
+    Mhz10,
+    Mhz16,
+    Mhz20,
+    Mhz40,
+    Mhz80,
+}
+
+impl SpiClock {
+    pub fn frequency_hz(self) -> u32 {
+        80_000_000 / self.map_to_ffi()
+    }
+
+    fn map_to_ffi(self) -> spi_clk_div_t {
+        match self {
+            SpiClock::Mhz2 => spi_clk_div_t_SPI_2MHz_DIV,
+            SpiClock::Mhz4 => spi_clk_div_t_SPI_4MHz_DIV,
+            SpiClock::Mhz5 => spi_clk_div_t_SPI_5MHz_DIV,
+            SpiClock::Mhz8 => spi_clk_div_t_SPI_8MHz_DIV,
+            SpiClock::Mhz10 => spi_clk_div_t_SPI_10MHz_DIV,
+            SpiClock::Mhz16 => spi_clk_div_t_SPI_16MHz_DIV,
+            SpiClock::Mhz20 => spi_clk_div_t_SPI_20MHz_DIV,
+            SpiClock::Mhz40 => spi_clk_div_t_SPI_40MHz_DIV,
+            SpiClock::Mhz80 => spi_clk_div_t_SPI_80MHz_DIV,
+        }
+    }
+}
+
+/// Clock polarity and phase
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpiMode {
+    /// CPOL = 0, CPHA = 0
+    Mode0,
+    /// CPOL = 0, CPHA = 1
+    Mode1,
+    /// CPOL = 1, CPHA = 0
+    Mode2,
+    /// CPOL = 1, CPHA = 1
+    Mode3,
+}
+
+impl SpiMode {
+    fn interface_bits(self) -> u32 {
+        match self {
+            SpiMode::Mode0 => 0,
+            SpiMode::Mode1 => INTERFACE_CPHA,
+            SpiMode::Mode2 => INTERFACE_CPOL,
+            SpiMode::Mode3 => INTERFACE_CPOL | INTERFACE_CPHA,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpiBitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpiError {
+    /// SPI driver rejected the configuration
+    InitializationFailed,
+    /// SPI driver failed to perform the transaction
+    TransactionFailed,
+    /// Data doesn't fit into the slave buffer
+    BufferTooLong,
+}
+
+/// Part of the [transaction](struct.SpiMaster.html#method.transaction)
+pub enum SpiOperation<'a> {
+    /// Reads bytes, MOSI state is not defined
+    Read(&'a mut [u8]),
+    /// Writes bytes, received bytes are discarded
+    Write(&'a [u8]),
+    /// Writes the second buffer and simultaneously reads the first one. If buffers have
+    /// different lengths, transfer continues until the longer one ends: missing bytes are written
+    /// as zeros and extra received bytes are discarded
+    Transfer(&'a mut [u8], &'a [u8]),
+    /// Writes buffer and replaces it with the received bytes
+    TransferInPlace(&'a mut [u8]),
+    /// Delay with CS held low
+    DelayUs(u32),
+}
+
+pub struct SpiInitializer {
+    miso: Gpio12,
+    mosi: Gpio13,
+    clk: Gpio14,
+    cs: Gpio15,
+    clock: SpiClock,
+    mode: SpiMode,
+    bit_order: SpiBitOrder,
+}
+
+impl SpiInitializer {
+    pub fn new(miso: Gpio12, mosi: Gpio13, clk: Gpio14, cs: Gpio15) -> Self {
+        Self {
+            miso,
+            mosi,
+            clk,
+            cs,
+            clock: SpiClock::Mhz2,
+            mode: SpiMode::Mode0,
+            bit_order: SpiBitOrder::MsbFirst,
+        }
+    }
+
+    /// Default is 2 MHz
+    pub fn set_clock(&mut self, clock: SpiClock) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Default is `Mode0`
+    pub fn set_mode(&mut self, mode: SpiMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Bit order of both sent and received bytes. Default is `MsbFirst`
+    pub fn set_bit_order(&mut self, bit_order: SpiBitOrder) -> &mut Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    fn interface_bits(&self) -> u32 {
+        let bit_order = match self.bit_order {
+            SpiBitOrder::MsbFirst => 0,
+            SpiBitOrder::LsbFirst => INTERFACE_BIT_TX_ORDER | INTERFACE_BIT_RX_ORDER,
+        };
+
+        // Hardware CS is not enabled, because it is released after each hardware transaction
+        self.mode.interface_bits() | bit_order | INTERFACE_MOSI_EN | INTERFACE_MISO_EN
+    }
+
+    /// Initializes HSPI controller in master mode. Returns error and initializer back if driver
+    /// rejects the configuration
+    pub fn initialize(self) -> Result<SpiMaster, (SpiError, Self)> {
+        let mut config = spi_config_t {
+            interface: spi_interface_t { val: self.interface_bits() },
+            intr_enable: spi_intr_enable_t { val: 0 },
+            event_cb: None,
+            mode: spi_mode_t_SPI_MASTER_MODE,
+            clk_div: self.clock.map_to_ffi(),
+        };
+
+        if unsafe { spi_init(spi_host_t_HSPI_HOST, &mut config) } != esp_err_t_ESP_OK {
+            return Err((SpiError::InitializationFailed, self));
+        }
+
+        let mut cs = PinInitializer::new(self.cs).configure_as_output().init();
+        cs.set_level(true);
+
+        Ok(SpiMaster {
+            miso: self.miso,
+            mosi: self.mosi,
+            clk: self.clk,
+            cs,
+        })
+    }
+}
+
+/// Returns `len` bytes of `buffer` starting at `offset`, clipped at the buffer end
+fn clip(buffer: &[u8], offset: usize, len: usize) -> &[u8] {
+    let start = offset.min(buffer.len());
+    &buffer[start..(offset + len).min(buffer.len())]
+}
+
+fn clip_mut(buffer: &mut [u8], offset: usize, len: usize) -> &mut [u8] {
+    let start = offset.min(buffer.len());
+    let end = (offset + len).min(buffer.len());
+    &mut buffer[start..end]
+}
+
+pub struct SpiMaster {
+    miso: Gpio12,
+    mosi: Gpio13,
+    clk: Gpio14,
+    cs: InitializedPin<Gpio15, Output<PushPull>>,
+}
+
+impl SpiMaster {
+    /// Performs single hardware transaction of `len` bytes. `write` bytes are sent first, the
+    /// rest is sent as zeros; first `read.len()` received bytes are stored to `read`. Transaction
+    /// is write-only or read-only if the corresponding buffer is not provided
+    fn transaction_chunk(&mut self, write: Option<&[u8]>, read: Option<&mut [u8]>, len: usize)
+        -> Result<(), SpiError>
+    {
+        let mut mosi = [0u32; MAX_TRANSACTION_WORDS];
+        let mut miso = [0u32; MAX_TRANSACTION_WORDS];
+
+        let mut bits = 0;
+        if let Some(write) = write {
+            for (index, byte) in write.iter().enumerate() {
+                mosi[index / 4] |= (*byte as u32) << (8 * (index % 4));
+            }
+            bits |= ((len * 8) as u32) << TRANS_MOSI_BITS_OFFSET;
+        }
+        if read.is_some() {
+            bits |= ((len * 8) as u32) << TRANS_MISO_BITS_OFFSET;
+        }
+
+        let mut trans = spi_trans_t {
+            cmd: null_mut(),
+            addr: null_mut(),
+            mosi: if write.is_some() { mosi.as_mut_ptr() } else { null_mut() },
+            miso: if read.is_some() { miso.as_mut_ptr() } else { null_mut() },
+            bits: spi_trans_t__bindgen_ty_1 { val: bits },
+        };
+
+        if unsafe { spi_trans(spi_host_t_HSPI_HOST, &mut trans) } != esp_err_t_ESP_OK {
+            return Err(SpiError::TransactionFailed);
+        }
+
+        if let Some(read) = read {
+            for (index, byte) in read.iter_mut().enumerate() {
+                *byte = (miso[index / 4] >> (8 * (index % 4))) as u8;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn bus_read(&mut self, buffer: &mut [u8]) -> Result<(), SpiError> {
+        for chunk in buffer.chunks_mut(MAX_TRANSACTION_BYTES) {
+            let len = chunk.len();
+            self.transaction_chunk(None, Some(chunk), len)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn bus_write(&mut self, bytes: &[u8]) -> Result<(), SpiError> {
+        for chunk in bytes.chunks(MAX_TRANSACTION_BYTES) {
+            self.transaction_chunk(Some(chunk), None, chunk.len())?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn bus_transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), SpiError> {
+        let len = read.len().max(write.len());
+
+        let mut offset = 0;
+        while offset < len {
+            let chunk_len = (len - offset).min(MAX_TRANSACTION_BYTES);
+            self.transaction_chunk(
+                Some(clip(write, offset, chunk_len)),
+                Some(clip_mut(read, offset, chunk_len)),
+                chunk_len,
+            )?;
+            offset += chunk_len;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn bus_transfer_in_place(&mut self, buffer: &mut [u8]) -> Result<(), SpiError> {
+        for chunk in buffer.chunks_mut(MAX_TRANSACTION_BYTES) {
+            let mut write = [0u8; MAX_TRANSACTION_BYTES];
+            let len = chunk.len();
+            write[..len].copy_from_slice(chunk);
+            self.transaction_chunk(Some(&write[..len]), Some(chunk), len)?;
+        }
+        Ok(())
+    }
+
+    fn run_operation(&mut self, operation: SpiOperation<'_>) -> Result<(), SpiError> {
+        match operation {
+            SpiOperation::Read(buffer) => self.bus_read(buffer),
+            SpiOperation::Write(bytes) => self.bus_write(bytes),
+            SpiOperation::Transfer(read, write) => self.bus_transfer(read, write),
+            SpiOperation::TransferInPlace(buffer) => self.bus_transfer_in_place(buffer),
+            SpiOperation::DelayUs(us) => {
+                Delay::new().delay_us(us);
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn run_transaction<'b, I>(&mut self, mut operations: I) -> Result<(), SpiError>
+        where I: Iterator<Item = SpiOperation<'b>>
+    {
+        self.cs.set_level(false);
+        let result = operations.try_for_each(|operation| self.run_operation(operation));
+        self.cs.set_level(true);
+        result
+    }
+
+    /// Executes `operations` with CS held low. CS is released even if one of the operations
+    /// fails, remaining operations are skipped in this case
+    pub fn transaction(&mut self, operations: &mut [SpiOperation<'_>]) -> Result<(), SpiError> {
+        self.run_transaction(operations.iter_mut().map(|operation| match operation {
+            SpiOperation::Read(buffer) => SpiOperation::Read(buffer),
+            SpiOperation::Write(bytes) => SpiOperation::Write(bytes),
+            SpiOperation::Transfer(read, write) => SpiOperation::Transfer(read, write),
+            SpiOperation::TransferInPlace(buffer) => SpiOperation::TransferInPlace(buffer),
+            SpiOperation::DelayUs(us) => SpiOperation::DelayUs(*us),
+        }))
+    }
+
+    /// Reads bytes in a separate transaction
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), SpiError> {
+        self.run_transaction(core::iter::once(SpiOperation::Read(buffer)))
+    }
+
+    /// Writes bytes in a separate transaction
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), SpiError> {
+        self.run_transaction(core::iter::once(SpiOperation::Write(bytes)))
+    }
+
+    /// Performs full-duplex transfer in a separate transaction. See
+    /// [SpiOperation::Transfer](enum.SpiOperation.html#variant.Transfer) for the buffer lengths
+    pub fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), SpiError> {
+        self.run_transaction(core::iter::once(SpiOperation::Transfer(read, write)))
+    }
+
+    pub fn transfer_in_place(&mut self, buffer: &mut [u8]) -> Result<(), SpiError> {
+        self.run_transaction(core::iter::once(SpiOperation::TransferInPlace(buffer)))
+    }
+
+    /// Deinitializes HSPI controller and returns pins back
+    pub fn deinitialize(self) -> (Gpio12, Gpio13, Gpio14, Gpio15) {
+        unsafe { spi_deinit(spi_host_t_HSPI_HOST) };
+        (self.miso, self.mosi, self.clk, self.cs.release())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::vec::Vec;
+
+    use crate::{
+        gpio::GpioHardware,
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn master(mode: SpiMode, bit_order: SpiBitOrder) -> SpiMaster {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let mut initializer = SpiInitializer::new(
+            gpio.gpio12.take().unwrap(),
+            gpio.gpio13.take().unwrap(),
+            gpio.gpio14.take().unwrap(),
+            gpio.gpio15.take().unwrap(),
+        );
+        initializer.set_clock(SpiClock::Mhz10).set_mode(mode).set_bit_order(bit_order);
+        initializer.initialize().ok().unwrap()
+    }
+
+    #[test]
+    fn modes_set_clock_polarity_and_phase() {
+        let modes = [
+            (SpiMode::Mode0, false, false),
+            (SpiMode::Mode1, false, true),
+            (SpiMode::Mode2, true, false),
+            (SpiMode::Mode3, true, true),
+        ];
+
+        for (mode, cpol, cpha) in modes.iter() {
+            let spi = master(*mode, SpiBitOrder::MsbFirst);
+
+            let interface = mock::spi::interface().unwrap();
+            assert_eq!((interface.cpol, interface.cpha), (*cpol, *cpha), "{:?}", mode);
+            // Data lines are driven by the controller, CS is driven as a GPIO
+            assert!(interface.mosi_en && interface.miso_en && !interface.cs_en);
+            assert!(!interface.bit_tx_lsb_first && !interface.bit_rx_lsb_first);
+            assert_eq!(mock::spi::config().unwrap().clk_div, spi_clk_div_t_SPI_10MHz_DIV);
+
+            spi.deinitialize();
+        }
+    }
+
+    #[test]
+    fn lsb_first_reverses_bits_of_both_directions() {
+        let mut spi = master(SpiMode::Mode0, SpiBitOrder::LsbFirst);
+        let interface = mock::spi::interface().unwrap();
+        assert!(interface.bit_tx_lsb_first && interface.bit_rx_lsb_first);
+
+        mock::spi::push_miso(&[0x01, 0xC0]);
+        let mut read = [0u8; 2];
+        spi.transfer(&mut read, &[0x01, 0x03]).unwrap();
+
+        assert_eq!(mock::spi::take_mosi(), [0x80, 0xC0]);
+        assert_eq!(read, [0x80, 0x03]);
+    }
+
+    #[test]
+    fn long_buffers_are_split_into_64_byte_transactions() {
+        let mut spi = master(SpiMode::Mode3, SpiBitOrder::MsbFirst);
+
+        let data: Vec<u8> = (0..150u32).map(|byte| byte as u8).collect();
+        mock::clear_calls();
+        spi.write(&data).unwrap();
+
+        assert_eq!(mock::spi::take_mosi(), data);
+        let transactions: Vec<_> = mock::calls_of("spi_trans").into_iter().map(|call| call.args).collect();
+        assert_eq!(transactions, [[1, 512, 0], [1, 512, 0], [1, 22 * 8, 0]]);
+        // CS is held low between the hardware transactions
+        assert_eq!(mock::calls_of("gpio_set_level").len(), 2);
+        assert!(mock::gpio::pin_state(15).output_level);
+
+        mock::clear_calls();
+        let mut buffer: Vec<u8> = (0..70u8).collect();
+        mock::spi::push_miso(&[0x55; 70]);
+        spi.transfer_in_place(&mut buffer).unwrap();
+
+        assert_eq!(buffer, [0x55; 70]);
+        assert_eq!(mock::spi::take_mosi(), (0..70u8).collect::<Vec<_>>());
+        assert_eq!(mock::calls_of("spi_trans").len(), 2);
+    }
+
+    #[test]
+    fn transaction_keeps_cs_low_between_operations() {
+        let mut spi = master(SpiMode::Mode0, SpiBitOrder::MsbFirst);
+
+        mock::spi::push_miso(&[1, 2, 3, 4, 5]);
+        let mut id = [0u8; 6];
+        spi.transaction(&mut [SpiOperation::Write(&[0x9F]), SpiOperation::Read(&mut id)]).unwrap();
+
+        // First byte is shifted out while the command is written
+        assert_eq!(id, [2, 3, 4, 5, 0xFF, 0xFF]);
+        assert_eq!(mock::spi::take_mosi(), [0x9F]);
+
+        mock::spi::push_miso(&[9, 8, 7]);
+        let mut read = [0u8; 2];
+        spi.transfer(&mut read, &[1, 2, 3]).unwrap();
+        assert_eq!(read, [9, 8]);
+        assert_eq!(mock::spi::take_mosi(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn failed_transaction_releases_cs() {
+        let mut spi = master(SpiMode::Mode0, SpiBitOrder::MsbFirst);
+
+        mock::inject_error("spi_trans", esp_err_t_ESP_FAIL);
+        assert_eq!(spi.write(&[1]), Err(SpiError::TransactionFailed));
+        assert!(mock::gpio::pin_state(15).output_level);
+    }
+
+    #[test]
+    fn rejected_configuration_returns_initializer() {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        let initializer = SpiInitializer::new(
+            gpio.gpio12.take().unwrap(),
+            gpio.gpio13.take().unwrap(),
+            gpio.gpio14.take().unwrap(),
+            gpio.gpio15.take().unwrap(),
+        );
+
+        mock::inject_error("spi_init", esp_err_t_ESP_FAIL);
+        assert!(matches!(initializer.initialize(), Err((SpiError::InitializationFailed, _))));
+    }
+}
@@ -0,0 +1,523 @@
+//! HSPI slave driver.
+//!
+//! In slave mode HSPI hardware implements the ESP SPI slave protocol. Each frame starts with the
+//! command, followed by the address and the data:
+//!
+//! | Command | Frame                                             |
+//! |---------|---------------------------------------------------|
+//! | `0x01`  | Master writes 32-bit status                       |
+//! | `0x02`  | Master writes data buffer                         |
+//! | `0x03`  | Master reads data buffer                          |
+//! | `0x04`  | Master reads 32-bit status                        |
+//!
+//! Slave has separate receive and transmit data buffers of up to 32 bytes. Lengths of the frame
+//! parts are configured with [SpiSlaveInitializer](struct.SpiSlaveInitializer.html) and should
+//! match the master configuration. Default framing is 8-bit command, 8-bit address and 32 bytes
+//! of data.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::{
+//! #     gpio::*,
+//! #     spi::*,
+//! #     peripherals::Peripherals,
+//! # };
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut gpio = GpioHardware::new(peripherals.gpio);
+//!
+//! let mut initializer = SpiSlaveInitializer::new(
+//!     gpio.gpio12.take().unwrap(),
+//!     gpio.gpio13.take().unwrap(),
+//!     gpio.gpio14.take().unwrap(),
+//!     gpio.gpio15.take().unwrap(),
+//! );
+//! // Echoes received data back to the master
+//! initializer.set_callback(|event, buffers| {
+//!     if event == SpiSlaveEvent::BufferWritten {
+//!         let mut data = [0u8; 32];
+//!         buffers.read_buffer(&mut data).ok().unwrap();
+//!         buffers.write_buffer(&data).ok().unwrap();
+//!     }
+//! });
+//! let mut slave = initializer.initialize().ok().unwrap();
+//! slave.buffers().set_status(1);
+//! ```
+use alloc::boxed::Box;
+use core::ptr::null_mut;
+
+use crate::{
+    critical_section,
+    gpio::{Gpio12, Gpio13, Gpio14, Gpio15},
+    sys::{
+        error::*,
+        ffi::*,
+        spi::*,
+    },
+};
+use super::{
+    SpiBitOrder, SpiError, SpiMode,
+    INTERFACE_BIT_RX_ORDER, INTERFACE_BIT_TX_ORDER, INTERFACE_MISO_EN, INTERFACE_MOSI_EN,
+    TRANS_MISO_BITS_OFFSET, TRANS_MOSI_BITS_OFFSET,
+};
+
+/// Size of each slave data buffer
+const SLAVE_BUFFER_BYTES: usize = 32;
+const SLAVE_BUFFER_WORDS: usize = SLAVE_BUFFER_BYTES / 4;
+
+const INTERFACE_CS_EN: u32 = 1 << 8;
+
+// Offsets of command and address lengths in `spi_trans_t::bits`
+const TRANS_COMMAND_BITS_OFFSET: u32 = 0;
+const TRANS_ADDRESS_BITS_OFFSET: u32 = 5;
+
+// Bits of the `SPI_TRANS_DONE_EVENT` status, which are also used in `spi_intr_enable_t`
+const SLAVE_READ_BUFFER_DONE: u32 = 1 << 0;
+const SLAVE_WRITE_BUFFER_DONE: u32 = 1 << 1;
+const SLAVE_READ_STATUS_DONE: u32 = 1 << 2;
+const SLAVE_WRITE_STATUS_DONE: u32 = 1 << 3;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpiSlaveConfigError {
+    /// Command length should be in 1..=16 bits range
+    InvalidCommandLength,
+    /// Address length should be in 1..=32 bits range
+    InvalidAddressLength,
+    /// Data length should be in 1..=32 bytes range
+    InvalidDataLength,
+}
+
+/// Completed frame of the ESP SPI slave protocol, reported to the
+/// [callback](struct.SpiSlaveInitializer.html#method.set_callback)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpiSlaveEvent {
+    /// Master has written the status, which can be read with
+    /// [status](struct.SpiSlaveBuffers.html#method.status)
+    StatusWritten,
+    /// Master has written the receive buffer, which can be read with
+    /// [read_buffer](struct.SpiSlaveBuffers.html#method.read_buffer)
+    BufferWritten,
+    /// Master has read the transmit buffer, so the next data can be loaded with
+    /// [write_buffer](struct.SpiSlaveBuffers.html#method.write_buffer)
+    BufferRead,
+    /// Master has read the status set with
+    /// [set_status](struct.SpiSlaveBuffers.html#method.set_status)
+    StatusRead,
+}
+
+impl SpiSlaveEvent {
+    fn status_bit(self) -> u32 {
+        match self {
+            SpiSlaveEvent::StatusWritten => SLAVE_WRITE_STATUS_DONE,
+            SpiSlaveEvent::BufferWritten => SLAVE_WRITE_BUFFER_DONE,
+            SpiSlaveEvent::BufferRead => SLAVE_READ_BUFFER_DONE,
+            SpiSlaveEvent::StatusRead => SLAVE_READ_STATUS_DONE,
+        }
+    }
+}
+
+const SLAVE_EVENTS: [SpiSlaveEvent; 4] = [
+    SpiSlaveEvent::StatusWritten,
+    SpiSlaveEvent::BufferWritten,
+    SpiSlaveEvent::BufferRead,
+    SpiSlaveEvent::StatusRead,
+];
+
+/// Provides access to the status registers and data buffers of the slave. Driver calls are made
+/// inside critical sections, so the callback can't interrupt the transfer started by the task
+pub struct SpiSlaveBuffers {
+    command_bits: u8,
+    address_bits: u8,
+    data_bytes: u8,
+}
+
+impl SpiSlaveBuffers {
+    fn frame_bits(&self) -> u32 {
+        ((self.command_bits as u32) << TRANS_COMMAND_BITS_OFFSET)
+            | ((self.address_bits as u32) << TRANS_ADDRESS_BITS_OFFSET)
+    }
+
+    /// Copies data written by the master to `buffer` and returns the address of the frame.
+    /// Data beyond the configured data length is not modified
+    pub fn read_buffer(&mut self, buffer: &mut [u8]) -> Result<u32, SpiError> {
+        let mut miso = [0u32; SLAVE_BUFFER_WORDS];
+        let mut address = 0u32;
+
+        let mut trans = spi_trans_t {
+            cmd: null_mut(),
+            addr: &mut address,
+            mosi: null_mut(),
+            miso: miso.as_mut_ptr(),
+            bits: spi_trans_t__bindgen_ty_1 {
+                val: self.frame_bits() | ((self.data_bytes as u32 * 8) << TRANS_MISO_BITS_OFFSET),
+            },
+        };
+        if critical_section::free(|| unsafe { spi_trans(spi_host_t_HSPI_HOST, &mut trans) }) != esp_err_t_ESP_OK {
+            return Err(SpiError::TransactionFailed);
+        }
+
+        for (index, byte) in buffer.iter_mut().take(self.data_bytes as usize).enumerate() {
+            *byte = (miso[index / 4] >> (8 * (index % 4))) as u8;
+        }
+        Ok(address)
+    }
+
+    /// Loads data, which is sent by the slave when master reads the buffer. Data shorter than
+    /// the configured data length is padded with zeros
+    pub fn write_buffer(&mut self, bytes: &[u8]) -> Result<(), SpiError> {
+        if bytes.len() > self.data_bytes as usize {
+            return Err(SpiError::BufferTooLong);
+        }
+
+        let mut mosi = [0u32; SLAVE_BUFFER_WORDS];
+        for (index, byte) in bytes.iter().enumerate() {
+            mosi[index / 4] |= (*byte as u32) << (8 * (index % 4));
+        }
+
+        let mut trans = spi_trans_t {
+            cmd: null_mut(),
+            addr: null_mut(),
+            mosi: mosi.as_mut_ptr(),
+            miso: null_mut(),
+            bits: spi_trans_t__bindgen_ty_1 {
+                val: self.frame_bits() | ((self.data_bytes as u32 * 8) << TRANS_MOSI_BITS_OFFSET),
+            },
+        };
+        if critical_section::free(|| unsafe { spi_trans(spi_host_t_HSPI_HOST, &mut trans) }) != esp_err_t_ESP_OK {
+            return Err(SpiError::TransactionFailed);
+        }
+        Ok(())
+    }
+
+    /// Status written by the master
+    pub fn status(&mut self) -> u32 {
+        let mut status = 0;
+        critical_section::free(|| unsafe { spi_slave_get_status(spi_host_t_HSPI_HOST, &mut status) });
+        status
+    }
+
+    /// Sets status, which is sent by the slave when master reads the status
+    pub fn set_status(&mut self, status: u32) {
+        let mut status = status;
+        critical_section::free(|| unsafe { spi_slave_set_status(spi_host_t_HSPI_HOST, &mut status) });
+    }
+}
+
+type SpiSlaveCallback = Box<dyn FnMut(SpiSlaveEvent, &mut SpiSlaveBuffers) + Send>;
+
+struct SlaveHandler {
+    callback: SpiSlaveCallback,
+    buffers: SpiSlaveBuffers,
+}
+
+// SPI driver doesn't pass user context to the event callback, so the handler of the single HSPI
+// slave is kept in the static. Should only be modified inside of the critical section
+static mut SLAVE_HANDLER: *mut SlaveHandler = null_mut();
+
+unsafe extern "C" fn slave_event_wrapper(event: xtensa_int, arg: *mut xtensa_void) {
+    if event != spi_event_t_SPI_TRANS_DONE_EVENT as xtensa_int || arg.is_null() {
+        return;
+    }
+
+    let handler = SLAVE_HANDLER;
+    if handler.is_null() {
+        return;
+    }
+
+    let status = *(arg as *const u32);
+    // Frames of the task are made inside critical sections as well, so the buffers are never
+    // accessed concurrently
+    critical_section::free(|| {
+        for event in SLAVE_EVENTS.iter() {
+            if status & event.status_bit() != 0 {
+                ((*handler).callback)(*event, &mut (*handler).buffers);
+            }
+        }
+    });
+}
+
+pub struct SpiSlaveInitializer {
+    miso: Gpio12,
+    mosi: Gpio13,
+    clk: Gpio14,
+    cs: Gpio15,
+    mode: SpiMode,
+    bit_order: SpiBitOrder,
+    command_bits: u8,
+    address_bits: u8,
+    data_bytes: u8,
+    callback: Option<SpiSlaveCallback>,
+}
+
+impl SpiSlaveInitializer {
+    pub fn new(miso: Gpio12, mosi: Gpio13, clk: Gpio14, cs: Gpio15) -> Self {
+        Self {
+            miso,
+            mosi,
+            clk,
+            cs,
+            mode: SpiMode::Mode0,
+            bit_order: SpiBitOrder::MsbFirst,
+            command_bits: 8,
+            address_bits: 8,
+            data_bytes: SLAVE_BUFFER_BYTES as u8,
+            callback: None,
+        }
+    }
+
+    /// Default is `Mode0`
+    pub fn set_mode(&mut self, mode: SpiMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Default is `MsbFirst`
+    pub fn set_bit_order(&mut self, bit_order: SpiBitOrder) -> &mut Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    /// Default is 8 bits
+    pub fn set_command_bits(&mut self, bits: u8) -> Result<&mut Self, SpiSlaveConfigError> {
+        if bits == 0 || bits > 16 {
+            Err(SpiSlaveConfigError::InvalidCommandLength)
+        } else {
+            self.command_bits = bits;
+            Ok(self)
+        }
+    }
+
+    /// Default is 8 bits
+    pub fn set_address_bits(&mut self, bits: u8) -> Result<&mut Self, SpiSlaveConfigError> {
+        if bits == 0 || bits > 32 {
+            Err(SpiSlaveConfigError::InvalidAddressLength)
+        } else {
+            self.address_bits = bits;
+            Ok(self)
+        }
+    }
+
+    /// Length of the data buffer frames. Default is 32 bytes
+    pub fn set_data_length(&mut self, bytes: u8) -> Result<&mut Self, SpiSlaveConfigError> {
+        if bytes == 0 || bytes as usize > SLAVE_BUFFER_BYTES {
+            Err(SpiSlaveConfigError::InvalidDataLength)
+        } else {
+            self.data_bytes = bytes;
+            Ok(self)
+        }
+    }
+
+    /// Sets callback, which is called from the interrupt handler after each completed frame.
+    /// Callback should be short and should not block
+    pub fn set_callback<F>(&mut self, callback: F) -> &mut Self
+        where F: FnMut(SpiSlaveEvent, &mut SpiSlaveBuffers) + Send + 'static
+    {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    fn buffers(&self) -> SpiSlaveBuffers {
+        SpiSlaveBuffers {
+            command_bits: self.command_bits,
+            address_bits: self.address_bits,
+            data_bytes: self.data_bytes,
+        }
+    }
+
+    fn interface_bits(&self) -> u32 {
+        let bit_order = match self.bit_order {
+            SpiBitOrder::MsbFirst => 0,
+            SpiBitOrder::LsbFirst => INTERFACE_BIT_TX_ORDER | INTERFACE_BIT_RX_ORDER,
+        };
+
+        self.mode.interface_bits() | bit_order | INTERFACE_MOSI_EN | INTERFACE_MISO_EN | INTERFACE_CS_EN
+    }
+
+    /// Initializes HSPI controller in slave mode and loads empty transmit buffer. Returns error
+    /// and initializer back if driver rejects the configuration
+    pub fn initialize(mut self) -> Result<SpiSlave, (SpiError, Self)> {
+        let handler = self.callback.take().map(|callback| {
+            Box::into_raw(Box::new(SlaveHandler { callback, buffers: self.buffers() }))
+        });
+
+        let mut config = spi_config_t {
+            interface: spi_interface_t { val: self.interface_bits() },
+            intr_enable: spi_intr_enable_t {
+                val: if handler.is_some() {
+                    SLAVE_READ_BUFFER_DONE | SLAVE_WRITE_BUFFER_DONE
+                        | SLAVE_READ_STATUS_DONE | SLAVE_WRITE_STATUS_DONE
+                } else {
+                    0
+                },
+            },
+            event_cb: handler.map(|_| slave_event_wrapper as unsafe extern "C" fn(xtensa_int, *mut xtensa_void)),
+            mode: spi_mode_t_SPI_SLAVE_MODE,
+            // Slave is clocked by the master
+            clk_div: spi_clk_div_t_SPI_2MHz_DIV,
+        };
+
+        // Handler should be available before the first event
+        critical_section::free(|| unsafe {
+            SLAVE_HANDLER = handler.unwrap_or(null_mut());
+        });
+
+        if unsafe { spi_init(spi_host_t_HSPI_HOST, &mut config) } != esp_err_t_ESP_OK {
+            if let Some(handler) = take_slave_handler() {
+                self.callback = Some(handler.callback);
+            }
+            return Err((SpiError::InitializationFailed, self));
+        }
+
+        let mut buffers = self.buffers();
+        if buffers.write_buffer(&[]).is_err() {
+            unsafe { spi_deinit(spi_host_t_HSPI_HOST) };
+            if let Some(handler) = take_slave_handler() {
+                self.callback = Some(handler.callback);
+            }
+            return Err((SpiError::InitializationFailed, self));
+        }
+
+        Ok(SpiSlave {
+            _miso: self.miso,
+            _mosi: self.mosi,
+            _clk: self.clk,
+            _cs: self.cs,
+            buffers,
+        })
+    }
+}
+
+fn take_slave_handler() -> Option<Box<SlaveHandler>> {
+    let handler = critical_section::free(|| unsafe {
+        let handler = SLAVE_HANDLER;
+        SLAVE_HANDLER = null_mut();
+        handler
+    });
+
+    if handler.is_null() {
+        None
+    } else {
+        Some(unsafe { Box::from_raw(handler) })
+    }
+}
+
+/// Initialized HSPI slave. Controller is deinitialized and the callback is dropped when the slave
+/// is dropped or [deinitialized](#method.deinitialize)
+pub struct SpiSlave {
+    _miso: Gpio12,
+    _mosi: Gpio13,
+    _clk: Gpio14,
+    _cs: Gpio15,
+    buffers: SpiSlaveBuffers,
+}
+
+impl SpiSlave {
+    /// Provides access to the buffers outside of the callback
+    pub fn buffers(&mut self) -> &mut SpiSlaveBuffers {
+        &mut self.buffers
+    }
+
+    /// Deinitializes HSPI controller, drops the callback and returns pins back
+    pub fn deinitialize(self) -> (Gpio12, Gpio13, Gpio14, Gpio15) {
+        drop(self);
+        (Gpio12::new(), Gpio13::new(), Gpio14::new(), Gpio15::new())
+    }
+}
+
+impl Drop for SpiSlave {
+    fn drop(&mut self) {
+        // Driver stops calling the event callback before the handler is freed
+        unsafe { spi_deinit(spi_host_t_HSPI_HOST) };
+        drop(take_slave_handler());
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        gpio::GpioHardware,
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn initializer() -> SpiSlaveInitializer {
+        let mut gpio = GpioHardware::new(GpioPeripherals {});
+        SpiSlaveInitializer::new(
+            gpio.gpio12.take().unwrap(),
+            gpio.gpio13.take().unwrap(),
+            gpio.gpio14.take().unwrap(),
+            gpio.gpio15.take().unwrap(),
+        )
+    }
+
+    #[test]
+    fn interface_enables_data_lines_and_cs() {
+        let mut initializer = initializer();
+        initializer.set_mode(SpiMode::Mode1).set_bit_order(SpiBitOrder::LsbFirst);
+        let _slave = initializer.initialize().ok().unwrap();
+
+        let interface = mock::spi::interface().unwrap();
+        assert!(interface.mosi_en && interface.miso_en && interface.cs_en);
+        assert!(!interface.cpol && interface.cpha);
+        assert!(interface.bit_tx_lsb_first && interface.bit_rx_lsb_first);
+    }
+
+    #[test]
+    fn callback_answers_master_frames() {
+        let mut initializer = initializer();
+        initializer.set_data_length(4).ok().unwrap();
+        initializer.set_callback(|event, buffers| match event {
+            SpiSlaveEvent::BufferWritten => {
+                let mut data = [0u8; 4];
+                let address = buffers.read_buffer(&mut data).unwrap();
+                for byte in data.iter_mut() {
+                    *byte = byte.wrapping_add(address as u8);
+                }
+                buffers.write_buffer(&data).unwrap();
+            }
+            SpiSlaveEvent::StatusWritten => {
+                let status = buffers.status();
+                buffers.set_status(status + 1);
+            }
+            _ => {}
+        });
+        let mut slave = initializer.initialize().ok().unwrap();
+
+        mock::spi::master_write_buffer(1, &[1, 2, 3, 4]);
+        assert_eq!(mock::spi::master_read_buffer(0, 4), [2, 3, 4, 5]);
+        mock::spi::master_write_status(41);
+        assert_eq!(mock::spi::master_read_status(), 42);
+
+        slave.buffers().set_status(7);
+        assert_eq!(mock::spi::master_read_status(), 7);
+        assert_eq!(slave.buffers().write_buffer(&[0; 5]), Err(SpiError::BufferTooLong));
+    }
+
+    #[test]
+    fn invalid_frame_lengths_are_rejected() {
+        let mut initializer = initializer();
+
+        assert_eq!(initializer.set_command_bits(17).err(), Some(SpiSlaveConfigError::InvalidCommandLength));
+        assert_eq!(initializer.set_address_bits(0).err(), Some(SpiSlaveConfigError::InvalidAddressLength));
+        assert_eq!(initializer.set_data_length(33).err(), Some(SpiSlaveConfigError::InvalidDataLength));
+    }
+
+    #[test]
+    fn drop_deinitializes_and_frees_callback() {
+        let mut initializer = initializer();
+        let callback_state = Arc::new(());
+        let captured = callback_state.clone();
+        initializer.set_callback(move |_, _| { let _ = &captured; });
+        let slave = initializer.initialize().ok().unwrap();
+        assert_eq!(Arc::strong_count(&callback_state), 2);
+
+        drop(slave);
+
+        assert!(mock::spi::config().is_none());
+        assert_eq!(Arc::strong_count(&callback_state), 1);
+        assert_eq!(mock::calls_of("spi_deinit").len(), 1);
+    }
+}
@@ -0,0 +1,32 @@
+//! FFI of the idf framework for the real chip.
+//!
+//! Modules bound by `idf-sys` are re-exported as is. The rest of the SDK, which is used by the
+//! HAL, is declared here after the ESP8266 RTOS SDK headers. These declarations should be
+//! removed once `idf-sys` binds the corresponding headers. Headers are mirrored as is, so not
+//! every declared item is used by the HAL.
+//!
+//! Declarations mirror the [mock](../mock/index.html) modules, so the HAL is built against the
+//! same API in both configurations.
+#![allow(dead_code)]
+
+pub use idf_sys::{
+    error,
+    ffi,
+    gpio,
+    network_adapter,
+    nvs,
+    pwm,
+    system_event,
+    uart,
+    watchdog,
+    wifi,
+};
+
+pub mod adc;
+pub mod esp_timer;
+pub mod freertos;
+pub mod hw_timer;
+pub mod rom;
+pub mod sleep;
+pub mod spi;
+pub mod xtensa;
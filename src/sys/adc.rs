@@ -0,0 +1,24 @@
+//! ADC driver from `driver/adc.h`.
+use super::{
+    error::esp_err_t,
+    ffi::*,
+};
+
+pub type adc_mode_t = xtensa_uint;
+pub const adc_mode_t_ADC_READ_TOUT_MODE: adc_mode_t = 0;
+pub const adc_mode_t_ADC_READ_VDD_MODE: adc_mode_t = 1;
+pub const adc_mode_t_ADC_READ_MAX_MODE: adc_mode_t = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct adc_config_t {
+    pub mode: adc_mode_t,
+    pub clk_div: u8,
+}
+
+extern "C" {
+    pub fn adc_init(config: *mut adc_config_t) -> esp_err_t;
+    pub fn adc_deinit() -> esp_err_t;
+    pub fn adc_read(data: *mut u16) -> esp_err_t;
+    pub fn adc_read_fast(data: *mut u16, len: u16) -> esp_err_t;
+}
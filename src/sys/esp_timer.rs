@@ -0,0 +1,38 @@
+//! High resolution software timers from `esp_timer.h`.
+use super::{
+    error::esp_err_t,
+    ffi::*,
+};
+
+#[repr(C)]
+pub struct esp_timer {
+    _unused: [u8; 0],
+}
+
+pub type esp_timer_handle_t = *mut esp_timer;
+pub type esp_timer_cb_t = Option<unsafe extern "C" fn(arg: *mut xtensa_void)>;
+
+pub type esp_timer_dispatch_t = xtensa_uint;
+pub const esp_timer_dispatch_t_ESP_TIMER_TASK: esp_timer_dispatch_t = 0;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct esp_timer_create_args_t {
+    pub callback: esp_timer_cb_t,
+    pub arg: *mut xtensa_void,
+    pub dispatch_method: esp_timer_dispatch_t,
+    pub name: *const xtensa_char,
+}
+
+extern "C" {
+    /// Microseconds since boot
+    pub fn esp_timer_get_time() -> i64;
+    pub fn esp_timer_create(
+        create_args: *const esp_timer_create_args_t,
+        out_handle: *mut esp_timer_handle_t,
+    ) -> esp_err_t;
+    pub fn esp_timer_start_once(timer: esp_timer_handle_t, timeout_us: u64) -> esp_err_t;
+    pub fn esp_timer_start_periodic(timer: esp_timer_handle_t, period: u64) -> esp_err_t;
+    pub fn esp_timer_stop(timer: esp_timer_handle_t) -> esp_err_t;
+    pub fn esp_timer_delete(timer: esp_timer_handle_t) -> esp_err_t;
+}
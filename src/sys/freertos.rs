@@ -0,0 +1,16 @@
+//! FreeRTOS API from `freertos/FreeRTOS.h` and `freertos/task.h`.
+//!
+//! Constants are defined by macros with casts, which bindgen doesn't evaluate.
+
+/// Tick count. Same type as the tick parameters of `idf-sys` uart bindings
+pub type TickType_t = usize;
+
+/// Should match `CONFIG_FREERTOS_HZ` of the sdkconfig, which is 100 Hz by default
+pub const configTICK_RATE_HZ: u32 = 100;
+pub const portMAX_DELAY: TickType_t = 0xffffffff;
+
+extern "C" {
+    pub fn vPortEnterCritical();
+    pub fn vPortExitCritical();
+    pub fn vTaskDelay(xTicksToDelay: TickType_t);
+}
@@ -0,0 +1,15 @@
+//! FRC1 hardware timer driver from `driver/hw_timer.h`.
+use super::{
+    error::esp_err_t,
+    ffi::*,
+};
+
+pub type hw_timer_callback_t = Option<unsafe extern "C" fn(arg: *mut xtensa_void)>;
+pub type nmi_callback_t = Option<unsafe extern "C" fn()>;
+
+extern "C" {
+    pub fn hw_timer_init(callback: hw_timer_callback_t, arg: *mut xtensa_void) -> esp_err_t;
+    pub fn hw_timer_deinit() -> esp_err_t;
+    pub fn hw_timer_alarm_us(value: u32, reload: bool) -> esp_err_t;
+    pub fn hw_timer_disarm() -> esp_err_t;
+}
@@ -0,0 +1,10 @@
+//! Functions of the ROM, which are exported by the `esp8266.rom.ld` linker script.
+use super::hw_timer::nmi_callback_t;
+
+extern "C" {
+    pub fn ets_delay_us(us: u32);
+    /// CPU frequency in MHz
+    pub fn ets_get_cpu_frequency() -> u32;
+    /// Routes FRC1 timer interrupt to NMI and sets its handler
+    pub fn NmiTimSetFunc(func: nmi_callback_t);
+}
@@ -0,0 +1,7 @@
+//! Sleep modes from `esp_sleep.h`.
+
+extern "C" {
+    /// Enters deep sleep. Chip is reset after `time_in_us` or by the wake line, zero time means
+    /// sleeping until the wake line is pulled low
+    pub fn esp_deep_sleep(time_in_us: u64) -> !;
+}
@@ -0,0 +1,80 @@
+//! SPI driver from `driver/spi.h`.
+//!
+//! Bit fields of the unions are accessed through their `val` word.
+use super::{
+    error::esp_err_t,
+    ffi::*,
+};
+
+pub type spi_host_t = xtensa_uint;
+pub const spi_host_t_CSPI_HOST: spi_host_t = 0;
+pub const spi_host_t_HSPI_HOST: spi_host_t = 1;
+
+pub type spi_clk_div_t = xtensa_uint;
+pub const spi_clk_div_t_SPI_2MHz_DIV: spi_clk_div_t = 40;
+pub const spi_clk_div_t_SPI_4MHz_DIV: spi_clk_div_t = 20;
+pub const spi_clk_div_t_SPI_5MHz_DIV: spi_clk_div_t = 16;
+pub const spi_clk_div_t_SPI_8MHz_DIV: spi_clk_div_t = 10;
+pub const spi_clk_div_t_SPI_10MHz_DIV: spi_clk_div_t = 8;
+pub const spi_clk_div_t_SPI_16MHz_DIV: spi_clk_div_t = 5;
+pub const spi_clk_div_t_SPI_20MHz_DIV: spi_clk_div_t = 4;
+pub const spi_clk_div_t_SPI_40MHz_DIV: spi_clk_div_t = 2;
+pub const spi_clk_div_t_SPI_80MHz_DIV: spi_clk_div_t = 1;
+
+pub type spi_mode_t = xtensa_uint;
+pub const spi_mode_t_SPI_MASTER_MODE: spi_mode_t = 0;
+pub const spi_mode_t_SPI_SLAVE_MODE: spi_mode_t = 1;
+
+pub type spi_event_t = xtensa_uint;
+pub const spi_event_t_SPI_INIT_EVENT: spi_event_t = 0;
+pub const spi_event_t_SPI_TRANS_START_EVENT: spi_event_t = 1;
+pub const spi_event_t_SPI_TRANS_DONE_EVENT: spi_event_t = 2;
+pub const spi_event_t_SPI_DEINIT_EVENT: spi_event_t = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union spi_interface_t {
+    pub val: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union spi_intr_enable_t {
+    pub val: u32,
+}
+
+pub type spi_event_callback_t = Option<unsafe extern "C" fn(event: xtensa_int, arg: *mut xtensa_void)>;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct spi_config_t {
+    pub interface: spi_interface_t,
+    pub intr_enable: spi_intr_enable_t,
+    pub event_cb: spi_event_callback_t,
+    pub mode: spi_mode_t,
+    pub clk_div: spi_clk_div_t,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union spi_trans_t__bindgen_ty_1 {
+    pub val: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct spi_trans_t {
+    pub cmd: *mut u16,
+    pub addr: *mut u32,
+    pub mosi: *mut u32,
+    pub miso: *mut u32,
+    pub bits: spi_trans_t__bindgen_ty_1,
+}
+
+extern "C" {
+    pub fn spi_init(host: spi_host_t, config: *mut spi_config_t) -> esp_err_t;
+    pub fn spi_deinit(host: spi_host_t) -> esp_err_t;
+    pub fn spi_trans(host: spi_host_t, trans: *mut spi_trans_t) -> esp_err_t;
+    pub fn spi_slave_get_status(host: spi_host_t, status: *mut u32) -> esp_err_t;
+    pub fn spi_slave_set_status(host: spi_host_t, status: *mut u32) -> esp_err_t;
+}
@@ -0,0 +1,6 @@
+//! Xtensa HAL from `xtensa/hal.h`. Functions are located in the ROM.
+
+extern "C" {
+    /// Returns value of the CCOUNT register, which is incremented every CPU cycle
+    pub fn xthal_get_ccount() -> u32;
+}
@@ -1,10 +1,10 @@
-use idf_sys::{
+use crate::sys::{
     system_event::*,
     ffi::*,
     wifi::*,
 };
 use alloc::boxed::Box;
-use idf_sys::error::{esp_err_t, esp_err_t_ESP_OK};
+use crate::sys::error::{esp_err_t, esp_err_t_ESP_OK};
 
 #[non_exhaustive]
 pub struct StaConnectedEvent {}
@@ -76,4 +76,81 @@ pub fn set_event_loop<F>(loop_handler: F)
 
         esp_event_loop_init(Some(event_loop_wrapper::<F>), closure_ptr as *mut xtensa_void);
     }
-}
\ No newline at end of file
+}
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        vec::Vec,
+    };
+    use crate::{
+        mock::{self, system_event::{dispatch_event, dispatch_sta_disconnected}},
+        peripherals::WiFiPeripherals,
+        wifi::{WiFiHardware, WiFiStaConfigurationBuilder},
+    };
+    use super::*;
+
+    #[derive(Debug, Eq, PartialEq)]
+    enum Received {
+        StaStarted,
+        StaConnected,
+        StaDisconnected(bool),
+        Unknown,
+    }
+
+    fn record_events() -> Arc<Mutex<Vec<Received>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let handler_events = events.clone();
+        set_event_loop(move |event| {
+            handler_events.lock().unwrap().push(match event {
+                SystemEvent::StaStarted => Received::StaStarted,
+                SystemEvent::StaConnected(_) => Received::StaConnected,
+                SystemEvent::StaDisconnected(event) => Received::StaDisconnected(
+                    event.reason == StaDisconnectReason::BasicRateIsNotSupported
+                ),
+                SystemEvent::Unknown => Received::Unknown,
+            });
+        });
+        events
+    }
+
+    #[test]
+    fn events_are_delivered_to_loop() {
+        let events = record_events();
+
+        let result = dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_STA_START));
+        assert_eq!(result, Some(esp_err_t_ESP_OK));
+        dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_STA_CONNECTED));
+        dispatch_event(system_event_t::new(system_event_id_t_SYSTEM_EVENT_AP_START));
+
+        assert_eq!(*events.lock().unwrap(), [
+            Received::StaStarted,
+            Received::StaConnected,
+            Received::Unknown,
+        ]);
+    }
+
+    #[test]
+    fn disconnect_reason_is_decoded() {
+        let events = record_events();
+
+        mock::wifi::drop_connection(wifi_err_reason_t_WIFI_REASON_BASIC_RATE_NOT_SUPPORT);
+        dispatch_sta_disconnected(wifi_err_reason_t_WIFI_REASON_BEACON_TIMEOUT);
+
+        assert_eq!(*events.lock().unwrap(), [
+            Received::StaDisconnected(true),
+            Received::StaDisconnected(false),
+        ]);
+    }
+
+    #[test]
+    fn wifi_events_reach_loop() {
+        let events = record_events();
+
+        let mut wifi = WiFiHardware::new(WiFiPeripherals {}).initialize().ok().unwrap();
+        let config = WiFiStaConfigurationBuilder::new().ssid("network").build().ok().unwrap();
+        wifi.set_sta_config(config).start().unwrap().connect().unwrap();
+
+        assert_eq!(*events.lock().unwrap(), [Received::StaStarted, Received::StaConnected]);
+    }
+}
@@ -0,0 +1,210 @@
+//! Monotonic time and delays.
+//!
+//! [Instant](struct.Instant.html) is a point in time measured by the microsecond system timer
+//! since boot. Intervals are represented by `core::time::Duration`, which is re-exported here.
+//!
+//! FreeRTOS APIs wait for whole ticks, [duration_to_ticks](fn.duration_to_ticks.html) converts
+//! the duration to ticks without knowing the tick rate. Tick-based methods of the drivers have
+//! `_timeout` counterparts, which take the duration.
+//!
+//! # Examples
+//! ```no_run
+//! # use idf_hal::time::*;
+//!
+//! let start = Instant::now();
+//!
+//! // Yields cpu to other tasks
+//! delay_ms(100);
+//! // Busy-waits, e.g. to satisfy the timing of a sensor protocol
+//! busy_wait_us(20);
+//!
+//! assert!(start.elapsed() >= Duration::from_micros(100_020));
+//! ```
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+pub use core::time::Duration;
+
+/// FreeRTOS tick count, which is taken by the tick-based methods of the drivers
+pub use crate::sys::freertos::TickType_t;
+
+use crate::{
+    delay::Delay,
+    sys::{
+        esp_timer::esp_timer_get_time,
+        freertos::*,
+        rom::ets_delay_us,
+    },
+};
+
+/// Monotonic point in time with microsecond resolution
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Instant {
+    us: u64,
+}
+
+impl Instant {
+    /// Current time of the system timer
+    pub fn now() -> Self {
+        Self { us: (unsafe { esp_timer_get_time() }) as u64 }
+    }
+
+    /// Time since boot in microseconds
+    pub fn as_micros(&self) -> u64 {
+        self.us
+    }
+
+    /// Time elapsed since this instant
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Time elapsed from `earlier` to this instant, or zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    /// Time elapsed from `earlier` to this instant, or `None` if `earlier` is later
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.us.checked_sub(earlier.us).map(Duration::from_micros)
+    }
+
+    /// Instant, which is `duration` later, truncated to microseconds. `None` on overflow
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        self.us.checked_add(duration_to_us(duration)?).map(|us| Self { us })
+    }
+
+    /// Instant, which is `duration` earlier, truncated to microseconds. `None` if it is before
+    /// boot
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        self.us.checked_sub(duration_to_us(duration)?).map(|us| Self { us })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+fn duration_to_us(duration: Duration) -> Option<u64> {
+    let us = duration.as_micros();
+    if us > u64::MAX as u128 { None } else { Some(us as u64) }
+}
+
+/// Converts `duration` to FreeRTOS ticks, rounding up, so the wait is never shorter than
+/// requested. Durations, which don't fit, are converted to `portMAX_DELAY` (wait forever)
+pub fn duration_to_ticks(duration: Duration) -> TickType_t {
+    let tick_period_ns = 1_000_000_000 / configTICK_RATE_HZ as u128;
+    let ticks = duration.as_nanos().div_ceil(tick_period_ns);
+
+    if ticks >= portMAX_DELAY as u128 {
+        portMAX_DELAY
+    } else {
+        ticks as TickType_t
+    }
+}
+
+/// Busy-waits for `us` microseconds. Other tasks of the same or lower priority don't run
+/// meanwhile, so it should only be used for short delays. Unlike
+/// [Delay::delay_us](../delay/struct.Delay.html#method.delay_us), it never yields
+pub fn busy_wait_us(us: u32) {
+    unsafe { ets_delay_us(us) };
+}
+
+/// Waits for `ms` milliseconds, yielding cpu to other tasks. See [Delay](../delay/struct.Delay.html)
+pub fn delay_ms(ms: u32) {
+    Delay::new().delay_ms(ms);
+}
+
+/// Waits for `duration`, yielding cpu to other tasks for whole ticks and busy-waiting the rest.
+/// Duration is rounded up to microseconds
+pub fn delay(duration: Duration) {
+    let mut delay = Delay::new();
+
+    delay.delay_ms(duration.as_millis().min(u32::MAX as u128) as u32);
+    busy_wait_us((duration.subsec_nanos() % 1_000_000).div_ceil(1000));
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+
+    use crate::timer::Timer;
+    use super::*;
+
+    #[test]
+    fn instant_arithmetic_saturates_and_checks_overflow() {
+        let start = Instant::now();
+        let later = start + Duration::from_millis(5);
+
+        assert_eq!(later - start, Duration::from_millis(5));
+        assert_eq!(start - later, Duration::ZERO);
+        assert_eq!(start.checked_duration_since(later), None);
+        assert_eq!(start.checked_sub(Duration::from_micros(start.as_micros() + 1)), None);
+    }
+
+    #[test]
+    fn durations_are_rounded_up_to_ticks() {
+        assert_eq!(duration_to_ticks(Duration::ZERO), 0);
+        assert_eq!(duration_to_ticks(Duration::from_nanos(1)), 1);
+        assert_eq!(duration_to_ticks(Duration::from_millis(10)), 1);
+        assert_eq!(duration_to_ticks(Duration::from_millis(11)), 2);
+        assert_eq!(duration_to_ticks(Duration::MAX), portMAX_DELAY);
+    }
+
+    #[test]
+    fn busy_wait_does_not_yield() {
+        let expired = Arc::new(AtomicBool::new(false));
+        let timer_expired = expired.clone();
+        let _timer = Timer::once(Duration::from_millis(1), move || {
+            timer_expired.store(true, Ordering::Relaxed);
+        }).ok().unwrap();
+        let start = Instant::now();
+
+        busy_wait_us(20_000);
+
+        assert_eq!(start.elapsed(), Duration::from_micros(20_000));
+        assert!(!expired.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn delay_is_rounded_up_to_microseconds() {
+        let start = Instant::now();
+
+        delay(Duration::from_nanos(2_345_001));
+
+        assert_eq!(start.elapsed(), Duration::from_micros(2_346));
+    }
+}
@@ -0,0 +1,294 @@
+//! Hardware and software timers.
+//!
+//! [Timer](struct.Timer.html) is a software timer, which calls the closure from the FreeRTOS
+//! timer task. Any number of software timers can be created without owning peripherals.
+//!
+//! [HwTimer](struct.HwTimer.html) wraps FRC1 timer, which calls the closure from the interrupt
+//! context after the given period, once or periodically. Timer is owned through
+//! `OwnedPeripherals::hw_timer`.
+//!
+//! Timer interrupt can be routed to NMI, which is not delayed by critical sections and other
+//! interrupts, so the callback jitter is minimal. NMI callback must not use critical sections,
+//! FreeRTOS API and any state shared with the tasks, except atomics.
+//!
+//! **NOTE:** Callbacks are executed in the interrupt context - they should be short, should not
+//! block and should not allocate memory
+//!
+//! # Examples
+//! ```no_run
+//! # use core::sync::atomic::{AtomicU32, Ordering};
+//! # use idf_hal::{
+//! #     peripherals::Peripherals,
+//! #     timer::*,
+//! # };
+//!
+//! static TICKS: AtomicU32 = AtomicU32::new(0);
+//!
+//! let peripherals = Peripherals::take().unwrap();
+//! let mut timer = HwTimer::new(peripherals.hw_timer);
+//!
+//! timer
+//!     .set_callback(HwTimerSource::Frc1, || { TICKS.fetch_add(1, Ordering::Relaxed); })
+//!     .ok().unwrap()
+//!     .start(1000, HwTimerMode::AutoReload)
+//!     .ok().unwrap();
+//! ```
+use alloc::boxed::Box;
+use core::ptr::null_mut;
+
+mod software;
+pub use software::*;
+
+use crate::{
+    critical_section,
+    peripherals::HwTimerPeripherals,
+    sys::{
+        error::*,
+        ffi::*,
+        hw_timer::*,
+        rom::NmiTimSetFunc,
+    },
+};
+
+const MIN_ONE_SHOT_PERIOD_US: u32 = 10;
+const MIN_AUTO_RELOAD_PERIOD_US: u32 = 50;
+/// FRC1 counter is 23-bit wide and is clocked at 5 MHz
+const MAX_PERIOD_US: u32 = 0x199999;
+
+type TimerHandler = Box<dyn FnMut() + Send + 'static>;
+
+/// Hardware timer error
+#[derive(Debug)]
+pub enum HwTimerError {
+    /// Period should be in 10..=1677721 us range for one-shot timer and in 50..=1677721 us range
+    /// for auto-reload timer
+    InvalidPeriod,
+    /// Timer can't be started without callback
+    NoCallback,
+    /// Internal IDF error
+    IdfError(esp_err_t),
+}
+
+/// Interrupt, which calls the timer callback
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HwTimerSource {
+    /// Regular FRC1 interrupt
+    Frc1,
+    /// Non-maskable interrupt
+    Nmi,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HwTimerMode {
+    /// Callback is called once after the period
+    OneShot,
+    /// Callback is called every period until the timer is stopped
+    AutoReload,
+}
+
+unsafe extern "C" fn timer_handler_wrapper(ctx: *mut xtensa_void) {
+    let handler = &mut *(ctx as *mut TimerHandler);
+    handler();
+}
+
+// NMI handler doesn't receive context, so the handler of the single timer is kept in the static.
+// Should only be modified inside of the critical section while NMI is not routed to the handler
+static mut NMI_HANDLER: *mut TimerHandler = null_mut();
+
+unsafe extern "C" fn nmi_handler_wrapper() {
+    let handler = NMI_HANDLER;
+    if !handler.is_null() {
+        (*handler)();
+    }
+}
+
+/// FRC1 hardware timer. Callback is removed when the timer is dropped or
+/// [released](#method.release)
+pub struct HwTimer {
+    handler: *mut TimerHandler,
+    source: HwTimerSource,
+}
+
+// Handler is required to be `Send` and is accessed only by the ISR until removal
+unsafe impl Send for HwTimer {}
+
+impl HwTimer {
+    pub fn new(_peripherals: HwTimerPeripherals) -> Self {
+        Self {
+            handler: null_mut(),
+            source: HwTimerSource::Frc1,
+        }
+    }
+
+    /// Sets `callback`, which is called from the interrupt selected by `source` when the timer
+    /// expires. Previous callback is removed and the timer is stopped
+    pub fn set_callback<F>(&mut self, source: HwTimerSource, callback: F) -> Result<&mut Self, HwTimerError>
+        where F: FnMut() + Send + 'static
+    {
+        self.remove_callback();
+
+        let handler: *mut TimerHandler = Box::into_raw(Box::new(Box::new(callback)));
+
+        unsafe {
+            let err = hw_timer_init(Some(timer_handler_wrapper), handler as *mut xtensa_void);
+            if err != esp_err_t_ESP_OK {
+                drop(Box::from_raw(handler));
+                return Err(HwTimerError::IdfError(err));
+            }
+
+            if source == HwTimerSource::Nmi {
+                critical_section::free(|| NMI_HANDLER = handler);
+                NmiTimSetFunc(Some(nmi_handler_wrapper));
+            }
+        }
+
+        self.handler = handler;
+        self.source = source;
+        Ok(self)
+    }
+
+    /// Stops the timer and removes its callback
+    pub fn remove_callback(&mut self) -> &mut Self {
+        if self.handler.is_null() {
+            return self;
+        }
+
+        unsafe {
+            hw_timer_disarm();
+            hw_timer_deinit();
+
+            if self.source == HwTimerSource::Nmi {
+                NmiTimSetFunc(None);
+                critical_section::free(|| NMI_HANDLER = null_mut());
+            }
+
+            drop(Box::from_raw(self.handler));
+        }
+        self.handler = null_mut();
+        self
+    }
+
+    /// Starts the timer with `period_us` period. Running timer is restarted with the new period
+    pub fn start(&mut self, period_us: u32, mode: HwTimerMode) -> Result<&mut Self, HwTimerError> {
+        let min_period_us = match mode {
+            HwTimerMode::OneShot => MIN_ONE_SHOT_PERIOD_US,
+            HwTimerMode::AutoReload => MIN_AUTO_RELOAD_PERIOD_US,
+        };
+        if period_us < min_period_us || period_us > MAX_PERIOD_US {
+            return Err(HwTimerError::InvalidPeriod);
+        }
+
+        if self.handler.is_null() {
+            return Err(HwTimerError::NoCallback);
+        }
+
+        match unsafe { hw_timer_alarm_us(period_us, mode == HwTimerMode::AutoReload) } {
+            esp_err_t_ESP_OK => Ok(self),
+            err => Err(HwTimerError::IdfError(err)),
+        }
+    }
+
+    /// Stops the timer, keeping its callback
+    pub fn stop(&mut self) -> &mut Self {
+        if !self.handler.is_null() {
+            unsafe { hw_timer_disarm() };
+        }
+        self
+    }
+
+    /// Stops the timer, removes its callback and returns peripherals back
+    pub fn release(mut self) -> HwTimerPeripherals {
+        self.remove_callback();
+        HwTimerPeripherals {}
+    }
+}
+
+impl Drop for HwTimer {
+    fn drop(&mut self) {
+        self.remove_callback();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    use crate::mock;
+    use super::*;
+
+    fn counting_callback(count: &Arc<AtomicU32>, increment: u32) -> impl FnMut() + Send + 'static {
+        let count = count.clone();
+        move || { count.fetch_add(increment, Ordering::Relaxed); }
+    }
+
+    #[test]
+    fn period_is_validated_for_each_mode() {
+        let mut timer = HwTimer::new(HwTimerPeripherals {});
+        assert!(matches!(timer.start(100, HwTimerMode::OneShot), Err(HwTimerError::NoCallback)));
+        timer.set_callback(HwTimerSource::Frc1, || {}).ok().unwrap();
+
+        assert!(matches!(timer.start(9, HwTimerMode::OneShot), Err(HwTimerError::InvalidPeriod)));
+        assert!(matches!(timer.start(49, HwTimerMode::AutoReload), Err(HwTimerError::InvalidPeriod)));
+        assert!(matches!(timer.start(MAX_PERIOD_US + 1, HwTimerMode::OneShot), Err(HwTimerError::InvalidPeriod)));
+        assert!(!mock::hw_timer::is_armed());
+
+        timer.start(MAX_PERIOD_US, HwTimerMode::AutoReload).ok().unwrap();
+        assert_eq!(mock::hw_timer::period_us(), MAX_PERIOD_US);
+        assert!(mock::hw_timer::is_reload());
+    }
+
+    #[test]
+    fn callback_is_called_once_or_periodically() {
+        let count = Arc::new(AtomicU32::new(0));
+        let mut timer = HwTimer::new(HwTimerPeripherals {});
+        timer.set_callback(HwTimerSource::Frc1, counting_callback(&count, 1)).ok().unwrap();
+
+        timer.start(100, HwTimerMode::OneShot).ok().unwrap();
+        assert!(mock::hw_timer::fire());
+        assert!(!mock::hw_timer::fire());
+
+        timer.start(1000, HwTimerMode::AutoReload).ok().unwrap();
+        assert!(mock::hw_timer::fire());
+        assert!(mock::hw_timer::fire());
+        timer.stop();
+        assert!(!mock::hw_timer::fire());
+
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn callback_is_routed_to_nmi_and_removed_on_drop() {
+        let count = Arc::new(AtomicU32::new(0));
+        let mut timer = HwTimer::new(HwTimerPeripherals {});
+        timer.set_callback(HwTimerSource::Frc1, counting_callback(&count, 1)).ok().unwrap();
+
+        // Previous callback is dropped when the new one is set
+        timer.set_callback(HwTimerSource::Nmi, counting_callback(&count, 10)).ok().unwrap();
+        assert_eq!(Arc::strong_count(&count), 2);
+        assert!(mock::hw_timer::is_nmi());
+
+        timer.start(500, HwTimerMode::OneShot).ok().unwrap();
+        assert!(mock::hw_timer::fire());
+        assert_eq!(count.load(Ordering::Relaxed), 10);
+
+        drop(timer);
+        assert!(!mock::hw_timer::is_nmi());
+        assert!(!mock::hw_timer::fire());
+        assert_eq!(Arc::strong_count(&count), 1);
+    }
+
+    #[test]
+    fn failed_initialization_keeps_timer_without_callback() {
+        let mut timer = HwTimer::new(HwTimerPeripherals {});
+        mock::inject_error("hw_timer_init", -1);
+
+        assert!(matches!(
+            timer.set_callback(HwTimerSource::Frc1, || {}),
+            Err(HwTimerError::IdfError(-1))
+        ));
+        assert!(matches!(timer.start(100, HwTimerMode::OneShot), Err(HwTimerError::NoCallback)));
+    }
+}
@@ -0,0 +1,309 @@
+//! Software timers.
+//!
+//! [Timer](struct.Timer.html) calls the closure once or periodically after the given duration.
+//! Timers are implemented by esp_timer on top of the FreeRTOS timer task, so callbacks run in the
+//! task context and may use FreeRTOS API and critical sections. Time is rounded up to the FreeRTOS
+//! tick. Any number of timers can be created, timer is cancelled when its handle is dropped.
+//!
+//! **NOTE:** All callbacks share the timer task - they should be short and should not block
+//!
+//! # Examples
+//! ```no_run
+//! # use core::{
+//! #     sync::atomic::{AtomicBool, Ordering},
+//! #     time::Duration,
+//! # };
+//! # use idf_hal::timer::*;
+//!
+//! static BLINK: AtomicBool = AtomicBool::new(false);
+//!
+//! let blink = Timer::periodic(Duration::from_millis(500), || {
+//!     BLINK.fetch_xor(true, Ordering::Relaxed);
+//! }).ok().unwrap();
+//! let timeout = Timer::once(Duration::from_secs(10), || { /* ... */ }).ok().unwrap();
+//!
+//! // Timeout is cancelled, blinking continues
+//! timeout.cancel();
+//! ```
+use alloc::boxed::Box;
+use core::{
+    ptr::null_mut,
+    time::Duration,
+};
+
+use crate::{
+    critical_section,
+    sys::{
+        error::*,
+        esp_timer::*,
+        ffi::*,
+    },
+};
+
+type TimerCallback = Box<dyn FnMut() + Send + 'static>;
+
+const TIMER_NAME: &[u8] = b"idf-hal\0";
+
+/// Software timer error
+#[derive(Debug)]
+pub enum TimerError {
+    /// Period of the periodic timer should not be zero
+    InvalidPeriod,
+    /// Internal IDF error
+    IdfError(esp_err_t),
+}
+
+/// Callback with the state shared by the timer task and the timer handle. `dropped` is accessed
+/// only inside critical sections
+struct TimerShared {
+    callback: TimerCallback,
+    /// Handle has been dropped, so the callback is not called anymore
+    dropped: bool,
+    /// Timer, which frees the shared state from the timer task
+    release_timer: esp_timer_handle_t,
+}
+
+unsafe extern "C" fn timer_callback_wrapper(arg: *mut xtensa_void) {
+    let shared = arg as *mut TimerShared;
+
+    // Timer task calls the expired timer even if it has been stopped after the expiration
+    if critical_section::free(|| (*shared).dropped) {
+        return;
+    }
+    ((*shared).callback)();
+}
+
+/// Called by the timer task after it has processed the stop and delete of the dropped timer, so
+/// the callback of that timer can't be called anymore
+unsafe extern "C" fn timer_release_wrapper(arg: *mut xtensa_void) {
+    let shared = Box::from_raw(arg as *mut TimerShared);
+    esp_timer_delete(shared.release_timer);
+}
+
+/// Handle of the running software timer. Timer is cancelled when the handle is dropped or
+/// [cancelled](#method.cancel). Handle can be dropped by the timer callback itself.
+///
+/// Callback is dropped later by the timer task, because the timer task may be about to call it
+/// when the handle is dropped
+pub struct Timer {
+    handle: esp_timer_handle_t,
+    shared: *mut TimerShared,
+}
+
+// Callback is required to be `Send` and is accessed only by the timer task until deletion
+unsafe impl Send for Timer {}
+
+impl Timer {
+    /// Starts the timer, which calls `callback` once after `timeout`
+    pub fn once<F>(timeout: Duration, callback: F) -> Result<Self, TimerError>
+        where F: FnOnce() + Send + 'static
+    {
+        let mut callback = Some(callback);
+        let timer = Self::create(Box::new(move || {
+            if let Some(callback) = callback.take() {
+                callback();
+            }
+        }))?;
+
+        match unsafe { esp_timer_start_once(timer.handle, duration_to_us(timeout)) } {
+            esp_err_t_ESP_OK => Ok(timer),
+            err => Err(TimerError::IdfError(err)),
+        }
+    }
+
+    /// Starts the timer, which calls `callback` every `period` until the timer is cancelled
+    pub fn periodic<F>(period: Duration, callback: F) -> Result<Self, TimerError>
+        where F: FnMut() + Send + 'static
+    {
+        let period_us = duration_to_us(period);
+        if period_us == 0 {
+            return Err(TimerError::InvalidPeriod);
+        }
+
+        let timer = Self::create(Box::new(callback))?;
+
+        match unsafe { esp_timer_start_periodic(timer.handle, period_us) } {
+            esp_err_t_ESP_OK => Ok(timer),
+            err => Err(TimerError::IdfError(err)),
+        }
+    }
+
+    fn create(callback: TimerCallback) -> Result<Self, TimerError> {
+        let shared = Box::into_raw(Box::new(TimerShared { callback, dropped: false, release_timer: null_mut() }));
+
+        let args = esp_timer_create_args_t {
+            callback: Some(timer_callback_wrapper),
+            arg: shared as *mut xtensa_void,
+            dispatch_method: esp_timer_dispatch_t_ESP_TIMER_TASK,
+            name: TIMER_NAME.as_ptr() as *const xtensa_char,
+        };
+
+        let mut handle = null_mut();
+        let err = unsafe { esp_timer_create(&args, &mut handle) };
+        if err != esp_err_t_ESP_OK {
+            drop(unsafe { Box::from_raw(shared) });
+            return Err(TimerError::IdfError(err));
+        }
+
+        Ok(Self { handle, shared })
+    }
+
+    /// Stops the timer and drops its callback
+    pub fn cancel(self) {}
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let shared = self.shared;
+        critical_section::free(|| unsafe { (*shared).dropped = true });
+
+        unsafe {
+            // Fails if one-shot timer has already expired
+            esp_timer_stop(self.handle);
+            esp_timer_delete(self.handle);
+        }
+
+        // Release timer is started after the stop and delete are queued to the timer task, so it
+        // expires after the last call of the callback
+        let args = esp_timer_create_args_t {
+            callback: Some(timer_release_wrapper),
+            arg: shared as *mut xtensa_void,
+            dispatch_method: esp_timer_dispatch_t_ESP_TIMER_TASK,
+            name: TIMER_NAME.as_ptr() as *const xtensa_char,
+        };
+
+        unsafe {
+            let mut release_timer = null_mut();
+            if esp_timer_create(&args, &mut release_timer) != esp_err_t_ESP_OK {
+                // Shared state is leaked, it is never safe to free it without the timer task
+                return;
+            }
+
+            (*shared).release_timer = release_timer;
+            if esp_timer_start_once(release_timer, 0) != esp_err_t_ESP_OK {
+                esp_timer_delete(release_timer);
+            }
+        }
+    }
+}
+
+fn duration_to_us(duration: Duration) -> u64 {
+    let us = duration.as_micros();
+    // Rounded up, so the timer never expires earlier than requested
+    let us = if Duration::from_micros(us as u64) < duration { us + 1 } else { us };
+    us.min(u64::MAX as u128) as u64
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex, atomic::{AtomicU32, Ordering}},
+        vec::Vec,
+    };
+
+    use crate::mock;
+    use super::*;
+
+    fn counter() -> (Arc<AtomicU32>, impl FnMut() + Send + 'static) {
+        let count = Arc::new(AtomicU32::new(0));
+        let callback_count = count.clone();
+        (count, move || { callback_count.fetch_add(1, Ordering::SeqCst); })
+    }
+
+    #[test]
+    fn timers_expire_in_deadline_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let periodic_log = log.clone();
+        let periodic = Timer::periodic(Duration::from_millis(30), move || {
+            periodic_log.lock().unwrap().push(("periodic", mock::time_us()));
+        }).ok().unwrap();
+        let once_log = log.clone();
+        let once = Timer::once(Duration::from_millis(50), move || {
+            once_log.lock().unwrap().push(("once", mock::time_us()));
+        }).ok().unwrap();
+
+        mock::esp_timer::advance(100_000);
+
+        assert_eq!(*log.lock().unwrap(), [
+            ("periodic", 30_000), ("once", 50_000), ("periodic", 60_000), ("periodic", 90_000),
+        ]);
+        drop((periodic, once));
+    }
+
+    #[test]
+    fn timeout_is_rounded_up_to_microseconds() {
+        let (count, callback) = counter();
+        let _timer = Timer::once(Duration::from_nanos(1500), callback).ok().unwrap();
+
+        mock::esp_timer::advance(1);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        mock::esp_timer::advance(1);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn zero_period_is_rejected() {
+        assert!(matches!(Timer::periodic(Duration::from_nanos(0), || {}), Err(TimerError::InvalidPeriod)));
+    }
+
+    #[test]
+    fn cancelled_timer_is_freed_by_timer_task() {
+        let (count, callback) = counter();
+        let timer = Timer::periodic(Duration::from_millis(10), callback).ok().unwrap();
+        mock::esp_timer::advance(10_000);
+
+        timer.cancel();
+        // Callback is kept until the timer task processes the cancellation
+        assert_eq!(Arc::strong_count(&count), 2);
+
+        mock::esp_timer::advance(100_000);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(Arc::strong_count(&count), 1);
+        assert_eq!(mock::esp_timer::timer_count(), 0);
+    }
+
+    #[test]
+    fn drop_while_callback_is_pending() {
+        let (count, callback) = counter();
+        let timer = Timer::once(Duration::from_millis(10), callback).ok().unwrap();
+
+        // Timer expires, but the timer task doesn't run until the handle is dropped
+        mock::advance_time(20_000);
+        drop(timer);
+
+        mock::esp_timer::advance(0);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert_eq!(Arc::strong_count(&count), 1);
+        assert_eq!(mock::esp_timer::timer_count(), 0);
+    }
+
+    struct DropCounter(Arc<AtomicU32>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(100, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn drop_from_own_callback() {
+        let slot: Arc<Mutex<Option<Timer>>> = Arc::new(Mutex::new(None));
+        let count = Arc::new(AtomicU32::new(0));
+        let drop_counter = DropCounter(count.clone());
+        let (callback_slot, callback_count) = (slot.clone(), count.clone());
+
+        let timer = Timer::periodic(Duration::from_millis(10), move || {
+            let _state = &drop_counter;
+            callback_count.fetch_add(1, Ordering::SeqCst);
+            drop(callback_slot.lock().unwrap().take());
+            // Callback state is still valid after its handle is dropped
+            callback_count.fetch_add(1, Ordering::SeqCst);
+        }).ok().unwrap();
+        *slot.lock().unwrap() = Some(timer);
+
+        mock::esp_timer::advance(50_000);
+
+        assert_eq!(count.load(Ordering::SeqCst), 102);
+        assert_eq!(mock::esp_timer::timer_count(), 0);
+    }
+}
@@ -1,9 +1,10 @@
 use crate::{
     gpio::*,
     peripherals::UartPeripherals,
+    time::{Duration, TickType_t, duration_to_ticks},
 };
 
-use idf_sys::{
+use crate::sys::{
     uart::*,
     ffi::*,
     error::*,
@@ -13,52 +14,22 @@ use core::{
     marker::PhantomData,
 };
 
+pub use crate::gpio::CaptureGpioPin;
+#[cfg(feature = "embedded-hal")]
+pub use crate::embedded_hal_impls::UartIoError;
+
 pub enum UartConfigError {
     InvalidBaudRate,
     InvalidRxThreshold,
     InvalidRxBufferSize,
     InvalidTxBufferSize,
+    /// Uart pins were already captured from `GpioHardware` by another driver
+    PinsNotAvailable,
     Unknown,
     #[deprecated(note = "Check UartConfigError with default match clause (_ => {...})")]
     __NonExhaustive,
 }
 
-pub trait CaptureGpioPin {
-    fn capture_pin(gpio_hw: &mut GpioHardware);
-    fn release_pin(gpio_hw: &mut GpioHardware);
-}
-
-impl CaptureGpioPin for Gpio1 {
-    fn capture_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio1.take(); }
-    fn release_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio1.replace( Gpio1::new() ); }
-}
-
-impl CaptureGpioPin for Gpio2 {
-    fn capture_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio2.take(); }
-    fn release_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio2.replace( Gpio2::new() ); }
-}
-
-impl CaptureGpioPin for Gpio3 {
-    fn capture_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio3.take(); }
-    fn release_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio3.replace( Gpio3::new() ); }
-}
-
-impl CaptureGpioPin for Gpio13 {
-    fn capture_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio13.take(); }
-    fn release_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio13.replace( Gpio13::new() ); }
-}
-
-impl CaptureGpioPin for Gpio15 {
-    fn capture_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio15.take(); }
-    fn release_pin(gpio_hw: &mut GpioHardware) { gpio_hw.gpio15.replace( Gpio15::new() ); }
-}
-
-impl CaptureGpioPin for PhantomPin {
-    fn capture_pin(_gpio_hw: &mut GpioHardware) {}
-    fn release_pin(_gpio_hw: &mut GpioHardware) {}
-}
-
-
 pub trait UartGpioPins {
     type TxPin : GpioPin + CaptureGpioPin;
     type RxPin : GpioPin + CaptureGpioPin;
@@ -133,6 +104,7 @@ impl UartCanRead for Uart0AltHardware {}
 
 impl Uart0AltHardware {
     fn new() -> Self { Uart0AltHardware }
+    pub fn into_normal_mode(self) -> Uart0Hardware { Uart0Hardware::new() }
 }
 
 #[non_exhaustive]
@@ -345,10 +317,15 @@ impl<Uart: UartHardwareInstance> UartInitializer<Uart> {
         }
     }
 
-    // TODO: capture pins
     pub fn initialize(mut self, gpio_hw: &mut GpioHardware)
         -> Result<Uart::InitializedType, UartConfigError>
     {
+        let marker = UartInitializedMarker::new(self.config.flow_ctrl);
+
+        if !marker.are_pins_available::<Uart>(gpio_hw) {
+            return Err(UartConfigError::PinsNotAvailable);
+        }
+
         unsafe {
             let uart_num = Uart::UART_PORT_NUM.map_to_ffi();
 
@@ -365,43 +342,66 @@ impl<Uart: UartHardwareInstance> UartInitializer<Uart> {
             ) != esp_err_t_ESP_OK {
                 return Err(UartConfigError::Unknown);
             }
-
-            <<Uart as UartHardwareInstance>::Pins as UartGpioPins>::TxPin::capture_pin(gpio_hw);
-            <<Uart as UartHardwareInstance>::Pins as UartGpioPins>::RxPin::capture_pin(gpio_hw);
-            match self.config.flow_ctrl {
-                uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS => {
-                    <<Uart as UartHardwareInstance>::Pins as UartGpioPins>::CtsPin::capture_pin(
-                        gpio_hw
-                    );
-                },
-                uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_RTS => {
-                    <<Uart as UartHardwareInstance>::Pins as UartGpioPins>::RtsPin::capture_pin(
-                        gpio_hw
-                    );
-                }
-                uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS_RTS => {
-                    <<Uart as UartHardwareInstance>::Pins as UartGpioPins>::CtsPin::capture_pin(
-                        gpio_hw
-                    );
-                    <<Uart as UartHardwareInstance>::Pins as UartGpioPins>::RtsPin::capture_pin(
-                        gpio_hw
-                    );
-                }
-                _ => {}
-            }
-
-            return Ok(Uart::InitializedType::build(UartInitializedMarker::new()));
-
         }
+
+        marker.capture_pins::<Uart>(gpio_hw);
+        Ok(Uart::InitializedType::build(marker))
     }
 }
 
 
+/// Describes pins captured by the initialized uart
 #[non_exhaustive]
-pub struct UartInitializedMarker;
+pub struct UartInitializedMarker {
+    flow_ctrl: uart_hw_flowcontrol_t,
+}
+
+type TxPinOf<U> = <<U as UartHardwareInstance>::Pins as UartGpioPins>::TxPin;
+type RxPinOf<U> = <<U as UartHardwareInstance>::Pins as UartGpioPins>::RxPin;
+type CtsPinOf<U> = <<U as UartHardwareInstance>::Pins as UartGpioPins>::CtsPin;
+type RtsPinOf<U> = <<U as UartHardwareInstance>::Pins as UartGpioPins>::RtsPin;
 
 impl UartInitializedMarker {
-    fn new() -> Self { UartInitializedMarker }
+    fn new(flow_ctrl: uart_hw_flowcontrol_t) -> Self { UartInitializedMarker { flow_ctrl } }
+
+    fn uses_cts(&self) -> bool {
+        self.flow_ctrl == uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS
+            || self.flow_ctrl == uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS_RTS
+    }
+
+    fn uses_rts(&self) -> bool {
+        self.flow_ctrl == uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_RTS
+            || self.flow_ctrl == uart_hw_flowcontrol_t_UART_HW_FLOWCTRL_CTS_RTS
+    }
+
+    fn are_pins_available<U: UartHardwareInstance>(&self, gpio_hw: &GpioHardware) -> bool {
+        TxPinOf::<U>::is_pin_available(gpio_hw)
+            && RxPinOf::<U>::is_pin_available(gpio_hw)
+            && (!self.uses_cts() || CtsPinOf::<U>::is_pin_available(gpio_hw))
+            && (!self.uses_rts() || RtsPinOf::<U>::is_pin_available(gpio_hw))
+    }
+
+    fn capture_pins<U: UartHardwareInstance>(&self, gpio_hw: &mut GpioHardware) {
+        TxPinOf::<U>::capture_pin(gpio_hw);
+        RxPinOf::<U>::capture_pin(gpio_hw);
+        if self.uses_cts() {
+            CtsPinOf::<U>::capture_pin(gpio_hw);
+        }
+        if self.uses_rts() {
+            RtsPinOf::<U>::capture_pin(gpio_hw);
+        }
+    }
+
+    fn release_pins<U: UartHardwareInstance>(&self, gpio_hw: &mut GpioHardware) {
+        TxPinOf::<U>::release_pin(gpio_hw);
+        RxPinOf::<U>::release_pin(gpio_hw);
+        if self.uses_cts() {
+            CtsPinOf::<U>::release_pin(gpio_hw);
+        }
+        if self.uses_rts() {
+            RtsPinOf::<U>::release_pin(gpio_hw);
+        }
+    }
 }
 
 pub trait Uart {
@@ -411,51 +411,57 @@ pub trait Uart {
 }
 
 
-#[non_exhaustive]
-pub struct Uart0;
-impl Uart0 {
-    fn new() -> Self { Uart0 }
-}
-
-#[non_exhaustive]
-pub struct Uart0Alt;
-impl Uart0Alt {
-    fn new() -> Self { Uart0Alt }
-}
+macro_rules! define_initialized_uarts {
+    ($($type:ident : $hardware:ident),+) => {$(
+        #[non_exhaustive]
+        pub struct $type {
+            marker: UartInitializedMarker,
+        }
 
-#[non_exhaustive]
-pub struct Uart1;
-impl Uart1 {
-    fn new() -> Self { Uart1 }
-}
+        impl Uart for $type {
+            type Hardware = $hardware;
 
-impl Uart for Uart0 {
-    type Hardware = Uart0Hardware;
+            fn build(marker: UartInitializedMarker) -> Self { Self { marker } }
+        }
 
-    fn build(_: UartInitializedMarker) -> Self { Self::new() }
-}
-impl Uart for Uart0Alt {
-    type Hardware = Uart0AltHardware;
+        impl $type {
+            /// Uninstalls uart driver, returns captured pins to `gpio_hw` and returns uart
+            /// hardware back, so pins or uart could be used in another configuration
+            pub fn deinitialize(self, gpio_hw: &mut GpioHardware) -> $hardware {
+                let uart_num = $hardware::UART_PORT_NUM.map_to_ffi();
+                unsafe { uart_driver_delete(uart_num) };
 
-    fn build(_: UartInitializedMarker) -> Self { Self::new() }
+                self.marker.release_pins::<$hardware>(gpio_hw);
+                $hardware::new()
+            }
+        }
+    )+}
 }
-impl Uart for Uart1 {
-    type Hardware = Uart1Hardware;
 
-    fn build(_: UartInitializedMarker) -> Self { Self::new() }
-}
+define_initialized_uarts!(
+    Uart0 : Uart0Hardware,
+    Uart0Alt : Uart0AltHardware,
+    Uart1 : Uart1Hardware
+);
 
+#[derive(Debug)]
 pub enum WaitError {
     Timeout,
 }
 
+#[derive(Debug)]
 pub enum ReadError {
     Timeout,
 }
 
 pub trait TransmittingUart {
     fn write_bytes(&mut self, data: &[u8]) -> usize;
-    fn wait_write_done(&mut self, ticks: usize) -> Result<(), WaitError>;
+    fn wait_write_done(&mut self, ticks: TickType_t) -> Result<(), WaitError>;
+
+    /// Waits up to `timeout` until all written bytes are transmitted
+    fn wait_write_done_timeout(&mut self, timeout: Duration) -> Result<(), WaitError> {
+        self.wait_write_done(duration_to_ticks(timeout))
+    }
 }
 
 impl<T : Uart> TransmittingUart for T where <T as Uart>::Hardware: UartCanWrite {
@@ -464,7 +470,7 @@ impl<T : Uart> TransmittingUart for T where <T as Uart>::Hardware: UartCanWrite
         unsafe { uart_write_bytes(uart_num, data.as_ptr(), data.len()) as usize }
     }
 
-    fn wait_write_done(&mut self, timeout: usize) -> Result<(), WaitError> {
+    fn wait_write_done(&mut self, timeout: TickType_t) -> Result<(), WaitError> {
         let uart_num = T::Hardware::UART_PORT_NUM.map_to_ffi();
         unsafe {
             if uart_wait_tx_done(uart_num, timeout) == esp_err_t_ESP_OK {
@@ -477,11 +483,16 @@ impl<T : Uart> TransmittingUart for T where <T as Uart>::Hardware: UartCanWrite
 }
 
 pub trait ReceivingUart {
-    fn read_bytes(&mut self, buffer: &mut[u8], timeout: usize) -> Result<usize, ReadError>;
+    fn read_bytes(&mut self, buffer: &mut[u8], timeout: TickType_t) -> Result<usize, ReadError>;
+
+    /// Reads bytes to `buffer`, waiting up to `timeout` for them
+    fn read_bytes_timeout(&mut self, buffer: &mut[u8], timeout: Duration) -> Result<usize, ReadError> {
+        self.read_bytes(buffer, duration_to_ticks(timeout))
+    }
 }
 
 impl<T: Uart> ReceivingUart for T where <T as Uart>::Hardware: UartCanRead {
-    fn read_bytes(&mut self, buffer: &mut[u8], timeout: usize) -> Result<usize, ReadError> {
+    fn read_bytes(&mut self, buffer: &mut[u8], timeout: TickType_t) -> Result<usize, ReadError> {
         let uart_num = T::Hardware::UART_PORT_NUM.map_to_ffi();
         unsafe {
             let written_bytes =
@@ -494,4 +505,96 @@ impl<T: Uart> ReceivingUart for T where <T as Uart>::Hardware: UartCanRead {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{
+        mock,
+        peripherals::GpioPeripherals,
+    };
+    use super::*;
+
+    fn hardware() -> (UartHardware, GpioHardware) {
+        (UartHardware::new(UartPeripherals {}), GpioHardware::new(GpioPeripherals {}))
+    }
+
+    #[test]
+    fn uart_transfers_data_through_fifo() {
+        let (mut uart, mut gpio) = hardware();
+        let mut initializer = UartInitializer::new(uart.uart0.take().unwrap());
+        initializer.set_baud_rate(115_200).ok().unwrap();
+        let mut uart0 = initializer.initialize(&mut gpio).ok().unwrap();
+
+        assert_eq!(mock::uart::config(0).unwrap().baud_rate, 115_200);
+        assert!(gpio.gpio1.is_none() && gpio.gpio3.is_none());
+        assert!(gpio.gpio13.is_some() && gpio.gpio15.is_some());
+
+        mock::uart::push_rx(0, b"hello");
+        let mut buffer = [0; 3];
+        assert_eq!(uart0.read_bytes(&mut buffer, 0).ok(), Some(3));
+        assert_eq!(&buffer, b"hel");
+        assert_eq!(uart0.read_bytes(&mut buffer, 0).ok(), Some(2));
+        assert_eq!(&buffer[..2], b"lo");
+        assert_eq!(uart0.read_bytes(&mut buffer, 0).ok(), Some(0));
+
+        assert_eq!(uart0.write_bytes(b"ping"), 4);
+        assert!(uart0.wait_write_done(0).is_ok());
+        assert_eq!(mock::uart::take_tx(0), b"ping");
+
+        let hardware = uart0.deinitialize(&mut gpio);
+        assert!(!mock::uart::is_driver_installed(0));
+        assert!(gpio.gpio1.is_some() && gpio.gpio3.is_some());
+
+        // Port could be initialized again
+        UartInitializer::new(hardware).initialize(&mut gpio).ok().unwrap();
+        assert!(mock::uart::is_driver_installed(0));
+    }
+
+    #[test]
+    fn flow_control_captures_cts_and_rts_pins() {
+        let (mut uart, mut gpio) = hardware();
+        let mut initializer = UartInitializer::new(uart.uart0.take().unwrap());
+        initializer.set_hw_control_flow(UartHwControlFlow::CtsRts).ok().unwrap();
+        let uart0 = initializer.initialize(&mut gpio).ok().unwrap();
+        assert!(gpio.gpio13.is_none() && gpio.gpio15.is_none());
+
+        uart0.deinitialize(&mut gpio);
+        assert!(gpio.gpio13.is_some() && gpio.gpio15.is_some());
+    }
+
+    #[test]
+    fn busy_pins_are_not_captured() {
+        let (mut uart, mut gpio) = hardware();
+        let _tx = gpio.gpio2.take().unwrap();
+
+        let result = UartInitializer::new(uart.uart1.take().unwrap()).initialize(&mut gpio);
+        assert!(matches!(result, Err(UartConfigError::PinsNotAvailable)));
+        assert!(mock::calls_of("uart_param_config").is_empty());
+    }
+
+    #[test]
+    fn failed_driver_install_keeps_pins() {
+        let (mut uart, mut gpio) = hardware();
+
+        mock::inject_error("uart_driver_install", esp_err_t_ESP_FAIL);
+        let result = UartInitializer::new(uart.uart1.take().unwrap()).initialize(&mut gpio);
+        assert!(matches!(result, Err(UartConfigError::Unknown)));
+        assert!(gpio.gpio2.is_some());
+        assert!(!mock::uart::is_driver_installed(1));
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let (mut uart, _) = hardware();
+        let mut initializer = UartInitializer::new(uart.uart0.take().unwrap());
+
+        assert!(matches!(initializer.set_baud_rate(100), Err(UartConfigError::InvalidBaudRate)));
+        assert!(matches!(
+            initializer.set_hw_control_flow_rx_threshold(128),
+            Err(UartConfigError::InvalidRxThreshold)
+        ));
+        assert!(matches!(initializer.set_rx_buffer_size(128), Err(UartConfigError::InvalidRxBufferSize)));
+        assert!(matches!(initializer.set_tx_buffer_size(128), Err(UartConfigError::InvalidTxBufferSize)));
+        assert!(initializer.set_tx_buffer_size(0).is_ok());
+    }
+}
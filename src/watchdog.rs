@@ -1,4 +1,4 @@
-use idf_sys::watchdog::*;
+use crate::sys::watchdog::*;
 
 pub fn reset_watchdog() {
     unsafe { esp_task_wdt_reset() };
@@ -17,7 +17,7 @@
 //!     .password("mypassword")
 //!     .build().ok().unwrap();
 //!
-//! let wifi_configurator = WiFiHardware::new(peripherals.wifi)
+//! let mut wifi_configurator = WiFiHardware::new(peripherals.wifi)
 //!     .initialize()
 //!     .ok().unwrap();
 //!
@@ -28,7 +28,7 @@
 //! ```
 use crate::peripherals::WiFiPeripherals;
 
-use idf_sys:: {
+use crate::sys:: {
     wifi::*,
     error::*,
     network_adapter::*,
@@ -811,4 +811,123 @@ impl WiFi {
             err => Err(WiFiConfigurationError::IdfError(err)),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::mock;
+    use super::*;
+
+    fn sta_config() -> WiFiStaConfiguration {
+        WiFiStaConfigurationBuilder::new()
+            .ssid("network")
+            .password("password")
+            .scan_threshold(WiFiScanThresholdBuilder::new().min_auth_mode(WiFiAuthMode::Wpa2Psk).build())
+            .build()
+            .ok().unwrap()
+    }
+
+    fn ap_config() -> WiFiApConfiguration {
+        WiFiApConfigurationBuilder::new()
+            .ssid("access point")
+            .auth_mode(WiFiAuthMode::OpenNetwork)
+            .channel(6)
+            .build()
+            .ok().unwrap()
+    }
+
+    #[test]
+    fn station_is_started_and_connected() {
+        let mut wifi = WiFiHardware::new(WiFiPeripherals {}).initialize().ok().unwrap();
+        assert!(mock::wifi::state().initialized);
+
+        wifi.set_sta_config(sta_config()).start().unwrap();
+        let state = mock::wifi::state();
+        assert!(state.started);
+        assert_eq!(state.mode, wifi_mode_t_WIFI_MODE_STA);
+        assert_eq!(&state.sta_config.unwrap().ssid[..8], b"network\0");
+
+        wifi.connect().unwrap();
+        assert!(mock::wifi::state().connected);
+
+        let hardware = wifi.downgrade();
+        let state = mock::wifi::state();
+        assert!(!state.started && !state.connected);
+
+        hardware.deinitialize();
+        assert!(!mock::wifi::state().initialized);
+    }
+
+    #[test]
+    fn combined_mode_configures_both_interfaces() {
+        let mut wifi = WiFiHardware::new(WiFiPeripherals {}).initialize().ok().unwrap();
+        wifi.set_sta_config(sta_config()).set_ap_config(ap_config()).start().unwrap();
+
+        let state = mock::wifi::state();
+        assert_eq!(state.mode, wifi_mode_t_WIFI_MODE_APSTA);
+        assert_eq!(state.ap_config.unwrap().channel, 6);
+        assert!(state.sta_config.is_some());
+
+        wifi.switch_sta_to_bgn_mode().unwrap();
+        let protocols = (WIFI_PROTOCOL_11B | WIFI_PROTOCOL_11G | WIFI_PROTOCOL_11N) as u8;
+        assert_eq!(mock::wifi::state().protocols[0], protocols);
+    }
+
+    #[test]
+    fn start_requires_configuration() {
+        let mut wifi = WiFiHardware::new(WiFiPeripherals {}).initialize().ok().unwrap();
+        assert!(matches!(wifi.start(), Err(WiFiConfigurationError::ConfigurationNotSet)));
+        assert!(mock::calls_of("esp_wifi_set_mode").is_empty());
+
+        // Station can't connect until started
+        assert!(matches!(
+            wifi.connect(),
+            Err(WiFiConfigurationError::IdfError(esp_err_t_ESP_ERR_WIFI_NOT_STARTED))
+        ));
+    }
+
+    #[test]
+    fn start_errors_are_mapped() {
+        let mut wifi = WiFiHardware::new(WiFiPeripherals {}).initialize().ok().unwrap();
+        wifi.set_sta_config(sta_config());
+
+        mock::inject_error("esp_wifi_set_config", esp_err_t_ESP_ERR_WIFI_PASSWORD);
+        assert!(matches!(wifi.start(), Err(WiFiConfigurationError::InvalidWifiPassword)));
+
+        mock::inject_error("esp_wifi_start", esp_err_t_ESP_ERR_NO_MEM);
+        assert!(matches!(wifi.start(), Err(WiFiConfigurationError::NoMemory)));
+        assert!(!mock::wifi::state().started);
+
+        // Stop of the not started wifi does nothing
+        wifi.stop();
+        assert!(mock::calls_of("esp_wifi_stop").is_empty());
+    }
+
+    #[test]
+    fn failed_initialization_returns_hardware() {
+        mock::inject_error("esp_wifi_init", esp_err_t_ESP_ERR_NO_MEM);
+        let result = WiFiHardware::new(WiFiPeripherals {}).initialize();
+        let hardware = match result {
+            Err((WiFiInitializationError::IdfError(esp_err_t_ESP_ERR_NO_MEM), hardware)) => hardware,
+            _ => panic!("Initialization should fail"),
+        };
+
+        // Not initialized hardware isn't deinitialized
+        hardware.deinitialize();
+        assert!(mock::calls_of("esp_wifi_deinit").is_empty());
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected() {
+        let result = WiFiApConfigurationBuilder::new().ssid("ap").auth_mode(WiFiAuthMode::Wpa2Psk).build();
+        assert!(matches!(result, Err(WiFiApConfigurationBuildError::PasswordNotSet)));
+        let result = WiFiApConfigurationBuilder::new().ssid("ap").auth_mode(WiFiAuthMode::Wep).build();
+        assert!(matches!(result, Err(WiFiApConfigurationBuildError::AuthModeNotSupported)));
+        let result = WiFiApConfigurationBuilder::new().ssid("ap").channel(15).build();
+        assert!(matches!(result, Err(WiFiApConfigurationBuildError::InvalidWiFiChannel)));
+
+        let result = WiFiStaConfigurationBuilder::new().password("password").build();
+        assert!(matches!(result, Err(WiFiStaConfigurationBuildError::SsidNotSet)));
+        let result = WiFiStaConfigurationBuilder::new().ssid("network").listen_interval(0).build();
+        assert!(matches!(result, Err(WiFiStaConfigurationBuildError::InvalidListenInterval)));
+    }
+}